@@ -1,4 +1,4 @@
 export interface SubscribeRequest {
     path: String,
-    game_id: number,
+    gameId: number,
 }
\ No newline at end of file
@@ -84,7 +84,7 @@ export class WebsocketService {
 
   doSubscribe(gameId: number) {
     console.log(`trying to subscribe game ${gameId}`);
-    let request: SubscribeRequest = { path: "/subscribe", game_id: gameId };
+    let request: SubscribeRequest = { path: "/subscribe", gameId: gameId };
     this.subject!.send(JSON.stringify(request));
   }
 
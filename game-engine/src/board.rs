@@ -219,7 +219,7 @@ pub fn have_captures(old: &BitBoard, new: &BitBoard, color: &Color) -> bool {
         Color::White => new.red_pawns | new.red_kings,
         Color::Red => new.white_pawns | new.white_kings,
     }.count_ones();
-    enemy_old < enemy_new
+    enemy_old > enemy_new
 }
 
 pub fn have_promotions(old: &BitBoard, new: &BitBoard, color: &Color) -> bool {
@@ -299,4 +299,12 @@ mod tests {
         assert_eq!(bit.mov, 0b10000000000000000000000000000001);
         assert_eq!(bit.start_end, 0b10000000000000000000000000000001);
     }
+
+    #[test]
+    fn test_captures_are_detected() {
+        let old = generate_bit_board("xxxxxxxxxx.x..x...o.ooo.oooooooo".to_string()).unwrap();
+        let new = generate_bit_board("xxxxxxxxxx.x.......xooo.oooooooo".to_string()).unwrap();
+        assert!(have_captures(&old, &new, &Color::White));
+        assert!(!have_captures(&old, &old, &Color::White));
+    }
 }
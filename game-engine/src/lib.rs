@@ -0,0 +1,11 @@
+mod board;
+mod ai;
+mod rules;
+pub mod rabbit;
+pub mod config;
+pub mod replay;
+
+use protocol::Color;
+use regex::Regex;
+
+const BIT_MASK: u32 = 0b1000_0000_0000_0000_0000_0000_0000_0000;
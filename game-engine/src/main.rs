@@ -1,12 +1,4 @@
-mod board;
-mod ai;
-mod rules;
-mod rabbit;
-mod config;
-
-use crate::rabbit::lapin_listen;
-use regex::Regex;
-use serde::{Deserialize, Serialize};
+use game_engine::{config, rabbit::lapin_listen};
 
 #[tokio::main]
 async fn main() {
@@ -16,11 +8,3 @@ async fn main() {
     let lapin_pool = cfg.create_pool(Some(deadpool_lapin::Runtime::Tokio1)).unwrap();
     lapin_listen(lapin_pool.clone()).await;
 }
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum Color {
-    White,
-    Red,
-}
-
-const BIT_MASK: u32 = 0b1000_0000_0000_0000_0000_0000_0000_0000;
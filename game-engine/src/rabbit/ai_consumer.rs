@@ -1,89 +1,34 @@
-use lapin::{message::DeliveryResult, options::BasicAckOptions, Channel};
+use std::sync::Arc;
 
-use serde::{Deserialize, Serialize};
+use protocol::{broker::{handler, Broker, BrokerError}, engine::{AIMoveEvent, EngineEvent}, envelope::encode, queues::AI_MOVES_QUEUE, AIType, RuleSet};
 
-use crate::{ai::get_engine, board::{generate_bit_board, have_captures, have_promotions}, rabbit::DESTINATION_EXCHANGE, rules::get_rules, Color};
+use crate::{ai::get_engine, board::{generate_bit_board, have_captures, have_promotions}, rabbit::{DESTINATION_EXCHANGE, PRODUCER}, rules::get_rules};
 
-use super::move_consumer::EngineEvent;
+pub async fn set_ai_delegate(broker: Arc<dyn Broker>) -> Result<(), BrokerError> {
+    let publisher = broker.clone();
+    broker.consume(AI_MOVES_QUEUE, "engine_ai_consumer", handler(move |delivery| {
+        println!("New ai move request");
+        let broker = publisher.clone();
+        async move {
+            let envelope = delivery.parse::<AIMoveEvent>()?;
+            let message = envelope.payload;
+            println!("Received message: {:?}", &message);
 
-pub fn set_ai_delegate(consumer: lapin::Consumer, channel: Channel) {
-    consumer.set_delegate({
-        move |delivery: DeliveryResult| {
-            println!("New ai move request");
-            let channel = channel.clone();
-            async move {
-                let channel = channel.clone();
-                let delivery = match delivery {
-                    Ok(Some(delivery)) => delivery,
-                    Ok(None) => return,
-                    Err(error) => {
-                        println!("Failed to consume queue message {}", error);
-                        return;
-                    }
-                };
 
-                let message = std::str::from_utf8(&delivery.data).unwrap();
-                let message: AiEvent = match serde_json::from_str(message) {
-                    Ok(msg) => msg,
-                    Err(err) => {
-                        println!("Failed to deserialize ai event: {:?}", err);
-                        return; // TODO
-                    }
-                };
-                println!("Received message: {:?}", &message);
+            let response = process_ai_event(message)?;
+            println!("Response: {:?}", &response);
+            let response = encode(response, PRODUCER, envelope.correlation_id);
 
-
-                let response = process_ai_event(message);
-                println!("Response: {:?}", &response);
-                let response = serde_json::to_string(&response).unwrap();
-
-                if let Err(err) = channel
-                    .basic_publish(
-                        DESTINATION_EXCHANGE,
-                        "engine",
-                        Default::default(),
-                        response.into_bytes().as_slice(),
-                        Default::default(),
-                        )
-                        .await {
-                            println!("Failed to publish message to destination exchange: {:?}", err);
-                        };
-
-                delivery
-                    .ack(BasicAckOptions::default())
-                    .await
-                    .expect("Failed to acknowledge message");
-            }
+            if let Err(err) = broker.publish(DESTINATION_EXCHANGE, "engine", response).await {
+                println!("Failed to publish message to destination exchange: {:?}", err);
+            };
+            Ok(())
         }
-    }
-    );
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct AiEvent {
-    game_id: usize,
-    game_state: String,
-    ruleset: RuleSet,
-    ai_type: AIType,
-    color: Color,
-    noncapture_moves: usize,
-    nonpromoting_moves: usize,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-pub enum RuleSet {
-    British,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-enum AIType {
-    Random,
-    Counting,
+    })).await
 }
 
 // TODO
-fn process_ai_event(message: AiEvent) -> EngineEvent {
+fn process_ai_event(message: AIMoveEvent) -> Result<EngineEvent, String> {
     let old_board = generate_bit_board(message.game_state).unwrap(); // TODO
     let rules = get_rules(match message.ruleset {
         RuleSet::British => crate::rules::RuleSet::British,
@@ -91,6 +36,7 @@ fn process_ai_event(message: AiEvent) -> EngineEvent {
     let mut engine = get_engine(match message.ai_type {
         AIType::Random => crate::ai::EngineType::Random,
         AIType::Counting => crate::ai::EngineType::Counting,
+        AIType::None => return Err("No AI type given for AI move".into()),
     });
     let mov = engine.get_move(&old_board, &message.color, &rules);
     println!("move: {:032b}", mov);
@@ -118,8 +64,9 @@ fn process_ai_event(message: AiEvent) -> EngineEvent {
     let drawn = !won && rules.is_game_drawn(noncaptures, nonpromotions);
     let finished = won || drawn;
 
-    EngineEvent {
+    Ok(EngineEvent {
         game_id: message.game_id,
+        correlation_id: message.correlation_id,
         new_state: board.to_string(),
         ai: true,
         legal: true,
@@ -127,5 +74,5 @@ fn process_ai_event(message: AiEvent) -> EngineEvent {
         finished,
         mov: move_string,
         ..Default::default()
-    }
+    })
 }
@@ -1,12 +1,14 @@
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
-use lapin::{options::{BasicConsumeOptions, ExchangeDeclareOptions, QueueBindOptions, QueueDeclareOptions}, types::FieldTable, ExchangeKind};
+use protocol::{broker::{Broker, BrokerError, LapinBroker}, queues::{AI_MOVES_QUEUE, MOVES_QUEUE}};
 
 use self::{ai_consumer::set_ai_delegate, move_consumer::set_move_delegate};
 
 mod move_consumer;
 mod ai_consumer;
 
+pub(crate) use self::move_consumer::get_reason;
+
 pub async fn lapin_listen(pool: deadpool_lapin::Pool) {
     let mut retry_interval = tokio::time::interval(Duration::from_secs(5));
     loop {
@@ -20,11 +22,12 @@ pub async fn lapin_listen(pool: deadpool_lapin::Pool) {
 }
 
 const EXCHANGE_NAME: &str = "checkers.moves.topic";
-const MOVES_QUEUE: &str = "checkers.moves.queue"; // move
-const AI_QUEUE: &str = "checkers.moves.ai.queue"; // move_ai
 
 pub const DESTINATION_EXCHANGE: &str = "checkers.engine.topic";
 
+// producer named in envelopes of published messages
+pub const PRODUCER: &str = "game-engine";
+
 async fn init_lapin_listen(pool: deadpool_lapin::Pool) -> Result<(), Box<dyn std::error::Error>> {
     let rmq_con = pool.get().await
         .map_err(|e| {
@@ -32,80 +35,13 @@ async fn init_lapin_listen(pool: deadpool_lapin::Pool) -> Result<(), Box<dyn std
         e
     })?;
     let channel = rmq_con.create_channel().await?;
+    let broker: Arc<dyn Broker> = Arc::new(LapinBroker::new(channel).await?);
 
-    channel.queue_declare(
-        MOVES_QUEUE,
-        QueueDeclareOptions::default(),
-        Default::default(),
-        )
-        .await
-        .expect("Cannot declare queue");
-
-    channel
-        .queue_bind(
-            MOVES_QUEUE,
-            EXCHANGE_NAME,
-            "move",
-            QueueBindOptions::default(),
-            FieldTable::default(),
-            )
-        .await
-        .expect("Cannot bind queue");
-
-    channel.queue_declare(
-        AI_QUEUE,
-        QueueDeclareOptions::default(),
-        Default::default(),
-        )
-        .await
-        .expect("Cannot declare queue");
-
-    channel
-        .queue_bind(
-            AI_QUEUE,
-            EXCHANGE_NAME,
-            "move_ai",
-            QueueBindOptions::default(),
-            FieldTable::default(),
-            )
-        .await
-        .expect("Cannot bind queue");
-
-    channel
-        .exchange_declare(
-            DESTINATION_EXCHANGE,
-            ExchangeKind::Topic,
-            ExchangeDeclareOptions {
-                durable: true,
-                ..Default::default()
-            },
-            FieldTable::default(),
-            )
-        .await
-        .expect("Cannot declare exchange");
-
-    let move_consumer = channel.basic_consume(
-        MOVES_QUEUE,
-        "engine_move_consumer",
-        BasicConsumeOptions::default(),
-        FieldTable::default())
-        .await
-        .expect("Cannot create consumer");
-
-    let ai_consumer = channel.basic_consume(
-        AI_QUEUE,
-        "engine_ai_consumer",
-        BasicConsumeOptions::default(),
-        FieldTable::default())
-        .await
-        .expect("Cannot create consumer");
-    
-    set_move_delegate(move_consumer, channel.clone());
-    set_ai_delegate(ai_consumer, channel.clone());
+    listen(broker.clone()).await?;
     let mut test_interval = tokio::time::interval(Duration::from_secs(5));
     loop {
         test_interval.tick().await;
-        match channel.status().connected() {
+        match broker.is_connected() {
             false => break,
             true => {},
         }
@@ -113,3 +49,14 @@ async fn init_lapin_listen(pool: deadpool_lapin::Pool) -> Result<(), Box<dyn std
 
     Ok(())
 }
+
+// moves exchange is declared by the game service
+pub async fn listen(broker: Arc<dyn Broker>) -> Result<(), BrokerError> {
+    broker.declare_queue(MOVES_QUEUE, EXCHANGE_NAME, "move").await?;
+    broker.declare_queue(AI_MOVES_QUEUE, EXCHANGE_NAME, "move_ai").await?;
+    broker.declare_exchange(DESTINATION_EXCHANGE).await?;
+
+    set_move_delegate(broker.clone()).await?;
+    set_ai_delegate(broker.clone()).await?;
+    Ok(())
+}
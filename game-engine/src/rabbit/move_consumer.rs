@@ -1,106 +1,46 @@
-use lapin::{message::DeliveryResult, options::BasicAckOptions, Channel};
-use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
-use crate::{board::{generate_bit_board, have_captures, have_promotions, move_to_bitboard}, rabbit::DESTINATION_EXCHANGE, rules::{get_rules, MoveVerification}, Color};
+use protocol::{broker::{handler, Broker, BrokerError}, engine::{EngineEvent, MoveEvent, RejectionReason}, envelope::encode, queues::MOVES_QUEUE, RuleSet};
 
-pub fn set_move_delegate(consumer: lapin::Consumer, channel: Channel) {
-    consumer.set_delegate({
-        move |delivery: DeliveryResult| {
-            println!("New move verification request");
-            let channel = channel.clone();
-            async move {
-                let channel = channel.clone();
-                let delivery = match delivery {
-                    Ok(Some(delivery)) => delivery,
-                    Ok(None) => return,
-                    Err(error) => {
-                        println!("Failed to consume queue message {}", error);
-                        return;
-                    }
-                };
+use crate::{board::{generate_bit_board, have_captures, have_promotions, move_to_bitboard}, rabbit::{DESTINATION_EXCHANGE, PRODUCER}, rules::{get_rules, IllegalMove, MoveVerification}};
 
-                let message = std::str::from_utf8(&delivery.data).unwrap();
-                let message: MoveEvent = match serde_json::from_str(message) {
-                    Ok(msg) => msg,
-                    Err(err) => {
-                        println!("Failed to deserialize game event: {:?}", err);
-                        return; // TODO
-                    }
-                };
-                println!("Received message: {:?}", &message);
+pub async fn set_move_delegate(broker: Arc<dyn Broker>) -> Result<(), BrokerError> {
+    let publisher = broker.clone();
+    broker.consume(MOVES_QUEUE, "engine_move_consumer", handler(move |delivery| {
+        println!("New move verification request");
+        let broker = publisher.clone();
+        async move {
+            let envelope = delivery.parse::<MoveEvent>()?;
+            let message = envelope.payload;
+            println!("Received message: {:?}", &message);
 
 
-                let response = process_move(message);
-                println!("Response: {:?}", &response);
-                let response = serde_json::to_string(&response).unwrap();
+            let response = process_move(message);
+            println!("Response: {:?}", &response);
+            let response = encode(response, PRODUCER, envelope.correlation_id);
 
-                if let Err(err) = channel
-                    .basic_publish(
-                        DESTINATION_EXCHANGE,
-                        "engine",
-                        Default::default(),
-                        response.into_bytes().as_slice(),
-                        Default::default(),
-                        )
-                        .await {
-                            println!("Failed to publish message to destination exchange: {:?}", err);
-                        };
-
-                delivery
-                    .ack(BasicAckOptions::default())
-                    .await
-                    .expect("Failed to acknowledge message");
-            }
+            if let Err(err) = broker.publish(DESTINATION_EXCHANGE, "engine", response).await {
+                println!("Failed to publish message to destination exchange: {:?}", err);
+            };
+            Ok(())
         }
-    }
-    );
+    })).await
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct MoveEvent {
-    game_id: usize,
-    game_state: String,
-    #[serde(rename = "move")]
-    mov: String,
-    ruleset: RuleSet,
-    color: Color,
-    noncapture_moves: usize,
-    nonpromoting_moves: usize,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct EngineEvent {
-    pub game_id: usize,
-    pub legal: bool,
-    pub new_state: String,
-    #[serde(rename = "move")]
-    pub mov: String,
-    pub ai: bool,
-    pub finished: bool,
-    pub lost: bool,
-    pub won: bool,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-pub enum RuleSet {
-    British,
-}
-
-impl Default for EngineEvent {
-    fn default() -> EngineEvent {
-        EngineEvent { 
-            game_id: 0,
-            legal: true,
-            new_state: "".into(),
-            mov: "".into(),
-            ai: false,
-            finished: false,
-            lost: false,
-            won: false,
-        } 
-    } 
+pub(crate) fn get_reason(verification: &MoveVerification) -> Option<RejectionReason> {
+    match verification {
+        MoveVerification::Ok(_) => None,
+        MoveVerification::Ambiguous(candidates) => Some(RejectionReason::Ambiguous { candidates: candidates.clone() }),
+        MoveVerification::Illegal(reason) => Some(match reason {
+            IllegalMove::NoPiece => RejectionReason::NoPiece,
+            IllegalMove::DestinationOccupied => RejectionReason::DestinationOccupied,
+            IllegalMove::CaptureAvailable => RejectionReason::CaptureAvailable,
+            IllegalMove::BackwardMove => RejectionReason::BackwardMove,
+            IllegalMove::IncompleteJump => RejectionReason::IncompleteJump,
+            IllegalMove::Unreachable => RejectionReason::Unreachable,
+            IllegalMove::InvalidNotation => RejectionReason::InvalidNotation,
+        }),
+    }
 }
 
 // TODO
@@ -113,8 +53,9 @@ fn process_move(message: MoveEvent) -> EngineEvent {
 
     let legality = match (mov, &board) {
         (Ok(mov), Ok(board)) => rules.verify_move(board, mov, &message.color),
-        (_, _) => MoveVerification::Illegal, 
+        (_, _) => MoveVerification::Illegal(IllegalMove::InvalidNotation), 
     };
+    let reason = get_reason(&legality);
     let legal = match legality {
         MoveVerification::Ok(_) => true,
         _ => false,
@@ -153,11 +94,13 @@ fn process_move(message: MoveEvent) -> EngineEvent {
 
     EngineEvent {
         game_id: message.game_id,
+        correlation_id: message.correlation_id,
         new_state: state,
         mov: message.mov,
         legal,
         won,
         finished,
+        reason,
         ..Default::default()
     }
 }
@@ -0,0 +1,111 @@
+// Replays stored games through the rules, so that saved states can be audited.
+
+use protocol::{engine::RejectionReason, Color, RuleSet};
+
+use crate::{board::{generate_bit_board, have_captures, have_promotions, move_to_bitboard}, rabbit::get_reason, rules::{get_rules, IllegalMove, MoveVerification}};
+
+// white starts the game
+pub const INITIAL_STATE: &str = "xxxxxxxxxxxx........oooooooooooo";
+
+// position after a move, counters are kept the way the game service keeps them
+#[derive(Debug, PartialEq, Clone)]
+pub struct Position {
+    pub current_state: String,
+    pub mover: Color,
+    pub noncapture_moves: usize,
+    pub nonpromoting_moves: usize,
+    pub won: bool,
+    pub drawn: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ReplayError {
+    pub ply: usize,
+    pub notation: String,
+    pub reason: Option<RejectionReason>,
+}
+
+pub fn replay(ruleset: RuleSet, moves: &[String]) -> Result<Vec<Position>, ReplayError> {
+    let rules = get_rules(match ruleset {
+        RuleSet::British => crate::rules::RuleSet::British,
+    });
+    let mut positions: Vec<Position> = Vec::new();
+    let mut board = generate_bit_board(INITIAL_STATE.into()).unwrap();
+    let mut color = Color::White;
+    let (mut noncaptures, mut nonpromotions) = (0, 0);
+
+    for (i, notation) in moves.iter().enumerate() {
+        let error = |reason| ReplayError { ply: i + 1, notation: notation.clone(), reason };
+        if positions.last().is_some_and(|position| position.won || position.drawn) {
+            return Err(error(None));
+        }
+        let verification = match move_to_bitboard(notation.clone()) {
+            Ok(mov) => rules.verify_move(&board, mov, &color),
+            Err(_) => MoveVerification::Illegal(IllegalMove::InvalidNotation),
+        };
+        let MoveVerification::Ok(mov) = verification else {
+            return Err(error(get_reason(&verification)));
+        };
+
+        let new_board = board.apply_move(mov, &color);
+        let captured = have_captures(&board, &new_board, &color);
+        let promoted = have_promotions(&board, &new_board, &color);
+        // engine judges draws on the counters from before the move
+        let won = rules.is_game_won(&new_board, &color);
+        let drawn = !won && rules.is_game_drawn(
+            if captured { 0 } else { noncaptures },
+            if promoted { 0 } else { nonpromotions },
+        );
+        noncaptures = if captured { 0 } else { noncaptures + 1 };
+        nonpromotions = if promoted { 0 } else { nonpromotions + 1 };
+
+        positions.push(Position {
+            current_state: new_board.to_string(),
+            mover: color.clone(),
+            noncapture_moves: noncaptures,
+            nonpromoting_moves: nonpromotions,
+            won,
+            drawn,
+        });
+        board = new_board;
+        color = match color {
+            Color::White => Color::Red,
+            Color::Red => Color::White,
+        };
+    }
+    Ok(positions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moves(notation: &[&str]) -> Vec<String> {
+        notation.iter().map(|mov| mov.to_string()).collect()
+    }
+
+    #[test]
+    fn test_replay_opening() {
+        let positions = replay(RuleSet::British, &moves(&["11-15", "22-18", "15x22"])).unwrap();
+        assert_eq!(positions.len(), 3);
+        assert_eq!(positions[0].mover, Color::White);
+        assert_eq!(positions[1].mover, Color::Red);
+        assert_eq!(positions[0].current_state, "xxxxxxxxxx.x..x.....oooooooooooo");
+        assert_eq!(positions[1].noncapture_moves, 2);
+        assert_eq!(positions[2].noncapture_moves, 0);
+        assert_eq!(positions[2].nonpromoting_moves, 3);
+        assert!(!positions[2].won);
+    }
+
+    #[test]
+    fn test_replay_illegal_move() {
+        let result = replay(RuleSet::British, &moves(&["11-15", "11-16"]));
+        assert_eq!(result.err().map(|err| err.ply), Some(2));
+    }
+
+    #[test]
+    fn test_replay_invalid_notation() {
+        let result = replay(RuleSet::British, &moves(&["eleven"]));
+        assert_eq!(result.err().and_then(|err| err.reason), Some(RejectionReason::InvalidNotation));
+    }
+}
@@ -2,7 +2,7 @@ use std::collections::VecDeque;
 
 use crate::{board::{BitBoard, MoveBit}, rules::Rules, Color};
 
-use super::{MoveVerification, RuleDefiniton};
+use super::{IllegalMove, MoveVerification, RuleDefiniton};
 
 // no flying kings, 8x8, pawns cannot move backwards, 
 // any capture sequence can be chosen, but captures are forced, 
@@ -69,37 +69,47 @@ impl Rules for BritishRules {
             Color::White => mov.start_end & (board.white_pawns | board.white_kings),
             Color::Red => mov.start_end & (board.red_pawns | board.red_kings),
         };
-        if start.count_ones() != 1 {
-            println!("No start, move illegal");
-            return MoveVerification::Illegal
+        match start.count_ones() {
+            0 => return MoveVerification::Illegal(IllegalMove::NoPiece),
+            1 => {},
+            _ => return MoveVerification::Illegal(IllegalMove::DestinationOccupied),
         }
+        let end = mov.start_end ^ start;
         let jumps = self.get_jumps_with_positions(board, start, color);
-        let matched_jumps: Vec<&u32> = jumps.iter()
+        let matched_jumps: Vec<&MoveCandidate> = jumps.iter()
             .filter(|&j| {
                 j.start_end == mov.start_end
                     &&
                 j.intermediate_positions & mov.mov == mov.mov
             })
-            .map(|j| &j.mov)
             .collect();
         if matched_jumps.len() == 1 {
-            println!("Single move. Legal.");
-            return MoveVerification::Ok(*matched_jumps[0])
+            return MoveVerification::Ok(matched_jumps[0].mov)
         } else if matched_jumps.len() > 1 {
-            println!("Ambiguous move. ILlegal.");
-            return MoveVerification::Ambiguous
+            let candidates = matched_jumps.iter()
+                .map(|j| self.candidate_to_string(j, start))
+                .collect();
+            return MoveVerification::Ambiguous(candidates)
+        }
+        let stopped_midway = jumps.iter()
+            .any(|j| (j.intermediate_positions & !j.start_end) & end != 0);
+        if stopped_midway {
+            return MoveVerification::Illegal(IllegalMove::IncompleteJump)
         }
         let any_jumpers = self.get_possible_jumpers(board, color) != 0;
         if any_jumpers {
-            println!("No jump matched, but there are possible jumpers.");
-            return MoveVerification::Illegal
+            return MoveVerification::Illegal(IllegalMove::CaptureAvailable)
         }
         let moves = self.get_moves(board, start, color);
         let matched_moves: Vec<&u32> = moves.iter().filter(|&j| *j == mov.start_end).collect();
         match matched_moves.len() {
             1 => MoveVerification::Ok(*matched_moves[0]),
-            0 => MoveVerification::Illegal,
-            _ => MoveVerification::Ambiguous,
+            0 => MoveVerification::Illegal(self.get_illegal_move_reason(board, start, end, color)),
+            _ => MoveVerification::Ambiguous(
+                matched_moves.iter()
+                    .map(|&m| self.move_to_string(board, *m, color))
+                    .collect()
+                ),
         }
     }
 
@@ -734,6 +744,62 @@ impl BritishRules {
         result
     }
 
+    fn get_illegal_move_reason(&self, board: &BitBoard, start: u32, end: u32, color: &Color) -> IllegalMove {
+        let occupied = board.white_pawns | board.red_pawns | board.red_kings | board.white_kings;
+        if end & occupied != 0 {
+            return IllegalMove::DestinationOccupied
+        }
+        let pawn = match color {
+            Color::White => board.white_pawns & start,
+            Color::Red => board.red_pawns & start,
+        };
+        if end != 0 && pawn != 0 {
+            // the same step would be legal for a king, so the pawn tried to move backwards
+            let promoted = match color {
+                Color::White => BitBoard {
+                    white_pawns: board.white_pawns ^ start,
+                    white_kings: board.white_kings | start,
+                    red_pawns: board.red_pawns,
+                    red_kings: board.red_kings,
+                },
+                Color::Red => BitBoard {
+                    white_pawns: board.white_pawns,
+                    white_kings: board.white_kings,
+                    red_pawns: board.red_pawns ^ start,
+                    red_kings: board.red_kings | start,
+                },
+            };
+            if self.get_moves(&promoted, start, color).contains(&(start | end)) {
+                return IllegalMove::BackwardMove
+            }
+        }
+        IllegalMove::Unreachable
+    }
+
+    // full notation of a jump, with every landing square in order
+    fn candidate_to_string(&self, candidate: &MoveCandidate, start: u32) -> String {
+        let end = candidate.start_end ^ start;
+        let mut remaining = candidate.intermediate_positions & !start;
+        let mut current = start;
+        let mut squares = vec![32-start.trailing_zeros()];
+        while remaining != 0 {
+            let hops = [current >> 7, current >> 9, current << 7, current << 9];
+            let next = hops.iter()
+                .find(|&&h| h & remaining != 0 && (h != end || remaining == end))
+                .or_else(|| hops.iter().find(|&&h| h & remaining != 0));
+            let Some(&next) = next else {
+                break;
+            };
+            squares.push(32-next.trailing_zeros());
+            remaining ^= next;
+            current = next;
+        }
+        squares.iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<String>>()
+            .join("x")
+    }
+
     // mover should have only one bit set
     fn get_jumps_with_positions(&self, board: &BitBoard, mover: u32, color: &Color) -> Vec<MoveCandidate> {
         match color {
@@ -1058,7 +1124,7 @@ mod tests {
         let rules = BritishRules::new();
         let result = rules.verify_move(&board, mov, &Color::White);
         
-        assert_eq!(result, MoveVerification::Illegal);
+        assert_eq!(result, MoveVerification::Illegal(IllegalMove::BackwardMove));
     }
 
     #[test]
@@ -1078,7 +1144,7 @@ mod tests {
         let rules = BritishRules::new();
         let result = rules.verify_move(&board, mov, &Color::White);
         
-        assert_eq!(result, MoveVerification::Illegal);
+        assert_eq!(result, MoveVerification::Illegal(IllegalMove::Unreachable));
     }
 
     #[test]
@@ -1098,7 +1164,7 @@ mod tests {
         let rules = BritishRules::new();
         let result = rules.verify_move(&board, mov, &Color::White);
         
-        assert_eq!(result, MoveVerification::Ambiguous);
+        assert_eq!(result, MoveVerification::Ambiguous(vec!["6x13x22".into(), "6x15x22".into()]));
     }
 
     #[test]
@@ -1140,4 +1206,84 @@ mod tests {
         
         assert_eq!(result, MoveVerification::Ok(0b0000_0100_1000_0000_1000_0100_0000_0000));
     }
+
+    #[test]
+    fn test_illegal_move_without_piece() {
+        let board = BitBoard {
+            white_pawns: 0b0000_0100_0000_0000_0000_0000_0000_0000,
+            red_pawns:   0b0000_0000_0000_0000_0000_0000_0000_0000,  
+            white_kings: 0b0000_0000_0000_0000_0000_0000_0000_0000,
+            red_kings:   0b0000_0000_0000_0000_0000_0000_0000_0000,  
+        };
+
+        let mov = MoveBit {
+            start_end:   0b1000_1000_0000_0000_0000_0000_0000_0000,
+            mov:         0b1000_1000_0000_0000_0000_0000_0000_0000,
+        };
+        
+        let rules = BritishRules::new();
+        let result = rules.verify_move(&board, mov, &Color::White);
+        
+        assert_eq!(result, MoveVerification::Illegal(IllegalMove::NoPiece));
+    }
+
+    #[test]
+    fn test_illegal_move_to_occupied_field() {
+        let board = BitBoard {
+            white_pawns: 0b0000_0100_0000_0000_0000_0000_0000_0000,
+            red_pawns:   0b0000_0000_0100_0010_0000_0000_0000_0000,  
+            white_kings: 0b0000_0000_0000_0000_0000_0000_0000_0000,
+            red_kings:   0b0000_0000_0000_0000_0000_0000_0000_0000,  
+        };
+
+        let mov = MoveBit {
+            start_end:   0b0000_0100_0100_0000_0000_0000_0000_0000,
+            mov:         0b0000_0100_0100_0000_0000_0000_0000_0000,
+        };
+        
+        let rules = BritishRules::new();
+        let result = rules.verify_move(&board, mov, &Color::White);
+        
+        assert_eq!(result, MoveVerification::Illegal(IllegalMove::DestinationOccupied));
+    }
+
+    #[test]
+    fn test_illegal_move_with_capture_available() {
+        let board = BitBoard {
+            white_pawns: 0b0000_0101_0000_0000_0000_0000_0000_0000,
+            red_pawns:   0b0000_0000_1100_0000_1100_0000_0000_0000,  
+            white_kings: 0b0000_0000_0000_0000_0000_0000_0000_0000,
+            red_kings:   0b0000_0000_0000_0000_0000_0000_0000_0000,  
+        };
+
+        let mov = MoveBit {
+            start_end:   0b0000_0001_0001_0000_0000_0000_0000_0000,
+            mov:         0b0000_0001_0001_0000_0000_0000_0000_0000,
+        };
+        
+        let rules = BritishRules::new();
+        let result = rules.verify_move(&board, mov, &Color::White);
+        
+        assert_eq!(result, MoveVerification::Illegal(IllegalMove::CaptureAvailable));
+    }
+
+    #[test]
+    fn test_illegal_incomplete_jump() {
+        let board = BitBoard {
+            white_pawns: 0b0000_0100_0000_0000_0000_0000_0000_0000,
+            red_pawns:   0b0000_0000_1100_0000_1100_0000_0000_0000,  
+            white_kings: 0b0000_0000_0000_0000_0000_0000_0000_0000,
+            red_kings:   0b0000_0000_0000_0000_0000_0000_0000_0000,  
+        };
+
+        let mov = MoveBit {
+            start_end:   0b0000_0100_0000_1000_0000_0000_0000_0000,
+            mov:         0b0000_0100_0000_1000_0000_0000_0000_0000,
+        };
+        
+        let rules = BritishRules::new();
+        let result = rules.verify_move(&board, mov, &Color::White);
+        
+        assert_eq!(result, MoveVerification::Illegal(IllegalMove::IncompleteJump));
+    }
 }
@@ -21,8 +21,19 @@ pub enum RuleSet {
 #[derive(Debug, PartialEq)]
 pub enum MoveVerification {
     Ok(u32),
-    Illegal,
-    Ambiguous,
+    Illegal(IllegalMove),
+    Ambiguous(Vec<String>),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum IllegalMove {
+    NoPiece,
+    DestinationOccupied,
+    CaptureAvailable,
+    BackwardMove,
+    IncompleteJump,
+    Unreachable,
+    InvalidNotation,
 }
 
 #[derive(Debug)]
@@ -0,0 +1,159 @@
+// Inspects and replays messages that services have moved to dead-letter queues.
+//
+// usage: deadletters [url] list [queue]
+//        deadletters [url] replay [queue] [count]
+//        deadletters [url] migrate
+//   e.g. deadletters amqp://localhost:5672 list
+//        deadletters amqp://localhost:5672 replay checkers.engine.queue 10
+//
+// Queues are given by the name of the queue the messages were rejected from,
+// list shows all of them if none is given. Replayed messages are published to
+// that queue again without the rejection headers.
+//
+// Queues declared by versions without dead-lettering lack its arguments, and
+// RabbitMQ refuses to declare them again with different ones. migrate deletes
+// the empty ones, services declare them again when they connect, see README.
+
+use std::env;
+
+use lapin::{message::Delivery, options::{BasicAckOptions, BasicGetOptions, BasicPublishOptions, QueueDeleteOptions}, types::{AMQPValue, FieldTable}, BasicProperties, Channel, Connection, ConnectionProperties};
+use protocol::{broker::{dead_letter_queue, QUEUE_HEADER, REASON_HEADER}, queues::QUEUES};
+
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = env::args().collect();
+    let url = args.get(1).cloned().unwrap_or("amqp://localhost:5672".into());
+    let command = args.get(2).cloned().unwrap_or("list".into());
+    let queue = args.get(3).cloned();
+    let count: usize = args.get(4).and_then(|n| n.parse().ok()).unwrap_or(usize::MAX);
+
+    let connection = match Connection::connect(url.as_str(), ConnectionProperties::default()).await {
+        Ok(connection) => connection,
+        Err(err) => {
+            eprintln!("Failed to connect to {}: {}", url, err);
+            return;
+        },
+    };
+
+    match (command.as_str(), queue) {
+        ("list", Some(queue)) => list(&connection, queue.as_str()).await,
+        ("list", None) => {
+            for queue in QUEUES {
+                list(&connection, queue).await;
+            }
+        },
+        ("replay", Some(queue)) => replay(&connection, queue.as_str(), count).await,
+        ("migrate", None) => {
+            for queue in QUEUES {
+                migrate(&connection, queue).await;
+            }
+        },
+        _ => eprintln!("usage: deadletters [url] list [queue] | deadletters [url] replay [queue] [count] | deadletters [url] migrate"),
+    }
+    let _ = connection.close(0, "").await;
+}
+
+// messages are fetched without acknowledging them, closing the channel puts them back
+async fn list(connection: &Connection, queue: &str) {
+    let Ok(channel) = connection.create_channel().await else {
+        eprintln!("Failed to open channel");
+        return;
+    };
+    let dead_queue = dead_letter_queue(queue);
+    let mut listed = 0;
+    loop {
+        let message = match channel.basic_get(dead_queue.as_str(), BasicGetOptions::default()).await {
+            Ok(Some(message)) => message,
+            Ok(None) => break,
+            Err(err) => {
+                eprintln!("Cannot read {}: {}", dead_queue, err);
+                break;
+            },
+        };
+        listed += 1;
+        let delivery = &message.delivery;
+        println!("{} #{}", dead_queue, listed);
+        println!("  reason: {}", header(delivery, REASON_HEADER).unwrap_or("rejected by broker".into()));
+        if let Some(timestamp) = delivery.properties.timestamp() {
+            println!("  rejected at: {}", timestamp);
+        }
+        println!("  body: {}", String::from_utf8_lossy(&delivery.data));
+    }
+    if listed == 0 {
+        println!("{}: empty", dead_queue);
+    }
+    let _ = channel.close(0, "").await;
+}
+
+async fn replay(connection: &Connection, queue: &str, count: usize) {
+    let Ok(channel) = connection.create_channel().await else {
+        eprintln!("Failed to open channel");
+        return;
+    };
+    let dead_queue = dead_letter_queue(queue);
+    let mut replayed = 0;
+    while replayed < count {
+        let message = match channel.basic_get(dead_queue.as_str(), BasicGetOptions::default()).await {
+            Ok(Some(message)) => message,
+            Ok(None) => break,
+            Err(err) => {
+                eprintln!("Cannot read {}: {}", dead_queue, err);
+                break;
+            },
+        };
+        let delivery = message.delivery;
+        if let Err(err) = republish(&channel, &delivery, queue).await {
+            eprintln!("Failed to replay message to {}: {}", queue, err);
+            break;
+        }
+        if let Err(err) = delivery.ack(BasicAckOptions::default()).await {
+            eprintln!("Failed to remove replayed message from {}: {}", dead_queue, err);
+            break;
+        }
+        replayed += 1;
+    }
+    println!("Replayed {} messages to {}", replayed, queue);
+    let _ = channel.close(0, "").await;
+}
+
+// a queue still holding messages is kept, its consumers have to drain it first
+async fn migrate(connection: &Connection, queue: &str) {
+    // a failed delete closes the channel, so every queue gets its own
+    let Ok(channel) = connection.create_channel().await else {
+        eprintln!("Failed to open channel");
+        return;
+    };
+    let options = QueueDeleteOptions { if_empty: true, ..Default::default() };
+    match channel.queue_delete(queue, options).await {
+        Ok(_) => println!("{}: deleted", queue),
+        Err(err) => eprintln!("{}: not deleted: {}", queue, err),
+    }
+    let _ = channel.close(0, "").await;
+}
+
+// default exchange routes by queue name, so the message only reaches the queue it came from
+async fn republish(channel: &Channel, delivery: &Delivery, queue: &str) -> Result<(), lapin::Error> {
+    let mut headers = FieldTable::default();
+    if let Some(original) = delivery.properties.headers() {
+        for (key, value) in original.inner() {
+            if ![REASON_HEADER, QUEUE_HEADER, "x-death"].contains(&key.as_str()) {
+                headers.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    let properties = BasicProperties::default().with_headers(headers);
+    channel
+        .basic_publish("", queue, BasicPublishOptions::default(), &delivery.data, properties)
+        .await?
+        .await?;
+    Ok(())
+}
+
+fn header(delivery: &Delivery, key: &str) -> Option<String> {
+    let headers = delivery.properties.headers().as_ref()?;
+    match headers.inner().get(key)? {
+        AMQPValue::LongString(value) => Some(value.to_string()),
+        AMQPValue::ShortString(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
@@ -0,0 +1,129 @@
+// Floods running game service with spectator chat and measures fan-out throughput.
+//
+// usage: loadtest [url] [game ids] [sockets] [messages]
+//   e.g. loadtest ws://localhost:8080/ws 1,2,3 3000 100
+//
+// Games have to exist and allow spectators with spectator chat. Sockets join as
+// guest spectators, spread evenly over the games, and one socket per game sends
+// the messages.
+
+use std::{env, sync::{atomic::{AtomicUsize, Ordering}, Arc}, time::{Duration, Instant}};
+
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::sync::Barrier;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+const MARKER: &str = "loadtest";
+
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = env::args().collect();
+    let url = args.get(1).cloned().unwrap_or("ws://localhost:8080/ws".into());
+    let games: Vec<usize> = args.get(2)
+        .map(|games| games.split(',').filter_map(|id| id.parse().ok()).collect())
+        .unwrap_or(vec![1]);
+    let sockets: usize = args.get(3).and_then(|n| n.parse().ok()).unwrap_or(1000);
+    let messages: usize = args.get(4).and_then(|n| n.parse().ok()).unwrap_or(100);
+    if games.is_empty() || sockets < games.len() {
+        eprintln!("Need at least one socket per game.");
+        return;
+    }
+
+    println!("Connecting {} sockets to {} games at {}…", sockets, games.len(), url);
+    let received = Arc::new(AtomicUsize::new(0));
+    let ready = Arc::new(Barrier::new(sockets + 1));
+    let start = Arc::new(Barrier::new(sockets + 1));
+
+    let mut tasks = Vec::new();
+    for i in 0..sockets {
+        let game = games[i % games.len()];
+        // first socket of every game is the one sending messages
+        let sending = i < games.len();
+        let task = tokio::spawn(socket(url.clone(), game, sending, messages, received.clone(), ready.clone(), start.clone()));
+        tasks.push(task);
+    }
+
+    ready.wait().await;
+    println!("All sockets subscribed, sending {} messages per game…", messages);
+    let started = Instant::now();
+    start.wait().await;
+
+    let expected = sockets * messages;
+    let deadline = started + Duration::from_secs(60);
+    while received.load(Ordering::Relaxed) < expected && Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let elapsed = started.elapsed().as_secs_f64();
+    let delivered = received.load(Ordering::Relaxed);
+
+    println!("Delivered {} of {} messages in {:.3} s", delivered, expected, elapsed);
+    println!("Throughput: {:.0} messages/s", delivered as f64 / elapsed);
+    if delivered < expected {
+        println!("Lost: {} messages", expected - delivered);
+    }
+    for task in tasks {
+        task.abort();
+    }
+}
+
+async fn socket(
+    url: String,
+    game: usize,
+    sending: bool,
+    messages: usize,
+    received: Arc<AtomicUsize>,
+    ready: Arc<Barrier>,
+    start: Arc<Barrier>,
+) {
+    let Ok((stream, _)) = connect_async(url.as_str()).await else {
+        fail(format!("Failed to connect to {}", url));
+    };
+    let (mut sender, mut receiver) = stream.split();
+
+    let subscribe = json!({ "path": "/subscribe", "gameId": game }).to_string();
+    if sender.send(Message::Text(subscribe)).await.is_err() {
+        fail(format!("Failed to subscribe to game {}", game));
+    }
+    // errors aren't tagged with a game, everything from the room is
+    match receiver.next().await {
+        Some(Ok(Message::Text(text))) => {
+            let msg: Value = serde_json::from_str(text.as_str()).unwrap_or_default();
+            if msg["gameId"].is_null() {
+                fail(format!("Failed to subscribe to game {}: {}", game, text));
+            }
+        },
+        _ => fail(format!("Connection closed while subscribing to game {}", game)),
+    }
+    ready.wait().await;
+    start.wait().await;
+
+    if sending {
+        tokio::spawn(async move {
+            for i in 0..messages {
+                let chat = json!({ "path": "/chat", "gameId": game, "message": format!("{} {}", MARKER, i) }).to_string();
+                if sender.send(Message::Text(chat)).await.is_err() {
+                    return;
+                }
+            }
+        });
+    }
+
+    while let Some(Ok(Message::Text(text))) = receiver.next().await {
+        let Ok(msg) = serde_json::from_str::<Value>(text.as_str()) else {
+            continue;
+        };
+        let is_chat = msg["message"]
+            .as_str()
+            .is_some_and(|message| message.starts_with(MARKER));
+        if is_chat {
+            received.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+// other sockets would wait on the barriers forever, so the whole run is stopped
+fn fail(msg: String) -> ! {
+    eprintln!("{}", msg);
+    std::process::exit(1);
+}
@@ -0,0 +1,181 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use protocol::TimeControl;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, info};
+
+use crate::{events, finish_game, get_game, store::StoreError, AppState, Game, GameStatus, GameType, Msg};
+
+// the player to move first gets this long before their time starts running, so they
+// can wait for the opponent to join without stalling the game forever
+const FIRST_MOVE_GRACE: i64 = 60;
+
+// all times are kept in milliseconds
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Clock {
+    pub time_control: TimeControl,
+    pub initial: i64,
+    pub increment: i64,
+    pub user_time: i64,
+    pub opponent_time: i64,
+    pub turn_started: Option<DateTime<Utc>>,
+}
+
+impl Clock {
+    // initial and increment are given in seconds
+    pub fn new(time_control: TimeControl, initial: i64, increment: i64) -> Option<Clock> {
+        if time_control == TimeControl::None {
+            return None
+        }
+        let initial = initial * 1000;
+        Some(Clock {
+            time_control,
+            initial,
+            increment: increment * 1000,
+            user_time: initial,
+            opponent_time: initial,
+            turn_started: None,
+        })
+    }
+
+    // times saved by main survive a reload of the game, the player to move is charged from
+    // the reload on, as the time since the last move isn't known, a fresh game starts the
+    // clock of the first player after the grace period
+    pub fn resume(mut self, user_time: Option<i64>, opponent_time: Option<i64>, now: DateTime<Utc>) -> Clock {
+        if user_time.is_none() && opponent_time.is_none() {
+            self.turn_started = Some(now + chrono::Duration::seconds(FIRST_MOVE_GRACE));
+            return self
+        }
+        self.user_time = user_time.unwrap_or(self.user_time);
+        self.opponent_time = opponent_time.unwrap_or(self.opponent_time);
+        self.turn_started = Some(now);
+        self
+    }
+
+    // turn_started lies ahead during the grace period of the first move, it isn't charged then
+    pub fn time_left(&self, first_user_turn: bool, now: DateTime<Utc>) -> i64 {
+        let time = match first_user_turn {
+            true => self.user_time,
+            false => self.opponent_time,
+        };
+        match self.turn_started {
+            None => time,
+            Some(started) => time - (now - started).num_milliseconds().max(0),
+        }
+    }
+
+    // returns false if the player ran out of time before the move, the player is charged until
+    // the move was made and the next turn starts once it's verified
+    pub fn on_move(&mut self, first_user_turn: bool, charged: bool, moved: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+        if charged {
+            let left = self.time_left(first_user_turn, moved);
+            if left <= 0 {
+                self.set_time(first_user_turn, 0);
+                return false
+            }
+            let left = match self.time_control {
+                TimeControl::Fischer => left + self.increment,
+                TimeControl::PerMove => self.initial,
+                TimeControl::None => left,
+            };
+            self.set_time(first_user_turn, left);
+        }
+        self.turn_started = Some(now);
+        true
+    }
+
+    pub fn set_time(&mut self, first_user_turn: bool, time: i64) {
+        match first_user_turn {
+            true => self.user_time = time,
+            false => self.opponent_time = time,
+        }
+    }
+}
+
+// times for the updates sent to main
+pub fn clock_times(clock: &Option<Clock>) -> (Option<i64>, Option<i64>) {
+    match clock {
+        Some(clock) => (Some(clock.user_time), Some(clock.opponent_time)),
+        None => (None, None),
+    }
+}
+
+pub fn is_charged(game: &Game) -> bool {
+    game.game_type != GameType::AI || game.first_user_turn
+}
+
+pub fn start_timer(state: Arc<AppState>, game: &Game) {
+    let Some(clock) = &game.clock else {
+        return;
+    };
+    let Some(turn_started) = clock.turn_started else {
+        return;
+    };
+    if game.finished || !is_charged(game) {
+        return;
+    }
+    let left = clock.time_left(game.first_user_turn, Utc::now()).max(0) as u64;
+    let id = game.id;
+    debug!("Clock for game {} will fall in {} ms", id, left);
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(left)).await;
+        check_flag(state, id, turn_started).await;
+    });
+}
+
+async fn check_flag(state: Arc<AppState>, id: usize, turn_started: DateTime<Utc>) {
+    loop {
+        let Ok(mut game) = get_game(&state, id).await else {
+            return;
+        };
+        let Some(clock) = &game.clock else {
+            return;
+        };
+        if game.finished || clock.turn_started != Some(turn_started) {
+            return;
+        }
+        if game.blocked {
+            // move is being verified, engine response will settle the clock
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            continue;
+        }
+        if clock.time_left(game.first_user_turn, Utc::now()) > 0 {
+            start_timer(state, &game);
+            return;
+        }
+        match flag_fall(&state, &mut game).await {
+            // game changed in the meantime, e.g. a move arrived, so it's checked again
+            Err(StoreError::Conflict) => continue,
+            Err(err) => error!("Failed to finish game {} on time: {}", id, err),
+            Ok(()) => (),
+        }
+        return;
+    }
+}
+
+pub async fn flag_fall(state: &Arc<AppState>, game: &mut Game) -> Result<(), StoreError> {
+    let player = game.get_current_user();
+    info!("Player {} ran out of time in game {}", player, game.id);
+    if let Some(clock) = game.clock.as_mut() {
+        clock.set_time(game.first_user_turn, 0);
+    }
+    game.status = match game.first_user_turn {
+        true => GameStatus::Lost,
+        false => GameStatus::Won,
+    };
+    finish_game(state, game).await?;
+
+    let msg = TimeoutMessage { player, status: game.status };
+    let msg = serde_json::to_string(&msg).unwrap();
+    let msg = Msg { msg, room: game.id, user: None, spectators_only: false };
+    events::send(state, msg).await;
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TimeoutMessage {
+    player: String,
+    status: GameStatus,
+}
@@ -0,0 +1,132 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tokio::sync::Mutex;
+use tracing::error;
+
+use crate::{get_game, rabbit::state_consumer::GameResponse, store::StoreError, AppState, Msg};
+
+// number of past events kept per room for resync
+pub const LOG_SIZE: usize = 200;
+// rooms share locks, a lock per room would have to be cleaned up after the game
+const LOCK_STRIPES: usize = 64;
+
+// held while an event of the room is numbered and handed to the hub
+pub struct RoomLocks(Vec<Mutex<()>>);
+
+impl Default for RoomLocks {
+    fn default() -> Self {
+        RoomLocks((0..LOCK_STRIPES).map(|_| Mutex::new(())).collect())
+    }
+}
+
+impl RoomLocks {
+    fn get(&self, room: usize) -> &Mutex<()> {
+        &self.0[room % self.0.len()]
+    }
+}
+
+fn seq_key(room: usize) -> String {
+    format!("seq_{}", room)
+}
+
+fn log_key(room: usize) -> String {
+    format!("events_{}", room)
+}
+
+// room-wide events get the next sequence number and land in the room log,
+// messages for a single user or for spectators only are sent as they are
+pub async fn send(state: &Arc<AppState>, mut msg: Msg) {
+    if msg.user.is_some() || msg.spectators_only {
+        state.hub.send(msg);
+        return;
+    }
+    // events reach the hub in the order of their numbers
+    let _lock = state.event_locks.get(msg.room).lock().await;
+    match log_event(state, msg.room, msg.msg.clone()).await {
+        Ok(logged) => msg.msg = logged,
+        Err(err) => error!("Failed to log event for room {}: {}", msg.room, err),
+    }
+    state.hub.send(msg);
+}
+
+// numbering, appending and trimming happen in one step, so the log has no gaps
+async fn log_event(state: &Arc<AppState>, room: usize, msg: String) -> Result<String, StoreError> {
+    let mut object = to_object(msg);
+    object.remove("seq");
+    let rest = Value::Object(object).to_string();
+    let head = "{\"seq\":";
+    let tail = match rest.as_str() {
+        "{}" => "}".to_string(),
+        _ => format!(",{}", &rest[1..]),
+    };
+    let seq = state.store.push_numbered(&seq_key(room), &log_key(room), head, &tail, LOG_SIZE).await?;
+    Ok(format!("{}{}{}", head, seq, tail))
+}
+
+pub async fn current_seq(state: &Arc<AppState>, room: usize) -> Result<u64, StoreError> {
+    let seq = state.store.get(&seq_key(room)).await?;
+    Ok(seq.and_then(|seq| seq.parse().ok()).unwrap_or(0))
+}
+
+pub fn with_seq(msg: String, seq: u64) -> String {
+    with_field(msg, "seq", seq.into())
+}
+
+pub fn with_field(msg: String, key: &str, value: Value) -> String {
+    let mut object = to_object(msg);
+    object.insert(key.into(), value);
+    Value::Object(object).to_string()
+}
+
+// plain text messages are wrapped so that they can carry fields
+fn to_object(msg: String) -> Map<String, Value> {
+    match serde_json::from_str::<Value>(msg.as_str()) {
+        Ok(Value::Object(object)) => object,
+        _ => Map::from_iter([("message".to_string(), Value::String(msg))]),
+    }
+}
+
+pub async fn events_since(state: &Arc<AppState>, room: usize, since: u64) -> Result<Vec<Value>, StoreError> {
+    let log = state.store.list(&log_key(room)).await?;
+    let mut events: Vec<Value> = log.iter()
+        .filter_map(|event| serde_json::from_str::<Value>(event.as_str()).ok())
+        .filter(|event| event["seq"].as_u64().is_some_and(|seq| seq > since))
+        .collect();
+    events.sort_by_key(|event| event["seq"].as_u64());
+    Ok(events)
+}
+
+pub async fn resync(state: &Arc<AppState>, username: &String, room: usize, request: ResyncRequest) -> Result<(), String> {
+    let game = get_game(state, room).await?;
+    let seq = current_seq(state, room).await?;
+    let events = events_since(state, room, request.since).await?;
+    // log has been trimmed past the client's position, only the state can be trusted
+    let complete = request.since >= seq
+        || events.first().and_then(|event| event["seq"].as_u64()) == Some(request.since + 1);
+
+    let msg = ResyncMessage {
+        seq,
+        game: GameResponse::from(&game),
+        events,
+        complete,
+    };
+    let msg = serde_json::to_string(&msg).unwrap();
+    let msg = Msg { msg, room, user: Some(username.clone()), spectators_only: false };
+    state.hub.send(msg);
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResyncRequest {
+    since: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ResyncMessage {
+    seq: u64,
+    game: GameResponse,
+    events: Vec<Value>,
+    complete: bool,
+}
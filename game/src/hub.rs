@@ -0,0 +1,164 @@
+use std::{collections::HashMap, sync::{Arc, Mutex}};
+
+use tokio::{sync::{broadcast, mpsc}, task::JoinHandle};
+
+use crate::{AppState, Msg, Role, Rooms};
+
+const ROOM_CAPACITY: usize = 256;
+const USER_CAPACITY: usize = 64;
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub enum Channel {
+    Room(usize),
+    User(String),
+}
+
+// channels are created for the first listener and dropped with the last one,
+// so a message only reaches connections that asked for it
+#[derive(Default)]
+pub struct Hub {
+    channels: Mutex<HashMap<Channel, broadcast::Sender<Msg>>>,
+}
+
+impl Hub {
+    pub fn send(&self, msg: Msg) {
+        let channel = match &msg.user {
+            Some(user) => Channel::User(user.clone()),
+            None => Channel::Room(msg.room),
+        };
+        let sender = self.channels
+            .lock()
+            .unwrap()
+            .get(&channel)
+            .cloned();
+        if let Some(sender) = sender {
+            let _ = sender.send(msg);
+        }
+    }
+
+    fn subscribe(&self, channel: &Channel) -> broadcast::Receiver<Msg> {
+        let capacity = match channel {
+            Channel::Room(_) => ROOM_CAPACITY,
+            Channel::User(_) => USER_CAPACITY,
+        };
+        self.channels
+            .lock()
+            .unwrap()
+            .entry(channel.clone())
+            .or_insert_with(|| broadcast::channel(capacity).0)
+            .subscribe()
+    }
+
+    fn release(&self, channel: &Channel) {
+        let mut channels = self.channels.lock().unwrap();
+        if channels.get(channel).is_some_and(|sender| sender.receiver_count() == 0) {
+            channels.remove(channel);
+        }
+    }
+}
+
+// receiver that gives its channel back to the hub when dropped
+struct Subscription {
+    state: Arc<AppState>,
+    channel: Channel,
+    rx: Option<broadcast::Receiver<Msg>>,
+}
+
+impl Subscription {
+    fn new(state: Arc<AppState>, channel: Channel) -> Subscription {
+        let rx = Some(state.hub.subscribe(&channel));
+        Subscription { state, channel, rx }
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.rx.take();
+        self.state.hub.release(&self.channel);
+    }
+}
+
+// task moving messages from a hub channel into the connection's queue
+struct Forwarder(JoinHandle<()>);
+
+impl Drop for Forwarder {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+fn forward(state: Arc<AppState>, channel: Channel, rooms: Rooms, tx: mpsc::Sender<Msg>) -> Forwarder {
+    // subscribing before spawning, so nothing sent right after the request is lost
+    let mut subscription = Subscription::new(state, channel);
+    let task = tokio::spawn(async move {
+        let Some(rx) = subscription.rx.as_mut() else {
+            return;
+        };
+        loop {
+            // lagged receivers skip ahead, clients notice the gap in sequence numbers and resync
+            let msg = match rx.recv().await {
+                Ok(msg) => msg,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+            if !is_delivered(&rooms, &msg) {
+                continue;
+            }
+            if tx.send(msg).await.is_err() {
+                break;
+            }
+        }
+    });
+    Forwarder(task)
+}
+
+// room 0 carries messages for the connection itself, like auth results
+fn is_delivered(rooms: &Rooms, msg: &Msg) -> bool {
+    match rooms.read().unwrap().get(&msg.room) {
+        Some(Role::Spectator) => true,
+        Some(Role::Player) => !msg.spectators_only,
+        None => msg.room == 0 && msg.user.is_some(),
+    }
+}
+
+// hub channels a single connection listens to
+pub struct Subscriptions {
+    state: Arc<AppState>,
+    rooms: Rooms,
+    tx: mpsc::Sender<Msg>,
+    forwarders: HashMap<usize, Forwarder>,
+    user: Option<Forwarder>,
+}
+
+impl Subscriptions {
+    pub fn new(state: Arc<AppState>, rooms: Rooms, tx: mpsc::Sender<Msg>, username: &String) -> Subscriptions {
+        let mut subscriptions = Subscriptions { state, rooms, tx, forwarders: HashMap::new(), user: None };
+        subscriptions.set_user(username);
+        subscriptions
+    }
+
+    pub fn set_user(&mut self, username: &String) {
+        self.user = None;
+        let channel = Channel::User(username.clone());
+        self.user = Some(forward(self.state.clone(), channel, self.rooms.clone(), self.tx.clone()));
+    }
+
+    // returns the previous role, if the room was already subscribed
+    pub fn add_room(&mut self, room: usize, role: Role) -> Option<Role> {
+        let previous = self.rooms.write().unwrap().insert(room, role);
+        if !self.forwarders.contains_key(&room) {
+            let forwarder = forward(self.state.clone(), Channel::Room(room), self.rooms.clone(), self.tx.clone());
+            self.forwarders.insert(room, forwarder);
+        }
+        previous
+    }
+
+    pub fn set_role(&mut self, room: usize, role: Role) {
+        self.rooms.write().unwrap().insert(room, role);
+    }
+
+    pub fn remove_room(&mut self, room: usize) -> Option<Role> {
+        self.forwarders.remove(&room);
+        self.rooms.write().unwrap().remove(&room)
+    }
+}
@@ -1,26 +1,38 @@
-use rabbit::{game_publisher::GameEvent, update_publisher::UpdateEvent};
-use redis::{Connection, Commands};
+use protocol::{engine::{AIMoveEvent, MoveEvent}, game::{GameEvent, RematchRequestEvent, UpdateEvent}, AIType, Color, GameStatus, GameType, RuleSet};
 use serde::{Deserialize, Serialize};
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, mpsc};
 use tracing::{debug, info};
-use std::sync::{Arc, Mutex, RwLock};
+use std::{collections::HashMap, sync::{Arc, Mutex, RwLock}, time::Duration};
 use axum::{extract::{ws::{Message, WebSocket}, State, WebSocketUpgrade}, response::IntoResponse, routing::get, Router};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 use futures::{sink::SinkExt, stream::StreamExt};
 use jsonwebtoken::{decode, DecodingKey, Validation};
 
 use crate::rabbit::lapin_listen;
-use crate::rabbit::move_publisher::MoveEvent;
+use crate::pending::{new_pending_move, send_pending_move, start_watchdog, PendingMove};
 use crate::config::get_config;
 use crate::rabbit::state_consumer::GameResponse;
+use crate::rabbit::update_publisher::GameUpdate;
+use crate::clock::{clock_times, flag_fall, start_timer, Clock};
+use crate::takeback::{answer_takeback, request_takeback};
+use crate::rematch::request_rematch;
+use crate::spectators::Spectators;
+use crate::events::{ResyncRequest, RoomLocks};
+use crate::hub::{Hub, Subscriptions};
+use crate::store::{ListChange, Store, StoreError};
 
 mod rabbit;
 mod config;
-
-#[derive(Clone, Serialize, Deserialize)]
-pub enum Color {
-    Red, White,
-}
+mod clock;
+mod takeback;
+mod rematch;
+mod spectators;
+mod events;
+mod pending;
+mod hub;
+mod store;
+#[cfg(test)]
+mod test;
 
 #[tokio::main]
 async fn main() {
@@ -28,25 +40,23 @@ async fn main() {
     tracing_subscriber::registry()
         .with(
             tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| format!("checkers={}", config.debug_level).into()),
+                .unwrap_or_else(|_| format!("game={}", config.debug_level).into()),
         )
         .with(tracing_subscriber::fmt::layer())
         .init();
     
-    let (tx, _rx) = broadcast::channel(100);
-
-    // TODO?: use connection pool
-    info!("Creating redis connection…");
-    let redis = redis::Client::open(config.redis)
-        .expect("Failed to connect to Redis");
-    let redis = redis.get_connection()
-        .expect("Failed to connect to Redis");
-    info!("Connected to redis…");
+    info!("Creating redis pool…");
+    let store = Store::new(config.redis.as_str())
+        .expect("Failed to create Redis pool");
+    info!("Redis pool created…");
 
     let (txmoves, _rxrabbit) = broadcast::channel(100);
+    let (txaimoves, _rxrabbit) = broadcast::channel(100);
     let (txgames, _rxrabbit) = broadcast::channel(100);
     let (txupdates, _rxrabbit) = broadcast::channel(100);
-    let state = AppState { jwt: config.jwt_secret, tx, redis: Mutex::from(redis), txmoves, txgames, txupdates };
+    let (txrematches, _rxrabbit) = broadcast::channel(100);
+    let spectators = Mutex::from(HashMap::new());
+    let state = AppState { jwt: config.jwt_secret, hub: Hub::default(), store, txmoves, txaimoves, txgames, txupdates, txrematches, spectators, event_locks: RoomLocks::default() };
     let state = Arc::new(state);
 
     let lapin_state = state.clone();
@@ -74,11 +84,15 @@ async fn main() {
 
 pub struct AppState {
     jwt: String,
-    tx: broadcast::Sender<Msg>,
-    redis: Mutex<Connection>,
+    hub: Hub,
+    store: Store,
     txmoves: broadcast::Sender<MoveEvent>,
+    txaimoves: broadcast::Sender<AIMoveEvent>,
     txgames: broadcast::Sender<GameEvent>,
-    txupdates: broadcast::Sender<UpdateEvent>,
+    txupdates: broadcast::Sender<GameUpdate>,
+    txrematches: broadcast::Sender<RematchRequestEvent>,
+    spectators: Mutex<Spectators>,
+    event_locks: RoomLocks,
 }
 
 async fn handle(ws: WebSocketUpgrade, State(state): State<Arc<AppState>>) -> impl IntoResponse {
@@ -90,82 +104,93 @@ pub struct Msg {
     msg: String,
     room: usize,
     user: Option<String>,
+    spectators_only: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Player, Spectator,
 }
 
+// subscribed rooms of a single connection
+pub type Rooms = Arc<RwLock<HashMap<usize, Role>>>;
+
 async fn websocket(stream: WebSocket, state: Arc<AppState>) {
     let (mut sender, mut receiver) = stream.split();
 
-    let mut rx = state.tx.subscribe();
+    // hub channels of the connection are forwarded into a single queue
+    let (tx, mut rx) = mpsc::channel::<Msg>(100);
 
-    let room = Arc::new(RwLock::new(0));
+    let rooms: Rooms = Arc::new(RwLock::new(HashMap::new()));
 
     let timestamped_name = format!("guest_{}", chrono::Utc::now().timestamp());
     let username = Arc::new(RwLock::new(timestamped_name));
 
-    let rm_send = room.clone();
-    let u = username.clone();
+    let mut subscriptions = Subscriptions::new(state.clone(), rooms.clone(), tx.clone(), &username.read().unwrap());
 
     let mut send_task = tokio::spawn(async move {
-        while let Ok(msg) = rx.recv().await {
-            if let Some(user) = msg.user {
-                if *u.read().unwrap() != user {
-                    continue;
-                }
-            }
-            if msg.room == *rm_send.read().unwrap() {
-                if sender.send(Message::Text(msg.msg)).await.is_err() {
-                    break;
-                }
+        while let Some(msg) = rx.recv().await {
+            let text = match msg.room {
+                0 => msg.msg,
+                room => events::with_field(msg.msg, "gameId", room.into()),
+            };
+            if sender.send(Message::Text(text)).await.is_err() {
+                break;
             }
         }
     });
 
-    let tx = state.tx.clone();
     let name = username.clone();
-    let rm = room.clone();
-    let state = state.clone();
+    let rm = rooms.clone();
 
+    let state_recv = state.clone();
     let mut recv_task = tokio::spawn(async move {
+        let state = state_recv;
         while let Some(Ok(Message::Text(text))) = receiver.next().await {
             let state = state.clone();
             debug!(text);
-            let path: WsPath = match serde_json::from_str(text.as_str())  {
+            let request: WsPath = match serde_json::from_str(text.as_str())  {
                 Err(_) => continue,
                 Ok(path) => path
             };
-            let path = path.path;
-            let username = name
+            let path = request.path;
+            let Ok(username) = name
                 .try_read()
-                .map(|a| a.clone());
-            let Ok(username) = username else {
+                .map(|a| a.clone()) else {
                 continue;
             };
-            match path.as_str() {
+            let room = get_room(&rm, request.game_id);
+            let res = match path.as_str() {
                 "/subscribe" => {
                     let room_request: SubscribeRequest = match serde_json::from_str(text.as_str())  {
                         Err(_) => continue,
                         Ok(request) => request,
                     };
-                    *rm.write().unwrap() = room_request.game_id;
-                    let room = *rm.read().unwrap();
-
-                    let game_loaded: Option<String> = state.redis
-                        .lock()
-                        .unwrap()
-                        .get(format!("room_{}", room)).unwrap();
-
-                    match game_loaded {
-                        None => {
-                            let event = GameEvent { game_id: room };
-                            let _ = state.txgames.send(event);
+                    subscribe(state, &username, room_request.game_id, &mut subscriptions).await
+                },
+                "/unsubscribe" => {
+                    let room_request: SubscribeRequest = match serde_json::from_str(text.as_str())  {
+                        Err(_) => continue,
+                        Ok(request) => request,
+                    };
+                    unsubscribe(&state, &username, room_request.game_id, &mut subscriptions).await
+                },
+                "/resync" => {
+                    let resync_request: ResyncRequest = match serde_json::from_str(text.as_str())  {
+                        Err(_) => continue,
+                        Ok(request) => request,
+                    };
+                    async { events::resync(&state, &username, room?, resync_request).await }.await
+                },
+                "/spectators" => {
+                    match room {
+                        Ok(room) => {
+                            let msg = spectators::spectators_message(&state, room);
+                            let msg: Msg = Msg { room, msg, user: Some(username.clone()), spectators_only: false };
+                            let _ = tx.send(msg).await;
+                            Ok(())
                         },
-                        Some(game) => {
-                            let game: Game = serde_json::from_str(game.as_str()).unwrap();
-                            let msg = GameResponse::from(&game);
-                            let msg = serde_json::to_string(&msg).unwrap();
-                            let msg = Msg { msg, room, user: Some(username) };
-                            let _ = state.tx.send(msg);
-                        }
+                        Err(err) => Err(err),
                     }
                 },
                 "/move" => {
@@ -173,42 +198,65 @@ async fn websocket(stream: WebSocket, state: Arc<AppState>) {
                         Err(_) => continue,
                         Ok(request) => request,
                     };
-                    let res = make_move(state, &username, *rm.read().unwrap(), move_request);
-                    if let Err(res) = res {
-                        let msg: Msg = Msg {room: *rm.read().unwrap(), msg: res, user: Some(username) };
-                        let _ = tx.send(msg);
-                    }
+                    async { make_move(state, &username, room?, move_request).await }.await
                 },
+                "/resign" => async { resign(state, &username, room?).await }.await,
+                "/offer_draw" => async { offer_draw(state, &username, room?).await }.await,
+                "/accept_draw" => async { answer_draw(state, &username, room?, true).await }.await,
+                "/decline_draw" => async { answer_draw(state, &username, room?, false).await }.await,
+                "/takeback" => async { request_takeback(state, &username, room?).await }.await,
+                "/accept_takeback" => async { answer_takeback(state, &username, room?, true).await }.await,
+                "/decline_takeback" => async { answer_takeback(state, &username, room?, false).await }.await,
+                "/rematch" => async { request_rematch(state, &username, room?, true).await }.await,
+                "/decline_rematch" => async { request_rematch(state, &username, room?, false).await }.await,
                 "/chat" => {
                     let chat_request: ChatRequest = match serde_json::from_str(text.as_str())  {
                         Err(_) => continue,
                         Ok(request) => request,
                     };
-                    chat(state, &username, *rm.read().unwrap(), chat_request)
+                    async {
+                        let room = room?;
+                        let is_spectator = rm.read().unwrap().get(&room) == Some(&Role::Spectator);
+                        chat(state, &username, room, chat_request, is_spectator).await
+                    }.await
                 },
                 "/auth" => {
                     let auth_request: AuthRequest = match serde_json::from_str(text.as_str())  {
                         Err(_) => continue,
                         Ok(request) => request,
                     };
-                    match token_to_username(auth_request.jwt, state) {
-                        Ok(username) => {
+                    match token_to_username(auth_request.jwt, state.clone()) {
+                        Ok(new_username) => {
+                            for room in spectated_rooms(&rm) {
+                                spectators::leave(&state, room, &username).await;
+                                spectators::join(&state, room, &new_username).await;
+                            }
+                            let username = new_username;
                             *name.write().unwrap() = username.clone();
+                            subscriptions.set_user(&username);
                             let msg = AuthMessage {authenticated: true, username: username.clone(), error: None };
                             let msg = serde_json::to_string(&msg).unwrap();
-                            let msg: Msg = Msg {room: *rm.read().unwrap(), msg, user: Some(username) };
-                            let _ = tx.send(msg);
+                            let msg: Msg = Msg {room: 0, msg, user: Some(username), spectators_only: false };
+                            let _ = tx.send(msg).await;
                         },
                         Err(err) => {
                             let msg = AuthMessage {authenticated: false, username: username.clone(), error: Some(err.to_string()) };
                             let msg = serde_json::to_string(&msg).unwrap();
-                            let msg: Msg = Msg {room: *rm.read().unwrap(), msg, user: Some(username) };
-                            let _ = tx.send(msg);
+                            let msg: Msg = Msg {room: 0, msg, user: Some(username.clone()), spectators_only: false };
+                            let _ = tx.send(msg).await;
                         },
                     };
+                    Ok(())
                 },
                 _ => continue,
             };
+            if let Err(res) = res {
+                let room = request.game_id
+                    .filter(|room| rm.read().unwrap().contains_key(room))
+                    .unwrap_or(0);
+                let msg: Msg = Msg {room, msg: res, user: Some(username), spectators_only: false };
+                let _ = tx.send(msg).await;
+            }
         }
     });
 
@@ -216,19 +264,121 @@ async fn websocket(stream: WebSocket, state: Arc<AppState>) {
         _ = (&mut send_task) => recv_task.abort(),
         _ = (&mut recv_task) => send_task.abort(),
     };
+
+    let username = username.read().unwrap().clone();
+    for room in spectated_rooms(&rooms) {
+        spectators::leave(&state, room, &username).await;
+    }
 }
 
-fn make_move(state: Arc<AppState>, username: &String, room: usize, request: MoveRequest) -> Result<(), String> {
-    let game_db: Option<String> = state.redis
-        .lock()
-        .unwrap()
-        .get(format!("room_{}", room)).unwrap();
-    let Some(game_db) = game_db else {
-        let event = GameEvent { game_id: room };
-        let _ = state.txgames.send(event);
-        return Err("Game not loaded!".into())
+// requests name their game, clients subscribed to a single one may leave it out
+fn get_room(rooms: &Rooms, game_id: Option<usize>) -> Result<usize, String> {
+    let rooms = rooms.read().unwrap();
+    match game_id {
+        Some(room) if rooms.contains_key(&room) => Ok(room),
+        Some(_) => Err("Not subscribed to game!".into()),
+        None if rooms.len() == 1 => Ok(*rooms.keys().next().unwrap()),
+        None => Err("Game id required!".into()),
+    }
+}
+
+fn spectated_rooms(rooms: &Rooms) -> Vec<usize> {
+    rooms.read().unwrap()
+        .iter()
+        .filter(|(_, role)| **role == Role::Spectator)
+        .map(|(room, _)| *room)
+        .collect()
+}
+
+async fn subscribe(state: Arc<AppState>, username: &String, room: usize, subscriptions: &mut Subscriptions) -> Result<(), String> {
+    // resubscribing decides the role again, e.g. after auth
+    let previous = subscriptions.add_room(room, Role::Player);
+    if previous == Some(Role::Spectator) {
+        spectators::leave(&state, room, username).await;
+    }
+
+    let loaded_game = match get_loaded_game(&state, room).await {
+        Ok(game) => game,
+        Err(err) => {
+            subscriptions.remove_room(room);
+            return Err(err.into())
+        },
     };
-    let mut game: Game = serde_json::from_str(game_db.as_str()).unwrap();
+    let (game, loaded) = match loaded_game {
+        Some(game) => (game, true),
+        None => {
+            let event = GameEvent { game_id: room };
+            let _ = state.txgames.send(event);
+            // role depends on the game, so wait for the state to be loaded
+            match wait_for_game(&state, room).await {
+                Ok(Some(game)) => (game, false),
+                Ok(None) => {
+                    subscriptions.remove_room(room);
+                    return Err("Game not loaded!".into())
+                },
+                Err(err) => {
+                    subscriptions.remove_room(room);
+                    return Err(err.into())
+                },
+            }
+        },
+    };
+
+    if username != &game.user && username != &game.opponent {
+        if !game.allow_spectators {
+            subscriptions.remove_room(room);
+            return Err("Spectators are not allowed in this game!".into())
+        }
+        subscriptions.set_role(room, Role::Spectator);
+        spectators::join(&state, room, username).await;
+    } else {
+        start_timer(state.clone(), &game);
+        start_watchdog(state.clone(), &game);
+    }
+
+    // freshly loaded state has already been broadcast to the room
+    if loaded {
+        let msg = GameResponse::from(&game);
+        let msg = serde_json::to_string(&msg).unwrap();
+        let msg = events::with_seq(msg, events::current_seq(&state, room).await?);
+        let msg = Msg { msg, room, user: Some(username.clone()), spectators_only: false };
+        state.hub.send(msg);
+    }
+    Ok(())
+}
+
+async fn unsubscribe(state: &Arc<AppState>, username: &String, room: usize, subscriptions: &mut Subscriptions) -> Result<(), String> {
+    let role = subscriptions.remove_room(room);
+    match role {
+        None => Err("Not subscribed to game!".into()),
+        Some(Role::Spectator) => {
+            spectators::leave(state, room, username).await;
+            Ok(())
+        },
+        Some(Role::Player) => Ok(()),
+    }
+}
+
+fn game_key(id: usize) -> String {
+    format!("room_{}", id)
+}
+
+async fn get_loaded_game(state: &Arc<AppState>, room: usize) -> Result<Option<Game>, StoreError> {
+    state.store.get_json(&game_key(room)).await
+}
+
+async fn wait_for_game(state: &Arc<AppState>, room: usize) -> Result<Option<Game>, StoreError> {
+    for _ in 0..20 {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        if let Some(game) = get_loaded_game(state, room).await? {
+            return Ok(Some(game))
+        }
+    }
+    Ok(None)
+}
+
+async fn make_move(state: Arc<AppState>, username: &String, room: usize, request: MoveRequest) -> Result<(), String> {
+    let mut game = get_game(&state, room).await?;
     if username != &game.user && username != &game.opponent {
         return Err("Not in game!".into())
     }
@@ -241,32 +391,168 @@ fn make_move(state: Arc<AppState>, username: &String, room: usize, request: Move
     if !((username == &game.user && game.first_user_turn) || (username == &game.opponent && !game.first_user_turn)) {
         return Err("Cannot move now!".into())
     }
+    let out_of_time = game.clock
+        .as_ref()
+        .is_some_and(|clock| clock.time_left(game.first_user_turn, chrono::Utc::now()) <= 0);
+    if out_of_time {
+        flag_fall(&state, &mut game).await?;
+        return Err("Out of time!".into())
+    }
+    let pending = new_pending_move(&state, username, request.mov).await?;
     game.blocked = true;
-    let game_ser = serde_json::to_string(&game).unwrap();
-    let _: () = state.redis
-        .lock()
-        .unwrap()
-        .set(format!("room_{}", room), game_ser).unwrap();
-    let color = game.get_current_color(); // TODO switch if rules definition says starting color is inverted
-
-    let event = MoveEvent {
+    game.pending_move = Some(pending.clone());
+    save_game(&state, &mut game).await?;
+
+    send_pending_move(&state, &game);
+    Ok(())
+}
+
+async fn get_game(state: &Arc<AppState>, room: usize) -> Result<Game, String> {
+    let Some(game) = get_loaded_game(state, room).await? else {
+        let event = GameEvent { game_id: room };
+        let _ = state.txgames.send(event);
+        return Err("Game not loaded!".into())
+    };
+    Ok(game)
+}
+
+// compare-and-set on the version, so a change based on a stale read is rejected
+async fn save_game(state: &Arc<AppState>, game: &mut Game) -> Result<(), StoreError> {
+    save_game_with(state, game, None).await
+}
+
+// the list, like the move history, is changed only if the game is saved
+async fn save_game_with(state: &Arc<AppState>, game: &mut Game, list: Option<(&str, ListChange)>) -> Result<(), StoreError> {
+    let version = game.version;
+    game.version += 1;
+    let value = serde_json::to_string(game)?;
+    if !state.store.set_if_version(&game_key(game.id), version, value, list).await? {
+        game.version = version;
+        return Err(StoreError::Conflict)
+    }
+    Ok(())
+}
+
+async fn get_game_for_player(state: &Arc<AppState>, username: &String, room: usize) -> Result<Game, String> {
+    let game = get_active_game(state, username, room).await?;
+    if game.blocked {
+        return Err("Cannot do that now!".into())
+    }
+    Ok(game)
+}
+
+// resignation and draws don't depend on the position, so they may come while a move is verified
+async fn get_active_game(state: &Arc<AppState>, username: &String, room: usize) -> Result<Game, String> {
+    let game = get_game(state, room).await?;
+    if username != &game.user && username != &game.opponent {
+        return Err("Not in game!".into())
+    }
+    if game.finished {
+        return Err("Game is finished!".into())
+    }
+    Ok(game)
+}
+
+// a move still being verified is dropped, the engine's late answer is then ignored
+async fn finish_game(state: &Arc<AppState>, game: &mut Game) -> Result<(), StoreError> {
+    game.finished = true;
+    game.blocked = false;
+    game.pending_move = None;
+    game.draw_offer = None;
+    game.takeback_offer = None;
+    save_game(state, game).await?;
+
+    let (user_time, opponent_time) = clock_times(&game.clock);
+    let event = UpdateEvent {
         game_id: game.id,
-        game_state: game.current_state,
-        mov: request.mov,
-        ruleset: game.ruleset,
-        color, 
-        noncapture_moves: game.noncapture_moves,
+        status: game.status,
+        current_state: game.current_state.clone(),
+        user_turn: game.first_user_turn,
+        last_move: String::new(),
+        timestamp: chrono::Utc::now(),
         nonpromoting_moves: game.nonpromoting_moves,
+        noncapture_moves: game.noncapture_moves,
+        event_id: Some(game.event_id()),
+        ply: Some(game.ply),
+        user_time,
+        opponent_time,
+    };
+    let _ = state.txupdates.send(GameUpdate::Move(event));
+    Ok(())
+}
+
+async fn resign(state: Arc<AppState>, username: &String, room: usize) -> Result<(), String> {
+    let mut game = get_active_game(&state, username, room).await?;
+    game.status = match username == &game.user {
+        true => GameStatus::Lost,
+        false => GameStatus::Won,
+    };
+    finish_game(&state, &mut game).await?;
+
+    let msg = ResignMessage { player: username.clone(), status: game.status };
+    let msg = serde_json::to_string(&msg).unwrap();
+    let msg: Msg = Msg { room, msg, user: None, spectators_only: false };
+    events::send(&state, msg).await;
+    Ok(())
+}
+
+async fn offer_draw(state: Arc<AppState>, username: &String, room: usize) -> Result<(), String> {
+    let mut game = get_active_game(&state, username, room).await?;
+    if game.game_type == GameType::AI {
+        return Err("AI doesn't accept draws!".into())
+    }
+    if game.draw_offer.is_some() {
+        return Err("Draw already offered!".into())
+    }
+    game.draw_offer = Some(username.clone());
+    save_game(&state, &mut game).await?;
+
+    let msg = DrawMessage { player: username.clone(), draw: DrawAction::Offered };
+    let msg = serde_json::to_string(&msg).unwrap();
+    let msg: Msg = Msg { room, msg, user: None, spectators_only: false };
+    events::send(&state, msg).await;
+    Ok(())
+}
+
+async fn answer_draw(state: Arc<AppState>, username: &String, room: usize, accepted: bool) -> Result<(), String> {
+    let mut game = get_active_game(&state, username, room).await?;
+    match &game.draw_offer {
+        Some(offering) if offering != username => {},
+        _ => return Err("No draw offer to answer!".into()),
+    }
+    let action = match accepted {
+        true => {
+            game.status = GameStatus::Drawn;
+            finish_game(&state, &mut game).await?;
+            DrawAction::Accepted
+        },
+        false => {
+            game.draw_offer = None;
+            save_game(&state, &mut game).await?;
+            DrawAction::Declined
+        },
     };
-    let _ = state.txmoves.send(event);
+
+    let msg = DrawMessage { player: username.clone(), draw: action };
+    let msg = serde_json::to_string(&msg).unwrap();
+    let msg: Msg = Msg { room, msg, user: None, spectators_only: false };
+    events::send(&state, msg).await;
     Ok(())
 }
 
-fn chat(state: Arc<AppState>, username: &String, room: usize, request: ChatRequest) {
-    let msg = ChatMessage {player: username.clone(), message: request.message };
+// spectator chat never reaches the players
+async fn chat(state: Arc<AppState>, username: &String, room: usize, request: ChatRequest, spectator: bool) -> Result<(), String> {
+    if spectator {
+        let game = get_game(&state, room).await?;
+        if !game.spectator_chat {
+            return Err("Spectator chat is disabled!".into())
+        }
+    }
+    let msg = ChatMessage {player: username.clone(), message: request.message, spectator };
     let msg = serde_json::to_string(&msg).unwrap();
-    let msg: Msg = Msg {room, msg, user: None };
-    let _ = state.tx.send(msg);
+    let msg: Msg = Msg {room, msg, user: None, spectators_only: spectator };
+    events::send(&state, msg).await;
+    Ok(())
 }
 
 pub struct UserData {
@@ -323,13 +609,18 @@ pub struct TokenClaims {
     pub refresh: bool,
 }
 
+// requests are tagged with gameId, like the messages sent to the rooms
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct WsPath {
     path: String,
+    game_id: Option<usize>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Game {
+    #[serde(default)]
+    pub version: u64,
     pub id: usize,
     pub user: String,
     pub opponent: String,
@@ -344,9 +635,23 @@ pub struct Game {
     pub status: GameStatus,
     pub noncapture_moves: usize,
     pub nonpromoting_moves: usize,
+    pub draw_offer: Option<String>,
+    pub takeback_offer: Option<String>,
+    pub clock: Option<Clock>,
+    pub allow_spectators: bool,
+    pub spectator_chat: bool,
+    #[serde(default)]
+    pub pending_move: Option<PendingMove>,
+    // moves played so far, numbers the updates sent to main
+    #[serde(default)]
+    pub ply: usize,
 }
 
 impl Game {
+    // versions restart when Redis loses the game, the timestamp keeps ids apart then
+    pub fn event_id(&self) -> String {
+        format!("{}-{}-{}", self.id, self.version, chrono::Utc::now().timestamp_micros())
+    }
     pub fn get_current_color(&self) -> Color {
         match (self.first_user_starts, self.first_user_turn) {
             (true, true) => Color::White,
@@ -363,26 +668,6 @@ impl Game {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
-pub enum AIType {
-    None, Random, Counting,
-}
-
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
-pub enum GameType {
-    User, AI,
-}
-
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
-pub enum RuleSet {
-    British,
-}
-
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Copy)]
-pub enum GameStatus {
-    NotFinished, Won, Lost, Drawn,
-}
-
 #[derive(Debug, Serialize, Deserialize)]
 struct MoveRequest {
     #[serde(rename="move")]
@@ -390,6 +675,7 @@ struct MoveRequest {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct SubscribeRequest {
     game_id: usize,
 }
@@ -403,6 +689,7 @@ struct ChatRequest {
 struct ChatMessage {
     player: String,
     message: String,
+    spectator: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -416,3 +703,20 @@ struct AuthMessage {
     authenticated: bool,
     error: Option<String>,
 }
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ResignMessage {
+    player: String,
+    status: GameStatus,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum DrawAction {
+    Offered, Accepted, Declined,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DrawMessage {
+    player: String,
+    draw: DrawAction,
+}
@@ -0,0 +1,168 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use protocol::engine::{AIMoveEvent, MoveEvent};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, warn};
+
+use crate::{events, get_loaded_game, save_game, store::StoreError, AppState, Game, Msg};
+
+// time the engine gets to verify a move before the request is sent again
+const ENGINE_TIMEOUT: i64 = 5000;
+// requests sent for a single move before the game is unblocked
+pub const MAX_ATTEMPTS: u32 = 3;
+
+const MOVE_ID_KEY: &str = "move_id";
+
+// move request a blocked game is waiting for, engine responses carry its id
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PendingMove {
+    pub id: u64,
+    pub player: String,
+    #[serde(rename = "move")]
+    pub mov: String,
+    pub attempts: u32,
+    pub sent: DateTime<Utc>,
+    // when the player made the move, the clock isn't charged for the time spent verifying it
+    #[serde(default = "Utc::now")]
+    pub moved: DateTime<Utc>,
+    // AI moves are requested from the engine without a move of their own
+    #[serde(default)]
+    pub ai: bool,
+}
+
+pub async fn new_pending_move(state: &Arc<AppState>, player: &String, mov: String) -> Result<PendingMove, StoreError> {
+    let id = state.store.incr(MOVE_ID_KEY).await?;
+    Ok(PendingMove { id, player: player.clone(), mov, attempts: 1, sent: Utc::now(), moved: Utc::now(), ai: false })
+}
+
+// the game is not blocked, the player cannot move anyway while it's the AI's turn
+pub async fn new_pending_ai_move(state: &Arc<AppState>, game: &Game) -> Result<PendingMove, StoreError> {
+    let id = state.store.incr(MOVE_ID_KEY).await?;
+    Ok(PendingMove { id, player: game.get_current_user(), mov: String::new(), attempts: 1, sent: Utc::now(), moved: Utc::now(), ai: true })
+}
+
+// requests the pending move of the saved game from the engine and watches for the answer
+pub fn send_pending_move(state: &Arc<AppState>, game: &Game) {
+    let Some(pending) = &game.pending_move else {
+        return;
+    };
+    match pending.ai {
+        true => {
+            let _ = state.txaimoves.send(get_ai_move_event(game, pending));
+        },
+        false => {
+            let _ = state.txmoves.send(get_move_event(game, pending));
+        },
+    }
+    start_watchdog(state.clone(), game);
+}
+
+fn get_ai_move_event(game: &Game, pending: &PendingMove) -> AIMoveEvent {
+    AIMoveEvent {
+        game_id: game.id,
+        correlation_id: Some(pending.id),
+        game_state: game.current_state.clone(),
+        ruleset: game.ruleset,
+        ai_type: game.ai_type,
+        color: game.get_current_color(),
+        noncapture_moves: game.noncapture_moves,
+        nonpromoting_moves: game.nonpromoting_moves,
+    }
+}
+
+fn get_move_event(game: &Game, pending: &PendingMove) -> MoveEvent {
+    MoveEvent {
+        game_id: game.id,
+        correlation_id: Some(pending.id),
+        game_state: game.current_state.clone(),
+        mov: pending.mov.clone(),
+        ruleset: game.ruleset,
+        color: game.get_current_color(), // TODO switch if rules definition says starting color is inverted
+        noncapture_moves: game.noncapture_moves,
+        nonpromoting_moves: game.nonpromoting_moves,
+    }
+}
+
+pub fn start_watchdog(state: Arc<AppState>, game: &Game) {
+    let Some(pending) = &game.pending_move else {
+        return;
+    };
+    let waited = (Utc::now() - pending.sent).num_milliseconds();
+    let left = (ENGINE_TIMEOUT - waited).max(0) as u64;
+    let id = game.id;
+    let attempt = (pending.id, pending.attempts);
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(left)).await;
+        check_pending_move(state, id, attempt).await;
+    });
+}
+
+// attempt is the move id and the number of requests sent when the watchdog was started
+pub async fn check_pending_move(state: Arc<AppState>, id: usize, attempt: (u64, u32)) {
+    loop {
+        let mut game = match get_loaded_game(&state, id).await {
+            Ok(Some(game)) => game,
+            Ok(None) => return,
+            Err(err) => {
+                error!("Failed to check pending move in game {}: {}", id, err);
+                return;
+            },
+        };
+        let Some(pending) = game.pending_move.as_mut() else {
+            return;
+        };
+        if (pending.id, pending.attempts) != attempt {
+            debug!("Move {} in game {} has been answered or sent again", pending.id, id);
+            return;
+        }
+
+        let result = match pending.attempts < MAX_ATTEMPTS {
+            true => retry(&state, &mut game).await,
+            false => give_up(&state, &mut game).await,
+        };
+        match result {
+            // engine answered in the meantime, or another watchdog got there first
+            Err(StoreError::Conflict) => continue,
+            Err(err) => error!("Failed to recover move in game {}: {}", id, err),
+            Ok(()) => (),
+        }
+        return;
+    }
+}
+
+async fn retry(state: &Arc<AppState>, game: &mut Game) -> Result<(), StoreError> {
+    let Some(pending) = game.pending_move.as_mut() else {
+        return Ok(());
+    };
+    pending.attempts += 1;
+    pending.sent = Utc::now();
+    warn!("Engine did not answer move {} in game {}, sending it again (attempt {})", pending.id, game.id, pending.attempts);
+    save_game(state, game).await?;
+
+    send_pending_move(state, game);
+    Ok(())
+}
+
+async fn give_up(state: &Arc<AppState>, game: &mut Game) -> Result<(), StoreError> {
+    let Some(pending) = game.pending_move.take() else {
+        return Ok(());
+    };
+    error!("Engine did not answer move {} in game {} after {} attempts, unblocking the game", pending.id, game.id, pending.attempts);
+    game.blocked = false;
+    save_game(state, game).await?;
+
+    let msg = EngineTimeoutMessage { player: pending.player, mov: pending.mov, timeout: true };
+    let msg = serde_json::to_string(&msg).unwrap();
+    let msg = Msg { msg, room: game.id, user: None, spectators_only: false };
+    events::send(state, msg).await;
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EngineTimeoutMessage {
+    player: String,
+    #[serde(rename = "move")]
+    mov: String,
+    timeout: bool,
+}
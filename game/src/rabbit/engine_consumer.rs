@@ -1,73 +1,79 @@
 use std::sync::Arc;
 
-use lapin::{message::{Delivery, DeliveryResult}, options::BasicAckOptions, Channel};
-use redis::Commands;
 use ::serde::{Deserialize, Serialize};
-use tracing::{error, debug, info};
+use protocol::{broker::{handler, Broker, BrokerError, Delivery}, engine::{EngineEvent, RejectionReason}, game::UpdateEvent, queues::ENGINE_QUEUE};
+use tracing::{error, debug, info, warn};
 
-use crate::{AppState, Color, Game, GameStatus, GameType, Msg};
+use crate::{clock::{clock_times, flag_fall, is_charged, start_timer, Clock}, events, get_loaded_game, pending::{new_pending_ai_move, send_pending_move}, rabbit::update_publisher::GameUpdate, save_game, store::StoreError, takeback::save_game_with_move, AppState, Color, GameStatus, GameType, Msg};
 
-use super::{state_consumer::AIMoveEvent, update_publisher::UpdateEvent, MOVES_EXCHANGE};
+const MAX_ATTEMPTS: usize = 5;
 
-pub fn set_engine_delegate(consumer: lapin::Consumer, channel: Channel, state: Arc<AppState>) {
-    consumer.set_delegate({
-        move |delivery: DeliveryResult| {
-            info!("New engine message");
-            let channel = channel.clone();
-            let state = state.clone();
-            async move {
-                let channel = channel.clone();
-                let state = state.clone();
-                let delivery = match delivery {
-                    Ok(Some(delivery)) => delivery,
-                    Ok(None) => return,
-                    Err(error) => {
-                        error!("Failed to consume queue message {}", error);
-                        return;
-                    }
-                };
-
-                if let Ok(game) = get_event_from_message(&delivery) {
-                    process_message(game, state, channel).await;
-                }
+pub async fn set_engine_delegate(broker: Arc<dyn Broker>, state: Arc<AppState>) -> Result<(), BrokerError> {
+    broker.consume(ENGINE_QUEUE, "engine_game_consumer", handler(move |delivery| {
+        info!("New engine message");
+        let state = state.clone();
+        async move {
+            let event = get_event_from_message(&delivery)?;
+            apply_event(&event, state).await;
+            Ok(())
+        }
+    })).await
+}
 
-                delivery
-                    .ack(BasicAckOptions::default())
-                    .await
-                    .expect("Failed to acknowledge message");
-            }
+// game could change between read and write, e.g. by resignation, so the response is applied to the newest state
+async fn apply_event(event: &EngineEvent, state: Arc<AppState>) {
+    for _ in 0..MAX_ATTEMPTS {
+        match process_message(event, state.clone()).await {
+            Err(StoreError::Conflict) => debug!("Game {} changed, retrying engine response", event.game_id),
+            Err(err) => {
+                error!("Failed to apply engine response for game {}: {}", event.game_id, err);
+                return;
+            },
+            Ok(()) => return,
         }
     }
-    );
+    error!("Failed to apply engine response for game {}: too many conflicts", event.game_id);
 }
 
-async fn process_message(event: EngineEvent, state: Arc<AppState>, channel: Channel) {
-    let game_db: Option<String> = state.redis
-        .lock()
-        .unwrap()
-        .get(format!("room_{}", event.game_id)).unwrap();
-    let Some(game_db) = game_db else {
-        return;
+async fn process_message(event: &EngineEvent, state: Arc<AppState>) -> Result<(), StoreError> {
+    let Some(mut game) = get_loaded_game(&state, event.game_id).await? else {
+        return Ok(());
     };
-    let mut game: Game = serde_json::from_str(game_db.as_str()).unwrap();
+    if game.finished {
+        return Ok(());
+    }
+    // late answers to moves that have been given up on, or duplicates of retried requests
+    if game.pending_move.as_ref().map(|pending| pending.id) != event.correlation_id {
+        warn!("Ignoring engine response {:?} for game {}, it's not the pending move", event.correlation_id, game.id);
+        return Ok(());
+    }
 
     if !event.legal {
         game.blocked = false;
-        let game_data = serde_json::to_string(&game).unwrap();
-        let _: () = state.redis
-            .lock()
-            .unwrap()
-            .set(format!("room_{}", game.id), game_data.clone()).unwrap();
+        game.pending_move = None;
+        save_game(&state, &mut game).await?;
         if !event.ai {
-            let msg = get_error_message(game.id, game.get_current_user(), event.mov);
-            let _ = state.tx.send(msg);
+            let msg = get_error_message(game.id, game.get_current_user(), event.mov.clone(), event.reason.clone());
+            state.hub.send(msg);
         }
-        return
+        return Ok(())
     }
 
-    let old_state = game.current_state.clone();
-    game.current_state = event.new_state;
     game.blocked = false;
+    let moved = game.pending_move.take().map(|pending| pending.moved).unwrap_or_else(chrono::Utc::now);
+    let charged = is_charged(&game);
+    let in_time = match game.clock.as_mut() {
+        Some(clock) => clock.on_move(game.first_user_turn, charged, moved, chrono::Utc::now()),
+        None => true,
+    };
+    if !in_time {
+        return flag_fall(&state, &mut game).await
+    }
+
+    let previous = game.clone();
+    game.takeback_offer = None;
+    let old_state = game.current_state.clone();
+    game.current_state = event.new_state.clone();
     if event.finished {
         game.finished = true;
         game.status = match (event.lost, event.won, game.first_user_turn) {
@@ -82,6 +88,9 @@ async fn process_message(event: EngineEvent, state: Arc<AppState>, channel: Chan
     let user = game.get_current_user();
     debug!("first user turn: {}", game.first_user_turn);
     debug!("current user: {}", user);
+    if game.draw_offer.as_ref().is_some_and(|offering| offering != &user) {
+        game.draw_offer = None;
+    }
     game.first_user_turn = !game.first_user_turn;
     debug!("first user turn: {}", game.first_user_turn);
     debug!("current user: {}", game.get_current_user());
@@ -93,84 +102,46 @@ async fn process_message(event: EngineEvent, state: Arc<AppState>, channel: Chan
         true => 0,
         false => game.nonpromoting_moves+1,
     };
+    game.ply += 1;
+    if !game.first_user_turn && game.game_type == GameType::AI && !game.finished {
+        game.pending_move = Some(new_pending_ai_move(&state, &game).await?);
+    }
 
+    save_game_with_move(&state, &mut game, &previous).await?;
 
-    let game_data = serde_json::to_string(&game).unwrap();
-    let _: () = state.redis
-        .lock()
-        .unwrap()
-        .set(format!("room_{}", game.id), game_data.clone()).unwrap();
+    start_timer(state.clone(), &game);
 
-    let msg = get_move_message(game.id, &old_state, &(game.current_state), user, event.mov.clone(), &color);
-    let _ = state.tx.send(msg);
+    let msg = get_move_message(game.id, &old_state, &(game.current_state), user, event.mov.clone(), &color, game.clock.clone());
+    events::send(&state, msg).await;
 
+    let (user_time, opponent_time) = clock_times(&game.clock);
     let event = UpdateEvent {
         game_id: game.id,
         status: game.status,
         current_state: game.current_state.clone(),
         user_turn: game.first_user_turn,
-        last_move: event.mov,
+        last_move: event.mov.clone(),
         timestamp: chrono::Utc::now(),
         nonpromoting_moves: game.nonpromoting_moves,
         noncapture_moves: game.noncapture_moves,
+        event_id: Some(game.event_id()),
+        ply: Some(game.ply),
+        user_time,
+        opponent_time,
     };
-    let _ = state.txupdates.send(event);
+    let _ = state.txupdates.send(GameUpdate::Move(event));
 
-    if !game.first_user_turn && game.game_type == GameType::AI && !game.finished {
-        let engine_event = AIMoveEvent {
-            game_id: game.id,
-            game_state: game.current_state,
-            ruleset: game.ruleset,
-            ai_type: game.ai_type,
-            color: match color {
-                Color::Red => Color::White,
-                Color::White => Color::Red,
-            },
-            noncapture_moves: game.noncapture_moves,
-            nonpromoting_moves: game.nonpromoting_moves,
-        };
-        let engine_event = serde_json::to_string(&engine_event).unwrap();
-        if let Err(err) = channel
-            .basic_publish(
-                MOVES_EXCHANGE,
-                "move_ai",
-                Default::default(),
-                engine_event.into_bytes().as_slice(),
-                Default::default(),
-                )
-                .await {
-                    error!("Failed to publish message to destination exchange: {:?}", err);
-                };
-    }
+    send_pending_move(&state, &game);
+
+    Ok(())
 }
 
-fn get_event_from_message(delivery: &Delivery) -> Result<EngineEvent, ()> {
-    let message = std::str::from_utf8(&delivery.data).unwrap();
-    let message: EngineEvent = match serde_json::from_str(message) {
-        Ok(msg) => msg,
-        Err(err) => {
-            error!("Failed to deserialize game event: {:?}", err);
-            return Err(());
-        }
-    };
+fn get_event_from_message(delivery: &Delivery) -> Result<EngineEvent, String> {
+    let message: EngineEvent = delivery.parse()?.payload;
     info!("Received message: {:?}", &message);
     return Ok(message);
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct EngineEvent {
-    game_id: i64,
-    legal: bool,
-    new_state: String,
-    ai: bool,
-    finished: bool,
-    lost: bool,
-    won: bool,
-    #[serde(rename = "move")]
-    pub mov: String,
-}
-
 #[derive(Debug, Serialize, Deserialize)]
 struct MoveWsMessage {
     player: String,
@@ -178,6 +149,8 @@ struct MoveWsMessage {
     mov: String,
     legal: bool,
     details: Option<MoveDetails>,
+    reason: Option<RejectionReason>,
+    clock: Option<Clock>,
     // TODO: status
 }
 
@@ -209,7 +182,7 @@ fn have_promotions(state_old: &String, state_new: &String, color: &Color) -> boo
     old < new
 }
 
-fn get_move_message(id: usize, state_old: &String, state_new: &String, player: String, mov: String, color: &Color) -> Msg {
+fn get_move_message(id: usize, state_old: &String, state_new: &String, player: String, mov: String, color: &Color, clock: Option<Clock>) -> Msg {
     let len = state_old.len();
     let old: Vec<char> = state_old.to_lowercase().chars().collect();
     let new: Vec<char> = state_new.to_lowercase().chars().collect();
@@ -253,9 +226,11 @@ fn get_move_message(id: usize, state_old: &String, state_new: &String, player: S
             captures,
             promotion,
         }),
+        reason: None,
+        clock,
     };
     let msg = serde_json::to_string(&msg).unwrap();
-    Msg { msg, room: id, user: None }
+    Msg { msg, room: id, user: None, spectators_only: false }
 }
 
 fn get_start_end(mov: &String) -> Option<(usize, usize)> {
@@ -268,13 +243,15 @@ fn get_start_end(mov: &String) -> Option<(usize, usize)> {
     None
 }
 
-fn get_error_message(id: usize, player: String, mov: String) -> Msg {
+fn get_error_message(id: usize, player: String, mov: String, reason: Option<RejectionReason>) -> Msg {
     let msg = MoveWsMessage { 
         player: player.clone(), 
         mov, 
         legal: false, 
         details: None,
+        reason,
+        clock: None,
     };
     let msg = serde_json::to_string(&msg).unwrap();
-    Msg { msg, room: id, user: Some(player) }
+    Msg { msg, room: id, user: Some(player), spectators_only: false }
 }
@@ -0,0 +1,36 @@
+use std::sync::Arc;
+
+use protocol::{broker::{handler, Broker, BrokerError}, game::MatchEvent, queues::MATCHES_QUEUE};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{AppState, Msg};
+
+pub async fn set_match_delegate(broker: Arc<dyn Broker>, state: Arc<AppState>) -> Result<(), BrokerError> {
+    broker.consume(MATCHES_QUEUE, "match_game_consumer", handler(move |delivery| {
+        info!("New match message");
+        let state = state.clone();
+        async move {
+            let message: MatchEvent = delivery.parse()?.payload;
+            info!("Received message: {:?}", &message);
+            notify(&state, message.game_id, &message.user, &message.opponent);
+            notify(&state, message.game_id, &message.opponent, &message.user);
+            Ok(())
+        }
+    })).await
+}
+
+// players aren't in the game's room yet, so they are told on their own channels
+fn notify(state: &Arc<AppState>, game_id: usize, player: &String, opponent: &String) {
+    let msg = MatchMessage { matched: true, game_id, opponent: opponent.clone() };
+    let msg = serde_json::to_string(&msg).unwrap();
+    state.hub.send(Msg { msg, room: 0, user: Some(player.clone()), spectators_only: false });
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MatchMessage {
+    matched: bool,
+    game_id: usize,
+    opponent: String,
+}
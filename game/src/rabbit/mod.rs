@@ -1,27 +1,31 @@
 use std::{sync::Arc, time::Duration};
 
-use deadpool_lapin::lapin::types::FieldTable;
-use lapin::{options::{BasicConsumeOptions, ExchangeDeclareOptions, QueueBindOptions, QueueDeclareOptions}, ExchangeKind};
+use protocol::{broker::{Broker, BrokerError, LapinBroker}, queues::{ENGINE_QUEUE, MATCHES_QUEUE, REMATCH_STATES_QUEUE, STATES_QUEUE}};
+use tokio::task::JoinHandle;
 use tracing::{debug, info};
 
-use crate::{rabbit::{engine_consumer::set_engine_delegate, game_publisher::game_publisher, move_publisher::move_publisher, state_consumer::set_state_delegate, update_publisher::update_publisher}, AppState};
+use crate::{rabbit::{engine_consumer::set_engine_delegate, game_publisher::game_publisher, match_consumer::set_match_delegate, move_publisher::{ai_move_publisher, move_publisher}, rematch_consumer::set_rematch_delegate, rematch_publisher::rematch_publisher, state_consumer::set_state_delegate, update_publisher::update_publisher}, AppState};
 
 mod engine_consumer;
+mod match_consumer;
+mod rematch_consumer;
 pub mod state_consumer;
 pub mod move_publisher;
 pub mod game_publisher;
 pub mod update_publisher;
+pub mod rematch_publisher;
 
-const UPDATES_EXCHANGE: &str = "checkers.updates.topic";
+pub const UPDATES_EXCHANGE: &str = "checkers.updates.topic";
 const GAMES_EXCHANGE: &str = "checkers.games.topic";
 
 pub const STATE_EXCHANGE: &str = "checkers.state.topic";
-const STATE_QUEUE: &str = "checkers.states.queue";
 
 pub const MOVES_EXCHANGE: &str = "checkers.moves.topic";
 
 pub const ENGINE_EXCHANGE: &str = "checkers.engine.topic";
-const ENGINE_QUEUE: &str = "checkers.engine.queue";
+
+// producer named in envelopes of published messages
+const PRODUCER: &str = "game";
 
 pub async fn lapin_listen(pool: deadpool_lapin::Pool, state: Arc<AppState>) {
     let mut retry_interval = tokio::time::interval(Duration::from_secs(5));
@@ -42,138 +46,14 @@ async fn init_lapin_listen(pool: deadpool_lapin::Pool, state: Arc<AppState>) ->
         e
     })?;
     let channel = rmq_con.create_channel().await?;
+    let broker: Arc<dyn Broker> = Arc::new(LapinBroker::new(channel).await?);
 
-    channel
-        .exchange_declare(
-            UPDATES_EXCHANGE,
-            ExchangeKind::Topic,
-            ExchangeDeclareOptions {
-                durable: true,
-                ..Default::default()
-            },
-            FieldTable::default(),
-            )
-        .await?;
-    debug!("Declared exchange {:?}", UPDATES_EXCHANGE);
-
-    channel
-        .exchange_declare(
-            GAMES_EXCHANGE,
-            ExchangeKind::Topic,
-            ExchangeDeclareOptions {
-                durable: true,
-                ..Default::default()
-            },
-            FieldTable::default(),
-            )
-        .await?;
-    debug!("Declared exchange {:?}", GAMES_EXCHANGE);
-
-    channel
-        .exchange_declare(
-            STATE_EXCHANGE,
-            ExchangeKind::Topic,
-            ExchangeDeclareOptions {
-                durable: true,
-                ..Default::default()
-            },
-            FieldTable::default(),
-            )
-        .await?;
-    debug!("Declared exchange {:?}", STATE_EXCHANGE);
-    
-    channel.queue_declare(
-        STATE_QUEUE,
-        QueueDeclareOptions::default(),
-        Default::default(),
-        )
-        .await?;
-    debug!("Declared queue {:?}", STATE_QUEUE);
-
-    channel
-        .queue_bind(
-            STATE_QUEUE,
-            STATE_EXCHANGE,
-            "state",
-            QueueBindOptions::default(),
-            FieldTable::default(),
-            )
-        .await?;
-    debug!("Declared bind {:?} -> {:?}", STATE_EXCHANGE, STATE_QUEUE);
-
-    channel
-        .exchange_declare(
-            MOVES_EXCHANGE,
-            ExchangeKind::Topic,
-            ExchangeDeclareOptions {
-                durable: true,
-                ..Default::default()
-            },
-            FieldTable::default(),
-            )
-        .await?;
-    debug!("Declared exchange {:?}", MOVES_EXCHANGE);
-
-    channel
-        .exchange_declare(
-            ENGINE_EXCHANGE,
-            ExchangeKind::Topic,
-            ExchangeDeclareOptions {
-                durable: true,
-                ..Default::default()
-            },
-            FieldTable::default(),
-            )
-        .await?;
-    debug!("Declared exchange {:?}", ENGINE_EXCHANGE);
-    
-    channel.queue_declare(
-        ENGINE_QUEUE,
-        QueueDeclareOptions::default(),
-        Default::default(),
-        )
-        .await?;
-    debug!("Declared queue {:?}", ENGINE_QUEUE);
-
-    channel
-        .queue_bind(
-            ENGINE_QUEUE,
-            ENGINE_EXCHANGE,
-            "engine",
-            QueueBindOptions::default(),
-            FieldTable::default(),
-            )
-        .await?;
-    debug!("Declared bind {:?} -> {:?}", ENGINE_EXCHANGE, ENGINE_QUEUE);
-
-    let engine_consumer = channel.basic_consume(
-        ENGINE_QUEUE,
-        "engine_game_consumer",
-        BasicConsumeOptions::default(),
-        FieldTable::default())
-        .await?;
-
-    let state_consumer = channel.basic_consume(
-        STATE_QUEUE,
-        "state_game_consumer",
-        BasicConsumeOptions::default(),
-        FieldTable::default())
-        .await?;
-
-    debug!("Consumer connected, waiting for messages");
-    set_engine_delegate(engine_consumer, channel.clone(), state.clone());
-    set_state_delegate(state_consumer, channel.clone(), state.clone());
-
-
-    let mut handles = vec![];
-    handles.push(move_publisher(channel.clone(), state.clone()));
-    handles.push(game_publisher(channel.clone(), state.clone()));
-    handles.push(update_publisher(channel.clone(), state.clone()));
+    let handles = listen(broker.clone(), state).await?;
 
     let mut test_interval = tokio::time::interval(Duration::from_secs(5));
     loop {
         test_interval.tick().await;
-        match channel.status().connected() {
+        match broker.is_connected() {
             false => break,
             true => {},
         }
@@ -184,3 +64,30 @@ async fn init_lapin_listen(pool: deadpool_lapin::Pool, state: Arc<AppState>) ->
     };
     Ok(())
 }
+
+// declares topology and starts consumers and publishers, the handles stop the publishers
+pub async fn listen(broker: Arc<dyn Broker>, state: Arc<AppState>) -> Result<Vec<JoinHandle<()>>, BrokerError> {
+    broker.declare_exchange(UPDATES_EXCHANGE).await?;
+    broker.declare_exchange(GAMES_EXCHANGE).await?;
+    broker.declare_exchange(STATE_EXCHANGE).await?;
+    broker.declare_queue(STATES_QUEUE, STATE_EXCHANGE, "state").await?;
+    broker.declare_queue(MATCHES_QUEUE, STATE_EXCHANGE, "match").await?;
+    broker.declare_queue(REMATCH_STATES_QUEUE, STATE_EXCHANGE, "rematch").await?;
+    broker.declare_exchange(MOVES_EXCHANGE).await?;
+    broker.declare_exchange(ENGINE_EXCHANGE).await?;
+    broker.declare_queue(ENGINE_QUEUE, ENGINE_EXCHANGE, "engine").await?;
+
+    set_engine_delegate(broker.clone(), state.clone()).await?;
+    set_state_delegate(broker.clone(), state.clone()).await?;
+    set_match_delegate(broker.clone(), state.clone()).await?;
+    set_rematch_delegate(broker.clone(), state.clone()).await?;
+    debug!("Consumer connected, waiting for messages");
+
+    let mut handles = vec![];
+    handles.push(move_publisher(broker.clone(), state.clone()));
+    handles.push(ai_move_publisher(broker.clone(), state.clone()));
+    handles.push(game_publisher(broker.clone(), state.clone()));
+    handles.push(update_publisher(broker.clone(), state.clone()));
+    handles.push(rematch_publisher(broker.clone(), state.clone()));
+    Ok(handles)
+}
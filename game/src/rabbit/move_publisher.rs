@@ -1,42 +1,34 @@
 use std::sync::Arc;
 
-use lapin::Channel;
-use serde::{Deserialize, Serialize};
+use protocol::{broker::Broker, envelope::encode};
 use tracing::error;
 
-use crate::{AppState, RuleSet, Color};
+use crate::AppState;
 
-use super::MOVES_EXCHANGE;
+use super::{MOVES_EXCHANGE, PRODUCER};
 
-#[derive(Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct MoveEvent {
-    pub game_id: usize,
-    pub game_state: String,
-    #[serde(rename = "move")]
-    pub mov: String,
-    pub ruleset: RuleSet,
-    pub color: Color,
-    pub noncapture_moves: usize,
-    pub nonpromoting_moves: usize,
+pub fn move_publisher(broker: Arc<dyn Broker>, state: Arc<AppState>) -> tokio::task::JoinHandle<()> {
+    let mut rx = state.txmoves.subscribe();
+    tokio::spawn(async move {
+        while let Ok(event) = rx.recv().await {
+            let correlation_id = event.correlation_id.map(|id| id.to_string());
+            let msg = encode(event, PRODUCER, correlation_id);
+            if let Err(err) = broker.publish(MOVES_EXCHANGE, "move", msg).await {
+                error!("Failed to publish message to destination exchange: {:?}", err);
+            };
+        }
+    })
 }
 
-pub fn move_publisher(channel: Channel, state: Arc<AppState>) -> tokio::task::JoinHandle<()> {
+pub fn ai_move_publisher(broker: Arc<dyn Broker>, state: Arc<AppState>) -> tokio::task::JoinHandle<()> {
+    let mut rx = state.txaimoves.subscribe();
     tokio::spawn(async move {
-        let mut rx = state.txmoves.subscribe();
         while let Ok(event) = rx.recv().await {
-            let msg = serde_json::to_string(&event).unwrap();
-            if let Err(err) = channel
-                .basic_publish(
-                    MOVES_EXCHANGE,
-                    "move",
-                    Default::default(),
-                    msg.into_bytes().as_slice(),
-                    Default::default(),
-                    )
-                    .await {
-                        error!("Failed to publish message to destination exchange: {:?}", err);
-                    };
+            let correlation_id = event.correlation_id.map(|id| id.to_string());
+            let msg = encode(event, PRODUCER, correlation_id);
+            if let Err(err) = broker.publish(MOVES_EXCHANGE, "move_ai", msg).await {
+                error!("Failed to publish message to destination exchange: {:?}", err);
+            };
         }
     })
 }
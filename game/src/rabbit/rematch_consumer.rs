@@ -0,0 +1,35 @@
+use std::sync::Arc;
+
+use protocol::{broker::{handler, Broker, BrokerError}, game::{RematchEvent, RematchStatus}, queues::REMATCH_STATES_QUEUE};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{events, AppState, Msg};
+
+pub async fn set_rematch_delegate(broker: Arc<dyn Broker>, state: Arc<AppState>) -> Result<(), BrokerError> {
+    broker.consume(REMATCH_STATES_QUEUE, "rematch_game_consumer", handler(move |delivery| {
+        info!("New rematch message");
+        let state = state.clone();
+        async move {
+            let message: RematchEvent = delivery.parse()?.payload;
+            info!("Received message: {:?}", &message);
+            notify(&state, message).await;
+            Ok(())
+        }
+    })).await
+}
+
+// both players are still in the finished game's room, clients move to the new one once accepted
+async fn notify(state: &Arc<AppState>, event: RematchEvent) {
+    let msg = RematchMessage { player: event.player, rematch: event.status, rematch_id: event.rematch_id };
+    let msg = serde_json::to_string(&msg).unwrap();
+    events::send(state, Msg { msg, room: event.game_id, user: None, spectators_only: false }).await;
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RematchMessage {
+    player: String,
+    rematch: RematchStatus,
+    rematch_id: usize,
+}
@@ -0,0 +1,20 @@
+use std::sync::Arc;
+
+use protocol::{broker::Broker, envelope::encode};
+use tracing::error;
+
+use crate::AppState;
+
+use super::{GAMES_EXCHANGE, PRODUCER};
+
+pub fn rematch_publisher(broker: Arc<dyn Broker>, state: Arc<AppState>) -> tokio::task::JoinHandle<()> {
+    let mut rx = state.txrematches.subscribe();
+    tokio::spawn(async move {
+        while let Ok(event) = rx.recv().await {
+            let msg = encode(event, PRODUCER, None);
+            if let Err(err) = broker.publish(GAMES_EXCHANGE, "rematch", msg).await {
+                error!("Failed to publish message to destination exchange: {:?}", err);
+            };
+        }
+    })
+}
@@ -1,110 +1,95 @@
 use std::sync::Arc;
 
-use lapin::{message::{Delivery, DeliveryResult}, options::BasicAckOptions, Channel};
-use redis::Commands;
 use ::serde::{Deserialize, Serialize};
+use protocol::{broker::{handler, Broker, BrokerError}, game::StateEvent, queues::STATES_QUEUE};
 use tracing::{debug, error, info};
 
-use crate::{rabbit::MOVES_EXCHANGE, AIType, AppState, Color, Game, GameStatus, GameType, Msg, RuleSet};
+use crate::{clock::Clock, events, get_loaded_game, pending::{new_pending_ai_move, send_pending_move}, store::StoreError, takeback::save_game_clearing_history, AppState, Game, GameStatus, GameType, Msg};
 
 
-pub fn set_state_delegate(consumer: lapin::Consumer, channel: Channel, state: Arc<AppState>) {
-    consumer.set_delegate({
-        move |delivery: DeliveryResult| {
-            info!("New state message");
-            let channel = channel.clone();
-            let state = state.clone();
-            async move {
-                let channel = channel.clone();
-                let state = state.clone();
-                let delivery = match delivery {
-                    Ok(Some(delivery)) => delivery,
-                    Ok(None) => return,
-                    Err(error) => {
-                        error!("Failed to consume queue message {}", error);
-                        return;
-                    }
-                };
-
-                if let Ok(game) = get_game_from_message(&delivery, state.clone()) {
-                    process_message(game, state, channel).await;
-                }
-
-                delivery
-                    .ack(BasicAckOptions::default())
-                    .await
-                    .expect("Failed to acknowledge message");
+pub async fn set_state_delegate(broker: Arc<dyn Broker>, state: Arc<AppState>) -> Result<(), BrokerError> {
+    broker.consume(STATES_QUEUE, "state_game_consumer", handler(move |delivery| {
+        info!("New state message");
+        let state = state.clone();
+        async move {
+            let message: StateEvent = delivery.parse()?.payload;
+            if let Ok(game) = get_game_from_message(message, state.clone()).await {
+                process_message(game, state).await;
             }
+            Ok(())
         }
-    }
-    );
+    })).await
 }
 
-async fn process_message(game: Game, state: Arc<AppState>, channel: Channel) {
+async fn process_message(mut game: Game, state: Arc<AppState>) {
     debug!("Adding state for game {} to Redis", game.id);
-    let game_data = serde_json::to_string(&game).unwrap();
-    let _: () = state.redis
-        .lock()
-        .unwrap()
-        .set(format!("room_{}", game.id), game_data.clone()).unwrap();
+    if !game.first_user_turn && game.game_type == GameType::AI {
+        match new_pending_ai_move(&state, &game).await {
+            Ok(pending) => game.pending_move = Some(pending),
+            Err(err) => error!("Failed to prepare AI move for game {}: {}", game.id, err),
+        }
+    }
+    match store_game(&state, &mut game).await {
+        Ok(true) => {},
+        Ok(false) => {
+            debug!("Game {} is already loaded, ignoring state", game.id);
+            return;
+        },
+        Err(err) => {
+            error!("Failed to add state for game {} to Redis: {}", game.id, err);
+            return;
+        },
+    }
     let response = GameResponse::from(&game);
     let response = serde_json::to_string(&response).unwrap();
-    let msg = Msg { msg: response, room: game.id, user: None };
-    let _ = state.tx.send(msg);
+    let msg = Msg { msg: response, room: game.id, user: None, spectators_only: false };
+    events::send(&state, msg).await;
 
-    if !game.first_user_turn && game.game_type == GameType::AI {
+    if game.pending_move.is_some() {
         debug!("Asking engine for AI move in game {}", game.id);
-        let color = game.get_current_color();  // TODO
-        let engine_event = AIMoveEvent {
-            game_id: game.id,
-            game_state: game.current_state,
-            ruleset: game.ruleset,
-            ai_type: game.ai_type,
-            color,
-            noncapture_moves: game.noncapture_moves,
-            nonpromoting_moves: game.nonpromoting_moves,
-        };
-        let engine_event = serde_json::to_string(&engine_event).unwrap();
-        if let Err(err) = channel
-            .basic_publish(
-                MOVES_EXCHANGE,
-                "move_ai",
-                Default::default(),
-                engine_event.into_bytes().as_slice(),
-                Default::default(),
-                )
-                .await {
-                    error!("Failed to publish message to destination exchange: {:?}", err);
-                };
+        send_pending_move(&state, &game);
     }
 }
 
-fn get_game_from_message(delivery: &Delivery, state: Arc<AppState>) -> Result<Game, ()> {
-    let message = std::str::from_utf8(&delivery.data).unwrap();
-    let message: StateEvent = match serde_json::from_str(message) {
-        Ok(msg) => msg,
-        Err(err) => {
-            error!("Failed to deserialize state event: {:?}", err);
-            return Err(());
+// several requests may load the same game, so a duplicate or late state must not replace one
+// in progress, it's stored only if the game is missing or behind it, returns false if it isn't
+async fn store_game(state: &Arc<AppState>, game: &mut Game) -> Result<bool, StoreError> {
+    loop {
+        let loaded = get_loaded_game(state, game.id).await?;
+        game.version = match &loaded {
+            None => 0,
+            Some(current) if current.ply < game.ply => current.version,
+            Some(_) => return Ok(false),
+        };
+        match save_game_clearing_history(state, game).await {
+            // another copy of the state got there first, or the game moved on
+            Err(StoreError::Conflict) if loaded.is_none() => continue,
+            Err(StoreError::Conflict) => return Ok(false),
+            Err(err) => return Err(err),
+            Ok(()) => return Ok(true),
         }
-    };
+    }
+}
+
+async fn get_game_from_message(message: StateEvent, state: Arc<AppState>) -> Result<Game, ()> {
     info!("Received message: {:?}", &message);
 
     if message.error {
         debug!("Error in state event for game {}.", message.game_id);
-        let msg = Msg { msg: message.error_message.unwrap(), room: message.game_id, user: None };
-        let _ = state.tx.send(msg);
+        let msg = Msg { msg: message.error_message.unwrap(), room: message.game_id, user: None, spectators_only: false };
+        events::send(&state, msg).await;
         return Err(());
     }
 
     if message.status != GameStatus::NotFinished {
         debug!("Finished state event for game {}.", message.game_id);
-        let msg = Msg { msg: "Game is already finished.".into(), room: message.game_id, user: None };
-        let _ = state.tx.send(msg);
+        let msg = Msg { msg: "Game is already finished.".into(), room: message.game_id, user: None, spectators_only: false };
+        events::send(&state, msg).await;
         return Err(());
     }
 
     Ok(Game {
+        version: 0,
         blocked: false,
         finished: false,
         first_user_turn: message.user_turn,
@@ -119,41 +104,17 @@ fn get_game_from_message(delivery: &Delivery, state: Arc<AppState>) -> Result<Ga
         first_user_starts: message.user_starts,
         noncapture_moves: message.noncapture_moves,
         nonpromoting_moves: message.nonpromoting_moves,
+        draw_offer: None,
+        takeback_offer: None,
+        clock: Clock::new(message.time_control, message.time_initial, message.time_increment)
+            .map(|clock| clock.resume(message.user_time, message.opponent_time, chrono::Utc::now())),
+        allow_spectators: message.allow_spectators,
+        spectator_chat: message.spectator_chat,
+        pending_move: None,
+        ply: message.ply,
     })
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct StateEvent {
-    game_id: usize,
-    error: bool,
-    error_message: Option<String>,
-    user: String,
-    opponent: String,
-    user_turn: bool,
-    user_starts: bool,
-    current_state: String,
-    game_type: GameType,
-    ruleset: RuleSet,
-    ai_type: AIType,
-    status: GameStatus,
-    noncapture_moves: usize,
-    nonpromoting_moves: usize,
-}
-
-
-#[derive(Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct AIMoveEvent {
-    pub game_id: usize,
-    pub game_state: String,
-    pub ruleset: RuleSet,
-    pub ai_type: AIType,
-    pub color: Color,
-    pub noncapture_moves: usize,
-    pub nonpromoting_moves: usize,
-}
-
 #[derive(Serialize, Deserialize, Clone)]
 pub enum Field {
     WhitePawn, WhiteKing, RedPawn, RedKing, Empty,
@@ -167,6 +128,7 @@ pub struct GameResponse {
     pub username1: String,
     pub username2: String,
     pub current_state: Vec<Vec<Field>>,
+    pub clock: Option<Clock>,
 }
 
 impl GameResponse {
@@ -203,6 +165,7 @@ impl GameResponse {
             username1: game.user.clone(),
             username2: game.opponent.clone(),
             current_state,
+            clock: game.clock.clone(),
         }
     }
 }
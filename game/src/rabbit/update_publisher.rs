@@ -1,42 +1,30 @@
 use std::sync::Arc;
 
-use lapin::Channel;
-use serde::{Deserialize, Serialize};
+use protocol::{broker::Broker, envelope::encode, game::{TakebackEvent, UpdateEvent}};
 use tracing::error;
 
-use crate::{AppState, GameStatus};
+use crate::AppState;
 
-use super::UPDATES_EXCHANGE;
+use super::{UPDATES_EXCHANGE, PRODUCER};
 
-#[derive(Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct UpdateEvent {
-    pub game_id: usize,
-    pub status: GameStatus,
-    pub current_state: String,
-    pub user_turn: bool,
-    pub timestamp: chrono::DateTime<chrono::Utc>,
-    pub last_move: String,
-    pub noncapture_moves: usize,
-    pub nonpromoting_moves: usize,
+// moves and takebacks are published in the order they are sent, main applies them in that order
+#[derive(Clone, Debug)]
+pub enum GameUpdate {
+    Move(UpdateEvent),
+    Takeback(TakebackEvent),
 }
 
-pub fn update_publisher(channel: Channel, state: Arc<AppState>) -> tokio::task::JoinHandle<()> {
+pub fn update_publisher(broker: Arc<dyn Broker>, state: Arc<AppState>) -> tokio::task::JoinHandle<()> {
+    let mut rx = state.txupdates.subscribe();
     tokio::spawn(async move {
-        let mut rx = state.txupdates.subscribe();
-        while let Ok(event) = rx.recv().await {
-            let msg = serde_json::to_string(&event).unwrap();
-            if let Err(err) = channel
-                .basic_publish(
-                    UPDATES_EXCHANGE,
-                    "update",
-                    Default::default(),
-                    msg.into_bytes().as_slice(),
-                    Default::default(),
-                    )
-                    .await {
-                        error!("Failed to publish message to destination exchange: {:?}", err);
-                    };
+        while let Ok(update) = rx.recv().await {
+            let (routing_key, msg) = match update {
+                GameUpdate::Move(event) => ("update", encode(event, PRODUCER, None)),
+                GameUpdate::Takeback(event) => ("takeback", encode(event, PRODUCER, None)),
+            };
+            if let Err(err) = broker.publish(UPDATES_EXCHANGE, routing_key, msg).await {
+                error!("Failed to publish message to destination exchange: {:?}", err);
+            };
         }
     })
 }
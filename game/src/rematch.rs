@@ -0,0 +1,20 @@
+use std::sync::Arc;
+
+use protocol::game::RematchRequestEvent;
+
+use crate::{get_game, AppState};
+
+// main decides on the rematch, the room learns about it from the state exchange
+pub async fn request_rematch(state: Arc<AppState>, username: &String, room: usize, accepted: bool) -> Result<(), String> {
+    let game = get_game(&state, room).await?;
+    if username != &game.user && username != &game.opponent {
+        return Err("Not in game!".into())
+    }
+    if !game.finished {
+        return Err("Game is not finished yet!".into())
+    }
+
+    let event = RematchRequestEvent { game_id: room, player: username.clone(), accepted };
+    let _ = state.txrematches.send(event);
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use std::{collections::HashMap, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{events, AppState, Msg};
+
+// room -> spectator -> number of open connections
+pub type Spectators = HashMap<usize, HashMap<String, usize>>;
+
+pub async fn join(state: &Arc<AppState>, room: usize, username: &String) {
+    {
+        let mut spectators = state.spectators.lock().unwrap();
+        let room_spectators = spectators.entry(room).or_default();
+        *room_spectators.entry(username.clone()).or_insert(0) += 1;
+    }
+    broadcast_spectators(state, room).await;
+}
+
+pub async fn leave(state: &Arc<AppState>, room: usize, username: &String) {
+    {
+        let mut spectators = state.spectators.lock().unwrap();
+        let Some(room_spectators) = spectators.get_mut(&room) else {
+            return;
+        };
+        if let Some(connections) = room_spectators.get_mut(username) {
+            *connections -= 1;
+            if *connections == 0 {
+                room_spectators.remove(username);
+            }
+        }
+        if room_spectators.is_empty() {
+            spectators.remove(&room);
+        }
+    }
+    broadcast_spectators(state, room).await;
+}
+
+pub fn get_spectators(state: &Arc<AppState>, room: usize) -> Vec<String> {
+    let spectators = state.spectators.lock().unwrap();
+    let mut list: Vec<String> = spectators
+        .get(&room)
+        .map(|s| s.keys().cloned().collect())
+        .unwrap_or_default();
+    list.sort();
+    list
+}
+
+pub fn spectators_message(state: &Arc<AppState>, room: usize) -> String {
+    let spectators = get_spectators(state, room);
+    let msg = SpectatorsMessage { count: spectators.len(), spectators };
+    serde_json::to_string(&msg).unwrap()
+}
+
+async fn broadcast_spectators(state: &Arc<AppState>, room: usize) {
+    let msg = spectators_message(state, room);
+    let msg = Msg { msg, room, user: None, spectators_only: false };
+    events::send(state, msg).await;
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SpectatorsMessage {
+    count: usize,
+    spectators: Vec<String>,
+}
@@ -0,0 +1,234 @@
+use std::{fmt, ops::Deref};
+
+use async_trait::async_trait;
+use deadpool_redis::{Config, Pool, PoolError, Runtime};
+use redis::{AsyncCommands, RedisError, Script};
+use serde::de::DeserializeOwned;
+use tracing::{debug, error};
+
+// values are JSON objects, a missing key or field counts as version 0,
+// the list in the optional second key is changed only along with the value
+const SET_IF_VERSION: &str = r"
+local current = redis.call('GET', KEYS[1])
+local version = 0
+if current then
+    version = cjson.decode(current).version or 0
+end
+if version ~= tonumber(ARGV[1]) then
+    return 0
+end
+redis.call('SET', KEYS[1], ARGV[2])
+if ARGV[3] == 'push' then
+    redis.call('RPUSH', KEYS[2], ARGV[4])
+elseif ARGV[3] == 'pop' then
+    for _ = 1, tonumber(ARGV[4]) do
+        redis.call('RPOP', KEYS[2])
+    end
+elseif ARGV[3] == 'clear' then
+    redis.call('DEL', KEYS[2])
+end
+return 1
+";
+
+// the counter is spliced into the entry, so numbers in the list never repeat or go back
+const PUSH_NUMBERED: &str = r"
+local number = redis.call('INCR', KEYS[1])
+redis.call('RPUSH', KEYS[2], ARGV[1] .. number .. ARGV[2])
+redis.call('LTRIM', KEYS[2], -tonumber(ARGV[3]), -1)
+return number
+";
+
+#[derive(Debug)]
+pub enum StoreError {
+    Pool(PoolError),
+    Redis(RedisError),
+    Serialization(serde_json::Error),
+    Conflict,
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Pool(err) => write!(f, "Cannot get Redis connection: {}", err),
+            StoreError::Redis(err) => write!(f, "Redis error: {}", err),
+            StoreError::Serialization(err) => write!(f, "Malformed data in Redis: {}", err),
+            StoreError::Conflict => write!(f, "Value was modified concurrently"),
+        }
+    }
+}
+
+impl From<PoolError> for StoreError {
+    fn from(err: PoolError) -> Self {
+        StoreError::Pool(err)
+    }
+}
+
+impl From<RedisError> for StoreError {
+    fn from(err: RedisError) -> Self {
+        StoreError::Redis(err)
+    }
+}
+
+impl From<serde_json::Error> for StoreError {
+    fn from(err: serde_json::Error) -> Self {
+        StoreError::Serialization(err)
+    }
+}
+
+// details are logged, players only learn that something went wrong
+impl From<StoreError> for String {
+    fn from(err: StoreError) -> Self {
+        if let StoreError::Conflict = err {
+            debug!("{}", err);
+            return "Cannot do that now!".into()
+        }
+        error!("{}", err);
+        "Server error!".into()
+    }
+}
+
+// commands used by the service, implemented by Redis and the in-memory store of the tests
+#[async_trait]
+pub trait Backend: Send + Sync {
+    async fn get(&self, key: &str) -> Result<Option<String>, StoreError>;
+    // writes the value only if the stored one still has the expected version,
+    // the list is changed only if the value is written
+    async fn set_if_version(&self, key: &str, version: u64, value: String, list: Option<(&str, ListChange)>) -> Result<bool, StoreError>;
+    async fn incr(&self, key: &str) -> Result<u64, StoreError>;
+    async fn llen(&self, key: &str) -> Result<usize, StoreError>;
+    // counts from the end for negative indexes, like Redis
+    async fn index(&self, key: &str, index: isize) -> Result<Option<String>, StoreError>;
+    async fn list(&self, key: &str) -> Result<Vec<String>, StoreError>;
+    // increments the counter and appends head, the new count and tail to the list in one step,
+    // keeping only the newest entries, returns the count
+    async fn push_numbered(&self, counter: &str, key: &str, head: &str, tail: &str, size: usize) -> Result<u64, StoreError>;
+
+    // the service itself doesn't need these, the tests prepare and clean up keys with them
+    #[allow(dead_code)]
+    async fn set(&self, key: &str, value: String) -> Result<(), StoreError>;
+    #[allow(dead_code)]
+    async fn del(&self, key: &str) -> Result<(), StoreError>;
+    #[allow(dead_code)]
+    async fn rpush(&self, key: &str, value: String) -> Result<(), StoreError>;
+    #[allow(dead_code)]
+    async fn rpop(&self, key: &str) -> Result<Option<String>, StoreError>;
+    // keeps only the newest entries of the list
+    #[allow(dead_code)]
+    async fn trim(&self, key: &str, size: usize) -> Result<(), StoreError>;
+}
+
+pub struct Store {
+    backend: Box<dyn Backend>,
+}
+
+// change of a list written together with a versioned value
+pub enum ListChange {
+    Push(String),
+    Pop(usize),
+    Clear,
+}
+
+impl Store {
+    pub fn new(url: &str) -> Result<Store, String> {
+        let pool = Config::from_url(url)
+            .create_pool(Some(Runtime::Tokio1))
+            .map_err(|err| err.to_string())?;
+        Ok(Store::from(Redis(pool)))
+    }
+
+    pub async fn get_json<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, StoreError> {
+        match self.get(key).await? {
+            None => Ok(None),
+            Some(value) => Ok(Some(serde_json::from_str(value.as_str())?)),
+        }
+    }
+}
+
+impl<B: Backend + 'static> From<B> for Store {
+    fn from(backend: B) -> Self {
+        Store { backend: Box::new(backend) }
+    }
+}
+
+impl Deref for Store {
+    type Target = dyn Backend;
+
+    fn deref(&self) -> &Self::Target {
+        self.backend.as_ref()
+    }
+}
+
+struct Redis(Pool);
+
+#[async_trait]
+impl Backend for Redis {
+    async fn get(&self, key: &str) -> Result<Option<String>, StoreError> {
+        Ok(self.0.get().await?.get(key).await?)
+    }
+
+    async fn set(&self, key: &str, value: String) -> Result<(), StoreError> {
+        Ok(self.0.get().await?.set(key, value).await?)
+    }
+
+    async fn set_if_version(&self, key: &str, version: u64, value: String, list: Option<(&str, ListChange)>) -> Result<bool, StoreError> {
+        let mut conn = self.0.get().await?;
+        let script = Script::new(SET_IF_VERSION);
+        let mut invocation = script.key(key);
+        invocation.arg(version).arg(value);
+        if let Some((list, change)) = list {
+            invocation.key(list);
+            match change {
+                ListChange::Push(entry) => invocation.arg("push").arg(entry),
+                ListChange::Pop(count) => invocation.arg("pop").arg(count),
+                ListChange::Clear => invocation.arg("clear"),
+            };
+        }
+        let updated: bool = invocation.invoke_async(&mut conn).await?;
+        Ok(updated)
+    }
+
+    async fn del(&self, key: &str) -> Result<(), StoreError> {
+        Ok(self.0.get().await?.del(key).await?)
+    }
+
+    async fn incr(&self, key: &str) -> Result<u64, StoreError> {
+        Ok(self.0.get().await?.incr(key, 1).await?)
+    }
+
+    async fn rpush(&self, key: &str, value: String) -> Result<(), StoreError> {
+        Ok(self.0.get().await?.rpush(key, value).await?)
+    }
+
+    async fn rpop(&self, key: &str) -> Result<Option<String>, StoreError> {
+        Ok(self.0.get().await?.rpop(key, None).await?)
+    }
+
+    async fn llen(&self, key: &str) -> Result<usize, StoreError> {
+        Ok(self.0.get().await?.llen(key).await?)
+    }
+
+    async fn index(&self, key: &str, index: isize) -> Result<Option<String>, StoreError> {
+        Ok(self.0.get().await?.lindex(key, index).await?)
+    }
+
+    async fn list(&self, key: &str) -> Result<Vec<String>, StoreError> {
+        Ok(self.0.get().await?.lrange(key, 0, -1).await?)
+    }
+
+    async fn trim(&self, key: &str, size: usize) -> Result<(), StoreError> {
+        Ok(self.0.get().await?.ltrim(key, -(size as isize), -1).await?)
+    }
+
+    async fn push_numbered(&self, counter: &str, key: &str, head: &str, tail: &str, size: usize) -> Result<u64, StoreError> {
+        let mut conn = self.0.get().await?;
+        let number: u64 = Script::new(PUSH_NUMBERED)
+            .key(counter)
+            .key(key)
+            .arg(head)
+            .arg(tail)
+            .arg(size)
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(number)
+    }
+}
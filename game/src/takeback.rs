@@ -0,0 +1,164 @@
+use std::sync::Arc;
+
+use protocol::game::TakebackEvent;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::{clock::start_timer, events, get_game_for_player, rabbit::{state_consumer::GameResponse, update_publisher::GameUpdate}, save_game, save_game_with, store::{ListChange, StoreError}, AppState, Game, GameType, Msg};
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Position {
+    pub current_state: String,
+    pub first_user_turn: bool,
+    pub noncapture_moves: usize,
+    pub nonpromoting_moves: usize,
+}
+
+fn history_key(id: usize) -> String {
+    format!("history_{}", id)
+}
+
+// the position before the move is kept only if the move is saved
+pub async fn save_game_with_move(state: &Arc<AppState>, game: &mut Game, previous: &Game) -> Result<(), StoreError> {
+    let position = Position {
+        current_state: previous.current_state.clone(),
+        first_user_turn: previous.first_user_turn,
+        noncapture_moves: previous.noncapture_moves,
+        nonpromoting_moves: previous.nonpromoting_moves,
+    };
+    let position = serde_json::to_string(&position)?;
+    save_game_with(state, game, Some((&history_key(game.id), ListChange::Push(position)))).await
+}
+
+// loaded games start with an empty history
+pub async fn save_game_clearing_history(state: &Arc<AppState>, game: &mut Game) -> Result<(), StoreError> {
+    save_game_with(state, game, Some((&history_key(game.id), ListChange::Clear))).await
+}
+
+pub async fn history_len(state: &Arc<AppState>, id: usize) -> Result<usize, StoreError> {
+    state.store.llen(&history_key(id)).await
+}
+
+// position before the last moves, the history itself is changed only by saving the game
+pub async fn history_position(state: &Arc<AppState>, id: usize, moves: usize) -> Result<Option<Position>, StoreError> {
+    match state.store.index(&history_key(id), -(moves as isize)).await? {
+        Some(entry) => Ok(Some(serde_json::from_str(entry.as_str())?)),
+        None => Ok(None),
+    }
+}
+
+// requester to move means the opponent already answered, so both moves are taken back
+fn moves_to_revert(game: &Game, requester: &String) -> usize {
+    match &game.get_current_user() == requester {
+        true => 2,
+        false => 1,
+    }
+}
+
+pub async fn request_takeback(state: Arc<AppState>, username: &String, room: usize) -> Result<(), String> {
+    let mut game = get_game_for_player(&state, username, room).await?;
+    let moves = moves_to_revert(&game, username);
+    if history_len(&state, room).await? < moves {
+        return Err("Nothing to take back!".into())
+    }
+
+    if game.game_type == GameType::AI {
+        if !game.first_user_turn {
+            return Err("Cannot take back now!".into())
+        }
+        revert(&state, &mut game, moves).await?;
+        return Ok(())
+    }
+
+    if game.takeback_offer.is_some() {
+        return Err("Takeback already requested!".into())
+    }
+    game.takeback_offer = Some(username.clone());
+    save_game(&state, &mut game).await?;
+
+    let msg = TakebackMessage { player: username.clone(), takeback: TakebackAction::Requested };
+    let msg = serde_json::to_string(&msg).unwrap();
+    let msg: Msg = Msg { room, msg, user: None, spectators_only: false };
+    events::send(&state, msg).await;
+    Ok(())
+}
+
+pub async fn answer_takeback(state: Arc<AppState>, username: &String, room: usize, accepted: bool) -> Result<(), String> {
+    let mut game = get_game_for_player(&state, username, room).await?;
+    let requester = match &game.takeback_offer {
+        Some(requester) if requester != username => requester.clone(),
+        _ => return Err("No takeback request to answer!".into()),
+    };
+    game.takeback_offer = None;
+    let action = match accepted {
+        true => {
+            let moves = moves_to_revert(&game, &requester);
+            if history_len(&state, room).await? < moves {
+                save_game(&state, &mut game).await?;
+                return Err("Nothing to take back!".into())
+            }
+            revert(&state, &mut game, moves).await?;
+            TakebackAction::Accepted
+        },
+        false => {
+            save_game(&state, &mut game).await?;
+            TakebackAction::Declined
+        },
+    };
+
+    let msg = TakebackMessage { player: username.clone(), takeback: action };
+    let msg = serde_json::to_string(&msg).unwrap();
+    let msg: Msg = Msg { room, msg, user: None, spectators_only: false };
+    events::send(&state, msg).await;
+    Ok(())
+}
+
+// a move saved meanwhile changes the version, so the positions are popped only if the game is still the one read
+async fn revert(state: &Arc<AppState>, game: &mut Game, moves: usize) -> Result<(), StoreError> {
+    let Some(position) = history_position(state, game.id, moves).await? else {
+        return Ok(());
+    };
+    debug!("Taking back {} moves in game {}", moves, game.id);
+    game.current_state = position.current_state;
+    game.first_user_turn = position.first_user_turn;
+    game.noncapture_moves = position.noncapture_moves;
+    game.nonpromoting_moves = position.nonpromoting_moves;
+    game.ply = game.ply.saturating_sub(moves);
+    game.takeback_offer = None;
+    game.draw_offer = None;
+    if let Some(clock) = game.clock.as_mut() {
+        clock.turn_started = Some(chrono::Utc::now());
+    }
+    save_game_with(state, game, Some((&history_key(game.id), ListChange::Pop(moves)))).await?;
+    start_timer(state.clone(), game);
+
+    let event = TakebackEvent {
+        game_id: game.id,
+        moves,
+        current_state: game.current_state.clone(),
+        user_turn: game.first_user_turn,
+        timestamp: chrono::Utc::now(),
+        noncapture_moves: game.noncapture_moves,
+        nonpromoting_moves: game.nonpromoting_moves,
+        ply: Some(game.ply),
+    };
+    let _ = state.txupdates.send(GameUpdate::Takeback(event));
+
+    let msg = GameResponse::from(game);
+    let msg = serde_json::to_string(&msg).unwrap();
+    let msg = Msg { msg, room: game.id, user: None, spectators_only: false };
+    events::send(state, msg).await;
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum TakebackAction {
+    Requested, Accepted, Declined,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TakebackMessage {
+    player: String,
+    takeback: TakebackAction,
+}
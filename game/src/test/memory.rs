@@ -0,0 +1,168 @@
+use std::{collections::{HashMap, VecDeque}, sync::Mutex};
+
+use async_trait::async_trait;
+use redis::RedisError;
+
+use crate::store::{Backend, ListChange, StoreError};
+
+// in-memory stand-in for Redis, implementing only the commands used by the service
+#[derive(Default)]
+pub struct MemoryBackend {
+    data: Mutex<HashMap<String, Entry>>,
+}
+
+enum Entry {
+    Value(String),
+    List(VecDeque<String>),
+}
+
+#[async_trait]
+impl Backend for MemoryBackend {
+    async fn get(&self, key: &str) -> Result<Option<String>, StoreError> {
+        // lets other tasks run in between, like a round trip to Redis would
+        tokio::task::yield_now().await;
+        match self.data.lock().unwrap().get(key) {
+            None => Ok(None),
+            Some(Entry::Value(value)) => Ok(Some(value.clone())),
+            Some(Entry::List(_)) => Err(wrong_type()),
+        }
+    }
+
+    async fn set(&self, key: &str, value: String) -> Result<(), StoreError> {
+        self.data.lock().unwrap().insert(key.into(), Entry::Value(value));
+        Ok(())
+    }
+
+    async fn set_if_version(&self, key: &str, version: u64, value: String, list: Option<(&str, ListChange)>) -> Result<bool, StoreError> {
+        let mut data = self.data.lock().unwrap();
+        let current = match data.get(key) {
+            None => 0,
+            Some(Entry::Value(current)) => {
+                let current: serde_json::Value = serde_json::from_str(current)?;
+                current["version"].as_u64().unwrap_or(0)
+            },
+            Some(Entry::List(_)) => return Err(wrong_type()),
+        };
+        if current != version {
+            return Ok(false)
+        }
+        if let Some((list, change)) = list {
+            if let Some(Entry::Value(_)) = data.get(list) {
+                return Err(wrong_type());
+            }
+            let entries = data.entry(list.into()).or_insert_with(|| Entry::List(VecDeque::new()));
+            if let Entry::List(entries) = entries {
+                match change {
+                    ListChange::Push(entry) => entries.push_back(entry),
+                    ListChange::Pop(count) => entries.truncate(entries.len().saturating_sub(count)),
+                    ListChange::Clear => entries.clear(),
+                }
+            }
+        }
+        data.insert(key.into(), Entry::Value(value));
+        Ok(true)
+    }
+
+    async fn del(&self, key: &str) -> Result<(), StoreError> {
+        self.data.lock().unwrap().remove(key);
+        Ok(())
+    }
+
+    async fn incr(&self, key: &str) -> Result<u64, StoreError> {
+        let mut data = self.data.lock().unwrap();
+        let value = match data.get(key) {
+            None => 0,
+            Some(Entry::Value(value)) => value.parse::<u64>().map_err(|_| wrong_type())?,
+            Some(Entry::List(_)) => return Err(wrong_type()),
+        };
+        data.insert(key.into(), Entry::Value((value + 1).to_string()));
+        Ok(value + 1)
+    }
+
+    async fn rpush(&self, key: &str, value: String) -> Result<(), StoreError> {
+        let mut data = self.data.lock().unwrap();
+        let entry = data.entry(key.into()).or_insert_with(|| Entry::List(VecDeque::new()));
+        let Entry::List(list) = entry else {
+            return Err(wrong_type());
+        };
+        list.push_back(value);
+        Ok(())
+    }
+
+    async fn rpop(&self, key: &str) -> Result<Option<String>, StoreError> {
+        match self.data.lock().unwrap().get_mut(key) {
+            None => Ok(None),
+            Some(Entry::List(list)) => Ok(list.pop_back()),
+            Some(Entry::Value(_)) => Err(wrong_type()),
+        }
+    }
+
+    async fn llen(&self, key: &str) -> Result<usize, StoreError> {
+        match self.data.lock().unwrap().get(key) {
+            None => Ok(0),
+            Some(Entry::List(list)) => Ok(list.len()),
+            Some(Entry::Value(_)) => Err(wrong_type()),
+        }
+    }
+
+    async fn index(&self, key: &str, index: isize) -> Result<Option<String>, StoreError> {
+        match self.data.lock().unwrap().get(key) {
+            None => Ok(None),
+            Some(Entry::List(list)) => {
+                let index = match index < 0 {
+                    true => list.len().checked_sub(index.unsigned_abs()),
+                    false => Some(index as usize),
+                };
+                Ok(index.and_then(|index| list.get(index)).cloned())
+            },
+            Some(Entry::Value(_)) => Err(wrong_type()),
+        }
+    }
+
+    async fn list(&self, key: &str) -> Result<Vec<String>, StoreError> {
+        match self.data.lock().unwrap().get(key) {
+            None => Ok(vec![]),
+            Some(Entry::List(list)) => Ok(list.iter().cloned().collect()),
+            Some(Entry::Value(_)) => Err(wrong_type()),
+        }
+    }
+
+    async fn trim(&self, key: &str, size: usize) -> Result<(), StoreError> {
+        match self.data.lock().unwrap().get_mut(key) {
+            None => Ok(()),
+            Some(Entry::List(list)) => {
+                while list.len() > size {
+                    list.pop_front();
+                }
+                Ok(())
+            },
+            Some(Entry::Value(_)) => Err(wrong_type()),
+        }
+    }
+
+    async fn push_numbered(&self, counter: &str, key: &str, head: &str, tail: &str, size: usize) -> Result<u64, StoreError> {
+        let mut data = self.data.lock().unwrap();
+        let number = match data.get(counter) {
+            None => 1,
+            Some(Entry::Value(value)) => value.parse::<u64>().map_err(|_| wrong_type())? + 1,
+            Some(Entry::List(_)) => return Err(wrong_type()),
+        };
+        if let Some(Entry::Value(_)) = data.get(key) {
+            return Err(wrong_type());
+        }
+        data.insert(counter.into(), Entry::Value(number.to_string()));
+        let entry = data.entry(key.into()).or_insert_with(|| Entry::List(VecDeque::new()));
+        let Entry::List(list) = entry else {
+            return Err(wrong_type());
+        };
+        list.push_back(format!("{}{}{}", head, number, tail));
+        while list.len() > size {
+            list.pop_front();
+        }
+        Ok(number)
+    }
+}
+
+fn wrong_type() -> StoreError {
+    StoreError::Redis(RedisError::from((redis::ErrorKind::TypeError, "WRONGTYPE")))
+}
@@ -0,0 +1,746 @@
+use std::{collections::HashMap, env, sync::{Arc, Mutex}, time::Duration};
+
+use protocol::{broker::{handler, Broker, MemoryBroker}, engine::EngineEvent, envelope::encode, game::{MatchEvent, RematchEvent, RematchRequestEvent, RematchStatus, StateEvent, UpdateEvent}, AIType, GameStatus, GameType, RuleSet, TimeControl};
+use tokio::sync::{broadcast, mpsc};
+
+use crate::{clock::Clock, events, get_game, hub::{Hub, Subscriptions}, make_move, offer_draw, pending::{check_pending_move, new_pending_ai_move, MAX_ATTEMPTS}, rabbit::{self, update_publisher::GameUpdate, ENGINE_EXCHANGE, STATE_EXCHANGE, UPDATES_EXCHANGE}, rematch::request_rematch, resign, save_game, store::{Store, StoreError}, takeback::{answer_takeback, history_len, history_position, save_game_with_move}, AppState, Game, MoveRequest, Msg, Role, SubscribeRequest, WsPath};
+
+mod memory;
+
+use memory::MemoryBackend;
+
+// tests run against Redis at REDIS_URL if it's set, and against the in-memory store otherwise
+fn get_store() -> Store {
+    match env::var("REDIS_URL") {
+        Ok(url) => Store::new(url.as_str()).unwrap(),
+        Err(_) => Store::from(MemoryBackend::default()),
+    }
+}
+
+fn get_state() -> Arc<AppState> {
+    let (txmoves, _rx) = broadcast::channel(100);
+    let (txaimoves, _rx) = broadcast::channel(100);
+    let (txgames, _rx) = broadcast::channel(100);
+    let (txupdates, _rx) = broadcast::channel(100);
+    let (txrematches, _rx) = broadcast::channel(100);
+    Arc::new(AppState {
+        jwt: "secret".into(),
+        hub: Hub::default(),
+        store: get_store(),
+        txmoves,
+        txaimoves,
+        txgames,
+        txupdates,
+        txrematches,
+        spectators: Mutex::from(HashMap::new()),
+        event_locks: Default::default(),
+    })
+}
+
+fn get_game_model(id: usize) -> Game {
+    Game {
+        version: 0,
+        id,
+        user: "user".into(),
+        opponent: "opponent".into(),
+        finished: false,
+        first_user_turn: true,
+        first_user_starts: true,
+        blocked: false,
+        current_state: "xxxxxxxxxxxx........oooooooooooo".into(),
+        ai_type: AIType::None,
+        game_type: GameType::User,
+        ruleset: RuleSet::British,
+        status: GameStatus::NotFinished,
+        noncapture_moves: 0,
+        nonpromoting_moves: 0,
+        draw_offer: None,
+        takeback_offer: None,
+        clock: Clock::new(TimeControl::None, 0, 0),
+        allow_spectators: true,
+        spectator_chat: true,
+        pending_move: None,
+        ply: 0,
+    }
+}
+
+async fn clear(state: &Arc<AppState>, id: usize) {
+    for key in [format!("room_{}", id), format!("history_{}", id), format!("seq_{}", id), format!("events_{}", id)] {
+        state.store.del(&key).await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn store_should_keep_values() {
+    let state = get_state();
+    state.store.del("test_value").await.unwrap();
+
+    assert_eq!(state.store.get("test_value").await.unwrap(), None);
+    state.store.set("test_value", "value".into()).await.unwrap();
+    assert_eq!(state.store.get("test_value").await.unwrap(), Some("value".into()));
+    state.store.del("test_value").await.unwrap();
+    assert_eq!(state.store.get("test_value").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn store_should_count_up() {
+    let state = get_state();
+    state.store.del("test_counter").await.unwrap();
+
+    assert_eq!(state.store.incr("test_counter").await.unwrap(), 1);
+    assert_eq!(state.store.incr("test_counter").await.unwrap(), 2);
+    assert_eq!(state.store.get("test_counter").await.unwrap(), Some("2".into()));
+}
+
+#[tokio::test]
+async fn store_should_keep_lists() {
+    let state = get_state();
+    state.store.del("test_list").await.unwrap();
+
+    for value in ["a", "b", "c", "d"] {
+        state.store.rpush("test_list", value.into()).await.unwrap();
+    }
+    assert_eq!(state.store.llen("test_list").await.unwrap(), 4);
+    assert_eq!(state.store.rpop("test_list").await.unwrap(), Some("d".into()));
+    state.store.trim("test_list", 2).await.unwrap();
+    assert_eq!(state.store.list("test_list").await.unwrap(), vec!["b".to_string(), "c".to_string()]);
+}
+
+#[tokio::test]
+async fn store_should_push_numbered_entries() {
+    let state = get_state();
+    state.store.del("test_numbered_counter").await.unwrap();
+    state.store.del("test_numbered_list").await.unwrap();
+
+    for _ in 0..3 {
+        state.store.push_numbered("test_numbered_counter", "test_numbered_list", "<", ">", 2).await.unwrap();
+    }
+    assert_eq!(state.store.get("test_numbered_counter").await.unwrap(), Some("3".into()));
+    assert_eq!(state.store.list("test_numbered_list").await.unwrap(), vec!["<2>".to_string(), "<3>".to_string()]);
+}
+
+#[tokio::test]
+async fn store_should_reject_wrong_type() {
+    let state = get_state();
+    state.store.del("test_wrong_type").await.unwrap();
+    state.store.rpush("test_wrong_type", "a".into()).await.unwrap();
+
+    let result = state.store.get("test_wrong_type").await;
+    assert!(result.is_err());
+    let msg: String = result.unwrap_err().into();
+    assert_eq!(msg, "Server error!");
+}
+
+#[tokio::test]
+async fn saved_game_should_be_loaded() {
+    let state = get_state();
+    clear(&state, 101).await;
+    let mut game = get_game_model(101);
+    game.noncapture_moves = 5;
+    save_game(&state, &mut game).await.unwrap();
+
+    let loaded = get_game(&state, 101).await.unwrap();
+    assert_eq!(loaded.id, 101);
+    assert_eq!(loaded.user, "user");
+    assert_eq!(loaded.noncapture_moves, 5);
+    assert_eq!(loaded.current_state, game.current_state);
+}
+
+#[tokio::test]
+async fn missing_game_should_be_requested() {
+    let state = get_state();
+    clear(&state, 102).await;
+    let mut rx = state.txgames.subscribe();
+
+    let result = get_game(&state, 102).await;
+    assert_eq!(result.err(), Some("Game not loaded!".into()));
+    let event = rx.try_recv().unwrap();
+    assert_eq!(event.game_id, 102);
+}
+
+#[tokio::test]
+async fn history_should_be_taken_back_in_reverse_order() {
+    let state = get_state();
+    clear(&state, 103).await;
+    let mut game = get_game_model(103);
+    save_game(&state, &mut game).await.unwrap();
+    for i in 0..3 {
+        let previous = game.clone();
+        game.noncapture_moves = i + 1;
+        save_game_with_move(&state, &mut game, &previous).await.unwrap();
+    }
+    assert_eq!(history_len(&state, 103).await.unwrap(), 3);
+
+    let position = history_position(&state, 103, 2).await.unwrap().unwrap();
+    assert_eq!(position.noncapture_moves, 1);
+    game.takeback_offer = Some("user".into());
+    save_game(&state, &mut game).await.unwrap();
+    answer_takeback(state.clone(), &"opponent".into(), 103, true).await.unwrap();
+    assert_eq!(history_len(&state, 103).await.unwrap(), 1);
+    assert_eq!(get_game(&state, 103).await.unwrap().noncapture_moves, 1);
+}
+
+#[tokio::test]
+async fn room_events_should_be_numbered() {
+    let state = get_state();
+    clear(&state, 104).await;
+
+    for i in 0..3 {
+        let msg = Msg { msg: format!("{{\"event\":{}}}", i), room: 104, user: None, spectators_only: false };
+        events::send(&state, msg).await;
+    }
+    let msg = Msg { msg: "private".into(), room: 104, user: Some("user".into()), spectators_only: false };
+    events::send(&state, msg).await;
+
+    assert_eq!(events::current_seq(&state, 104).await.unwrap(), 3);
+    let missed = events::events_since(&state, 104, 1).await.unwrap();
+    assert_eq!(missed.len(), 2);
+    assert_eq!(missed[0]["seq"], 2);
+    assert_eq!(missed[0]["event"], 1);
+    assert_eq!(missed[1]["seq"], 3);
+}
+
+#[tokio::test]
+async fn event_log_should_be_bounded() {
+    let state = get_state();
+    clear(&state, 105).await;
+
+    let events = events::LOG_SIZE + 10;
+    for _ in 0..events {
+        let msg = Msg { msg: "move".into(), room: 105, user: None, spectators_only: false };
+        events::send(&state, msg).await;
+    }
+
+    let logged = events::events_since(&state, 105, 0).await.unwrap();
+    assert_eq!(logged.len(), events::LOG_SIZE);
+    assert_eq!(logged[0]["seq"], 11);
+    assert_eq!(logged[0]["message"], "move");
+}
+
+#[tokio::test]
+async fn stale_game_update_should_be_rejected() {
+    let state = get_state();
+    clear(&state, 106).await;
+    save_game(&state, &mut get_game_model(106)).await.unwrap();
+
+    let mut first = get_game(&state, 106).await.unwrap();
+    let mut second = get_game(&state, 106).await.unwrap();
+    first.noncapture_moves = 1;
+    save_game(&state, &mut first).await.unwrap();
+    second.noncapture_moves = 2;
+    let result = save_game(&state, &mut second).await;
+    assert!(matches!(result, Err(StoreError::Conflict)));
+
+    let loaded = get_game(&state, 106).await.unwrap();
+    assert_eq!(loaded.noncapture_moves, 1);
+    assert_eq!(loaded.version, 2);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_moves_should_be_processed_once() {
+    let state = get_state();
+    clear(&state, 107).await;
+    save_game(&state, &mut get_game_model(107)).await.unwrap();
+    let mut rx = state.txmoves.subscribe();
+
+    let mut tasks = Vec::new();
+    for _ in 0..10 {
+        let state = state.clone();
+        tasks.push(tokio::spawn(async move {
+            let request = MoveRequest { mov: "11-15".into() };
+            make_move(state, &"user".into(), 107, request).await
+        }));
+    }
+    let mut accepted = 0;
+    for task in tasks {
+        if task.await.unwrap().is_ok() {
+            accepted += 1;
+        }
+    }
+
+    assert_eq!(accepted, 1);
+    assert_eq!(rx.try_recv().unwrap().game_id, 107);
+    assert!(rx.try_recv().is_err());
+    assert!(get_game(&state, 107).await.unwrap().blocked);
+}
+
+#[tokio::test]
+async fn unanswered_move_should_be_sent_again() {
+    let state = get_state();
+    clear(&state, 108).await;
+    save_game(&state, &mut get_game_model(108)).await.unwrap();
+    make_move(state.clone(), &"user".into(), 108, MoveRequest { mov: "11-15".into() }).await.unwrap();
+    let mut rx = state.txmoves.subscribe();
+
+    let pending = get_game(&state, 108).await.unwrap().pending_move.unwrap();
+    check_pending_move(state.clone(), 108, (pending.id, 1)).await;
+
+    let event = rx.try_recv().unwrap();
+    assert_eq!(event.correlation_id, Some(pending.id));
+    assert_eq!(event.mov, "11-15");
+    let game = get_game(&state, 108).await.unwrap();
+    assert!(game.blocked);
+    assert_eq!(game.pending_move.unwrap().attempts, 2);
+
+    // watchdog of the first attempt is outdated now
+    check_pending_move(state.clone(), 108, (pending.id, 1)).await;
+    assert!(rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn game_should_be_unblocked_when_engine_never_answers() {
+    let state = get_state();
+    clear(&state, 109).await;
+    save_game(&state, &mut get_game_model(109)).await.unwrap();
+    make_move(state.clone(), &"user".into(), 109, MoveRequest { mov: "11-15".into() }).await.unwrap();
+    let rx = state.txmoves.subscribe();
+
+    let id = get_game(&state, 109).await.unwrap().pending_move.unwrap().id;
+    for attempt in 1..=MAX_ATTEMPTS {
+        check_pending_move(state.clone(), 109, (id, attempt)).await;
+    }
+
+    let game = get_game(&state, 109).await.unwrap();
+    assert!(!game.blocked);
+    assert!(game.pending_move.is_none());
+    assert_eq!(rx.len(), MAX_ATTEMPTS as usize - 1);
+    let logged = events::events_since(&state, 109, 0).await.unwrap();
+    assert_eq!(logged.last().unwrap()["timeout"], true);
+}
+
+#[tokio::test]
+async fn move_should_be_verified_by_engine_through_broker() {
+    let state = get_state();
+    clear(&state, 110).await;
+    save_game(&state, &mut get_game_model(110)).await.unwrap();
+    let broker: Arc<dyn Broker> = Arc::new(MemoryBroker::default());
+    let handles = rabbit::listen(broker.clone(), state.clone()).await.unwrap();
+    game_engine::rabbit::listen(broker.clone()).await.unwrap();
+
+    // stands in for the main service
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    broker.declare_queue("test.updates.queue", UPDATES_EXCHANGE, "update").await.unwrap();
+    broker.consume("test.updates.queue", "test", handler(move |delivery| {
+        let tx = tx.clone();
+        async move {
+            let _ = tx.send(delivery.parse::<UpdateEvent>()?);
+            Ok(())
+        }
+    })).await.unwrap();
+
+    make_move(state.clone(), &"user".into(), 110, MoveRequest { mov: "11-15".into() }).await.unwrap();
+
+    let update = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+    assert_eq!(update.producer, "game");
+    assert_eq!(update.payload.game_id, 110);
+    assert_eq!(update.payload.last_move, "11-15");
+    assert!(!update.payload.user_turn);
+    assert_eq!(update.payload.ply, Some(1));
+    assert!(update.payload.event_id.is_some());
+    let game = get_game(&state, 110).await.unwrap();
+    assert_eq!(game.ply, 1);
+    assert!(!game.blocked);
+    assert!(game.pending_move.is_none());
+    assert_eq!(game.current_state, update.payload.current_state);
+    assert_ne!(game.current_state, get_game_model(110).current_state);
+    for task in handles {
+        task.abort();
+    }
+}
+
+#[tokio::test]
+async fn matched_players_should_be_notified() {
+    let state = get_state();
+    let broker: Arc<dyn Broker> = Arc::new(MemoryBroker::default());
+    let handles = rabbit::listen(broker.clone(), state.clone()).await.unwrap();
+    let (tx, mut rx) = mpsc::channel(10);
+    let _subscriptions = Subscriptions::new(state.clone(), Default::default(), tx, &"matched_user".into());
+
+    let event = MatchEvent { game_id: 111, user: "matched_user".into(), opponent: "matched_opponent".into() };
+    broker.publish(STATE_EXCHANGE, "match", encode(event, "main", None)).await.unwrap();
+
+    let msg = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+    let msg: serde_json::Value = serde_json::from_str(msg.msg.as_str()).unwrap();
+    assert_eq!(msg["matched"], true);
+    assert_eq!(msg["gameId"], 111);
+    assert_eq!(msg["opponent"], "matched_opponent");
+    for task in handles {
+        task.abort();
+    }
+}
+
+fn get_state_event(id: usize, ply: usize) -> StateEvent {
+    StateEvent {
+        game_id: id,
+        error: false,
+        error_message: None,
+        user: "user".into(),
+        opponent: "opponent".into(),
+        user_turn: true,
+        user_starts: true,
+        current_state: get_game_model(id).current_state,
+        game_type: GameType::User,
+        ruleset: RuleSet::British,
+        ai_type: AIType::None,
+        status: GameStatus::NotFinished,
+        noncapture_moves: 0,
+        nonpromoting_moves: 0,
+        time_control: TimeControl::None,
+        time_initial: 0,
+        time_increment: 0,
+        allow_spectators: true,
+        spectator_chat: true,
+        ply,
+        user_time: None,
+        opponent_time: None,
+    }
+}
+
+#[tokio::test]
+async fn late_state_should_not_replace_game_in_progress() {
+    let state = get_state();
+    clear(&state, 120).await;
+    clear(&state, 121).await;
+    let broker: Arc<dyn Broker> = Arc::new(MemoryBroker::default());
+    let handles = rabbit::listen(broker.clone(), state.clone()).await.unwrap();
+    let mut game = get_game_model(120);
+    game.ply = 3;
+    game.current_state = "xxxxxxxxxxx.x.......oooooooooooo".into();
+    game.takeback_offer = Some("user".into());
+    save_game(&state, &mut game).await.unwrap();
+
+    for ply in [3, 1] {
+        broker.publish(STATE_EXCHANGE, "state", encode(get_state_event(120, ply), "main", None)).await.unwrap();
+    }
+    // states are consumed in order, so the other game is loaded after both were handled
+    broker.publish(STATE_EXCHANGE, "state", encode(get_state_event(121, 0), "main", None)).await.unwrap();
+    for _ in 0..50 {
+        if get_game(&state, 121).await.is_ok() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    let loaded = get_game(&state, 120).await.unwrap();
+    assert_eq!(loaded.version, game.version);
+    assert_eq!(loaded.current_state, game.current_state);
+    assert_eq!(loaded.takeback_offer, Some("user".into()));
+    assert_eq!(get_game(&state, 121).await.unwrap().ply, 0);
+
+    broker.publish(STATE_EXCHANGE, "state", encode(get_state_event(120, 5), "main", None)).await.unwrap();
+    for _ in 0..50 {
+        if get_game(&state, 120).await.unwrap().ply == 5 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(get_game(&state, 120).await.unwrap().ply, 5);
+    for task in handles {
+        task.abort();
+    }
+}
+
+#[tokio::test]
+async fn rematch_should_be_requested_once_game_is_finished() {
+    let state = get_state();
+    clear(&state, 112).await;
+    let mut game = get_game_model(112);
+    save_game(&state, &mut game).await.unwrap();
+    let mut rx = state.txrematches.subscribe();
+
+    assert!(request_rematch(state.clone(), &"user".into(), 112, true).await.is_err());
+    game.finished = true;
+    game.status = GameStatus::Won;
+    save_game(&state, &mut game).await.unwrap();
+    assert!(request_rematch(state.clone(), &"spectator".into(), 112, true).await.is_err());
+    request_rematch(state.clone(), &"opponent".into(), 112, false).await.unwrap();
+
+    let event = rx.try_recv().unwrap();
+    assert_eq!(event, RematchRequestEvent { game_id: 112, player: "opponent".into(), accepted: false });
+    assert!(rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn accepted_rematch_should_reach_room() {
+    let state = get_state();
+    clear(&state, 113).await;
+    let broker: Arc<dyn Broker> = Arc::new(MemoryBroker::default());
+    let handles = rabbit::listen(broker.clone(), state.clone()).await.unwrap();
+
+    let event = RematchEvent { game_id: 113, rematch_id: 114, player: "opponent".into(), status: RematchStatus::Accepted };
+    broker.publish(STATE_EXCHANGE, "rematch", encode(event, "main", None)).await.unwrap();
+
+    let mut logged = vec![];
+    for _ in 0..50 {
+        logged = events::events_since(&state, 113, 0).await.unwrap();
+        if !logged.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(logged.len(), 1);
+    assert_eq!(logged[0]["rematch"], "Accepted");
+    assert_eq!(logged[0]["rematchId"], 114);
+    assert_eq!(logged[0]["player"], "opponent");
+    for task in handles {
+        task.abort();
+    }
+}
+
+#[tokio::test]
+async fn player_should_resign_while_move_is_verified() {
+    let state = get_state();
+    clear(&state, 115).await;
+    save_game(&state, &mut get_game_model(115)).await.unwrap();
+    let mut rx = state.txupdates.subscribe();
+
+    make_move(state.clone(), &"user".into(), 115, MoveRequest { mov: "11-15".into() }).await.unwrap();
+    offer_draw(state.clone(), &"opponent".into(), 115).await.unwrap();
+    resign(state.clone(), &"opponent".into(), 115).await.unwrap();
+
+    let game = get_game(&state, 115).await.unwrap();
+    assert!(game.finished);
+    assert!(!game.blocked);
+    assert!(game.pending_move.is_none());
+    assert_eq!(game.status, GameStatus::Won);
+    let GameUpdate::Move(update) = rx.try_recv().unwrap() else {
+        panic!("Expected update of the resigned game");
+    };
+    assert_eq!(update.ply, Some(0));
+}
+
+#[test]
+fn fischer_clock_should_add_increment() {
+    let start = chrono::Utc::now();
+    let mut clock = Clock::new(TimeControl::Fischer, 60, 2).unwrap();
+
+    // waiting for the first move isn't charged
+    assert!(clock.on_move(true, true, start + chrono::Duration::seconds(30), start + chrono::Duration::seconds(30)));
+    assert_eq!(clock.user_time, 62000);
+    assert!(clock.on_move(false, true, start + chrono::Duration::seconds(35), start + chrono::Duration::seconds(35)));
+    assert_eq!(clock.opponent_time, 57000);
+    assert_eq!(clock.time_left(true, start + chrono::Duration::seconds(45)), 52000);
+}
+
+#[test]
+fn per_move_clock_should_start_over_each_move() {
+    let start = chrono::Utc::now();
+    let mut clock = Clock::new(TimeControl::PerMove, 10, 0).unwrap();
+
+    assert!(clock.on_move(true, true, start, start));
+    assert!(clock.on_move(false, true, start + chrono::Duration::seconds(9), start + chrono::Duration::seconds(9)));
+    assert_eq!(clock.opponent_time, 10000);
+    assert_eq!(clock.time_left(true, start + chrono::Duration::seconds(12)), 7000);
+}
+
+#[test]
+fn clock_should_fall_when_time_runs_out() {
+    let start = chrono::Utc::now();
+    let mut clock = Clock::new(TimeControl::Fischer, 60, 2).unwrap();
+
+    assert!(clock.on_move(true, true, start, start));
+    assert!(!clock.on_move(false, true, start + chrono::Duration::seconds(61), start + chrono::Duration::seconds(61)));
+    assert_eq!(clock.opponent_time, 0);
+    assert!(Clock::new(TimeControl::None, 60, 2).is_none());
+}
+
+#[test]
+fn uncharged_move_should_only_restart_turn() {
+    let start = chrono::Utc::now();
+    let mut clock = Clock::new(TimeControl::Fischer, 60, 2).unwrap();
+
+    assert!(clock.on_move(true, true, start, start));
+    assert!(clock.on_move(false, false, start + chrono::Duration::seconds(20), start + chrono::Duration::seconds(20)));
+    assert_eq!(clock.opponent_time, 60000);
+    assert_eq!(clock.turn_started, Some(start + chrono::Duration::seconds(20)));
+}
+
+#[test]
+fn verification_should_not_be_charged() {
+    let start = chrono::Utc::now();
+    let mut clock = Clock::new(TimeControl::Fischer, 60, 0).unwrap();
+
+    assert!(clock.on_move(true, true, start, start));
+    // move made after 10s, verified 15s later
+    assert!(clock.on_move(false, true, start + chrono::Duration::seconds(10), start + chrono::Duration::seconds(25)));
+    assert_eq!(clock.opponent_time, 50000);
+    assert_eq!(clock.time_left(true, start + chrono::Duration::seconds(35)), 50000);
+}
+
+#[test]
+fn resumed_clock_should_keep_saved_times() {
+    let now = chrono::Utc::now();
+    let fresh = Clock::new(TimeControl::Fischer, 60, 2).unwrap().resume(None, None, now);
+    assert_eq!(fresh.user_time, 60000);
+    // the first player's time only runs after the grace period
+    assert_eq!(fresh.time_left(true, now + chrono::Duration::seconds(30)), 60000);
+    assert_eq!(fresh.time_left(true, now + chrono::Duration::seconds(90)), 30000);
+
+    let resumed = Clock::new(TimeControl::Fischer, 60, 2).unwrap().resume(Some(41000), Some(12000), now);
+    assert_eq!(resumed.user_time, 41000);
+    assert_eq!(resumed.opponent_time, 12000);
+    assert_eq!(resumed.time_left(false, now + chrono::Duration::seconds(2)), 10000);
+}
+
+#[test]
+fn requests_should_name_game_in_camel_case() {
+    let request: WsPath = serde_json::from_str(r#"{"path": "/resign", "gameId": 3}"#).unwrap();
+    assert_eq!(request.game_id, Some(3));
+    let request: SubscribeRequest = serde_json::from_str(r#"{"path": "/subscribe", "gameId": 4}"#).unwrap();
+    assert_eq!(request.game_id, 4);
+    let request: WsPath = serde_json::from_str(r#"{"path": "/resign", "game_id": 3}"#).unwrap();
+    assert_eq!(request.game_id, None);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_room_events_should_arrive_in_order() {
+    let state = get_state();
+    clear(&state, 116).await;
+    let (tx, mut rx) = mpsc::channel(100);
+    let mut subscriptions = Subscriptions::new(state.clone(), Default::default(), tx, &"user".into());
+    subscriptions.add_room(116, Role::Player);
+
+    let mut tasks = Vec::new();
+    for i in 0..50 {
+        let state = state.clone();
+        tasks.push(tokio::spawn(async move {
+            let msg = Msg { msg: format!("{{\"event\":{}}}", i), room: 116, user: None, spectators_only: false };
+            events::send(&state, msg).await;
+        }));
+    }
+    for task in tasks {
+        task.await.unwrap();
+    }
+
+    for seq in 1..=50 {
+        let msg = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+        let msg: serde_json::Value = serde_json::from_str(msg.msg.as_str()).unwrap();
+        assert_eq!(msg["seq"], seq);
+    }
+    let logged = events::events_since(&state, 116, 0).await.unwrap();
+    let seqs: Vec<u64> = logged.iter().filter_map(|event| event["seq"].as_u64()).collect();
+    assert_eq!(seqs, (1..=50).collect::<Vec<u64>>());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn takeback_racing_move_should_keep_history() {
+    let state = get_state();
+    clear(&state, 117).await;
+    let mut game = get_game_model(117);
+    save_game(&state, &mut game).await.unwrap();
+    for _ in 0..2 {
+        let previous = game.clone();
+        game.ply += 1;
+        save_game_with_move(&state, &mut game, &previous).await.unwrap();
+    }
+
+    for _ in 0..20 {
+        let mut game = get_game(&state, 117).await.unwrap();
+        game.takeback_offer = Some("opponent".into());
+        save_game(&state, &mut game).await.unwrap();
+
+        let takeback = tokio::spawn({
+            let state = state.clone();
+            async move { answer_takeback(state, &"user".into(), 117, true).await }
+        });
+        let mov = tokio::spawn({
+            let state = state.clone();
+            async move {
+                loop {
+                    let mut game = get_game(&state, 117).await.unwrap();
+                    let previous = game.clone();
+                    game.ply += 1;
+                    match save_game_with_move(&state, &mut game, &previous).await {
+                        Err(StoreError::Conflict) => continue,
+                        result => return result,
+                    }
+                }
+            }
+        });
+        let _ = takeback.await.unwrap();
+        mov.await.unwrap().unwrap();
+
+        let game = get_game(&state, 117).await.unwrap();
+        assert_eq!(history_len(&state, 117).await.unwrap(), game.ply);
+    }
+}
+
+#[tokio::test]
+async fn redelivered_ai_move_should_be_applied_once() {
+    let state = get_state();
+    clear(&state, 118).await;
+    let mut game = get_game_model(118);
+    game.game_type = GameType::AI;
+    game.ai_type = AIType::Random;
+    save_game(&state, &mut game).await.unwrap();
+    let broker: Arc<dyn Broker> = Arc::new(MemoryBroker::default());
+    let handles = rabbit::listen(broker.clone(), state.clone()).await.unwrap();
+    game_engine::rabbit::listen(broker.clone()).await.unwrap();
+    let mut requests = state.txaimoves.subscribe();
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    broker.declare_queue("test.engine.queue", ENGINE_EXCHANGE, "engine").await.unwrap();
+    broker.consume("test.engine.queue", "test", handler(move |delivery| {
+        let tx = tx.clone();
+        async move {
+            let _ = tx.send(delivery.data.clone());
+            Ok(())
+        }
+    })).await.unwrap();
+
+    make_move(state.clone(), &"user".into(), 118, MoveRequest { mov: "11-15".into() }).await.unwrap();
+
+    let request = tokio::time::timeout(Duration::from_secs(5), requests.recv()).await.unwrap().unwrap();
+    let reply = loop {
+        let data = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+        let event = protocol::envelope::decode::<EngineEvent>(&data).unwrap().payload;
+        if event.ai {
+            assert_eq!(event.correlation_id, request.correlation_id);
+            break data;
+        }
+    };
+    let mut game = get_game(&state, 118).await.unwrap();
+    for _ in 0..50 {
+        if game.ply == 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        game = get_game(&state, 118).await.unwrap();
+    }
+    assert_eq!(game.ply, 2);
+    assert!(game.pending_move.is_none());
+
+    broker.publish(ENGINE_EXCHANGE, "engine", reply).await.unwrap();
+    rx.recv().await.unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let redelivered = get_game(&state, 118).await.unwrap();
+    assert_eq!(redelivered.ply, 2);
+    assert_eq!(redelivered.current_state, game.current_state);
+    assert!(redelivered.first_user_turn);
+    for task in handles {
+        task.abort();
+    }
+}
+
+#[tokio::test]
+async fn unanswered_ai_move_should_be_requested_again() {
+    let state = get_state();
+    clear(&state, 119).await;
+    let mut game = get_game_model(119);
+    game.game_type = GameType::AI;
+    game.ai_type = AIType::Random;
+    game.first_user_turn = false;
+    game.pending_move = Some(new_pending_ai_move(&state, &game).await.unwrap());
+    save_game(&state, &mut game).await.unwrap();
+    let mut rx = state.txaimoves.subscribe();
+
+    let pending = game.pending_move.unwrap();
+    check_pending_move(state.clone(), 119, (pending.id, 1)).await;
+
+    let event = rx.try_recv().unwrap();
+    assert_eq!(event.correlation_id, Some(pending.id));
+    assert_eq!(event.game_id, 119);
+    let game = get_game(&state, 119).await.unwrap();
+    assert!(!game.blocked);
+    assert_eq!(game.pending_move.unwrap().attempts, 2);
+}
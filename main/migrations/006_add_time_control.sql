@@ -0,0 +1,4 @@
+ALTER TABLE game
+ADD COLUMN time_control SMALLINT DEFAULT 0 NOT NULL,
+ADD COLUMN time_initial BIGINT DEFAULT 0 NOT NULL,
+ADD COLUMN time_increment BIGINT DEFAULT 0 NOT NULL;
@@ -0,0 +1,3 @@
+ALTER TABLE game
+ADD COLUMN allow_spectators BOOLEAN DEFAULT TRUE NOT NULL,
+ADD COLUMN spectator_chat BOOLEAN DEFAULT TRUE NOT NULL;
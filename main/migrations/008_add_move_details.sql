@@ -0,0 +1,14 @@
+ALTER TABLE move RENAME COLUMN last_move TO notation;
+ALTER TABLE move DROP COLUMN IF EXISTS timestamp;
+ALTER TABLE move
+ADD COLUMN ply INTEGER,
+ADD COLUMN mover_id BIGINT,
+ADD COLUMN created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW() NOT NULL,
+ADD CONSTRAINT fk_mover_id
+   FOREIGN KEY(mover_id)
+   REFERENCES account(id);
+UPDATE move m SET ply = numbered.ply
+FROM (SELECT id, ROW_NUMBER() OVER (PARTITION BY game_id ORDER BY id) AS ply FROM move) numbered
+WHERE m.id = numbered.id;
+ALTER TABLE move ALTER COLUMN ply SET NOT NULL;
+ALTER TABLE move ADD CONSTRAINT uc_move_game_ply UNIQUE (game_id, ply);
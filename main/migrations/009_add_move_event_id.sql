@@ -0,0 +1,3 @@
+ALTER TABLE move
+ADD COLUMN event_id VARCHAR(255),
+ADD CONSTRAINT uc_move_event_id UNIQUE (event_id);
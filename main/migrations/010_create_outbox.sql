@@ -0,0 +1,14 @@
+CREATE TABLE outbox (
+   id BIGINT GENERATED BY DEFAULT AS IDENTITY NOT NULL,
+
+   exchange VARCHAR(255) NOT NULL,
+   routing_key VARCHAR(255) NOT NULL,
+   payload BYTEA NOT NULL,
+
+   created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW() NOT NULL,
+   published_at TIMESTAMP WITH TIME ZONE,
+   attempts INTEGER DEFAULT 0 NOT NULL,
+   last_error TEXT,
+   CONSTRAINT pk_outbox PRIMARY KEY (id)
+);
+CREATE INDEX idx_outbox_pending ON outbox (id) WHERE published_at IS NULL;
@@ -0,0 +1,17 @@
+CREATE TABLE matchmaking_queue (
+   id BIGINT GENERATED BY DEFAULT AS IDENTITY NOT NULL,
+   user_id BIGINT NOT NULL,
+
+   ruleset SMALLINT NOT NULL,
+   time_control SMALLINT NOT NULL,
+   time_initial BIGINT NOT NULL,
+   time_increment BIGINT NOT NULL,
+   rating INTEGER NOT NULL,
+
+   joined_at TIMESTAMP WITH TIME ZONE DEFAULT NOW() NOT NULL,
+   CONSTRAINT pk_matchmaking_queue PRIMARY KEY (id),
+   CONSTRAINT uc_matchmaking_user UNIQUE (user_id),
+   CONSTRAINT fk_matchmaking_user_id
+      FOREIGN KEY(user_id)
+      REFERENCES account(id)
+);
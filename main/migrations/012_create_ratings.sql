@@ -0,0 +1,42 @@
+ALTER TABLE game
+ADD COLUMN rated BOOLEAN DEFAULT FALSE NOT NULL;
+
+CREATE TABLE rating (
+   user_id BIGINT NOT NULL,
+   ruleset SMALLINT NOT NULL,
+
+   rating DOUBLE PRECISION NOT NULL,
+   deviation DOUBLE PRECISION NOT NULL,
+   volatility DOUBLE PRECISION NOT NULL,
+   games INTEGER NOT NULL,
+
+   updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW() NOT NULL,
+   CONSTRAINT pk_rating PRIMARY KEY (user_id, ruleset),
+   CONSTRAINT fk_rating_user_id
+      FOREIGN KEY(user_id)
+      REFERENCES account(id)
+);
+
+CREATE TABLE rating_history (
+   id BIGINT GENERATED BY DEFAULT AS IDENTITY NOT NULL,
+   user_id BIGINT NOT NULL,
+   game_id BIGINT NOT NULL,
+   ruleset SMALLINT NOT NULL,
+
+   rating DOUBLE PRECISION NOT NULL,
+   deviation DOUBLE PRECISION NOT NULL,
+   volatility DOUBLE PRECISION NOT NULL,
+   change DOUBLE PRECISION NOT NULL,
+
+   created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW() NOT NULL,
+   CONSTRAINT pk_rating_history PRIMARY KEY (id),
+   CONSTRAINT uc_rating_history_game_user UNIQUE (game_id, user_id),
+   CONSTRAINT fk_rating_history_user_id
+      FOREIGN KEY(user_id)
+      REFERENCES account(id),
+   CONSTRAINT fk_rating_history_game_id
+      FOREIGN KEY(game_id)
+      REFERENCES game(id)
+);
+
+CREATE INDEX idx_rating_history_user ON rating_history (user_id, created_at);
@@ -0,0 +1,8 @@
+ALTER TABLE game
+ADD COLUMN finished_at TIMESTAMP WITH TIME ZONE;
+UPDATE game g SET finished_at = COALESCE((SELECT MAX(m.created_at) FROM move m WHERE m.game_id = g.id), NOW())
+WHERE g.status > 0;
+
+CREATE INDEX idx_game_user_finished ON game (user_id, finished_at) WHERE status > 0;
+CREATE INDEX idx_game_opponent_finished ON game (opponent_id, finished_at) WHERE status > 0;
+CREATE INDEX idx_rating_leaderboard ON rating (ruleset, rating DESC);
@@ -0,0 +1,57 @@
+CREATE TABLE tournament (
+   id BIGINT GENERATED BY DEFAULT AS IDENTITY NOT NULL,
+   name VARCHAR(255) NOT NULL,
+   creator_id BIGINT NOT NULL,
+
+   ruleset SMALLINT NOT NULL,
+   time_control SMALLINT NOT NULL,
+   time_initial BIGINT NOT NULL,
+   time_increment BIGINT NOT NULL,
+   pairing SMALLINT NOT NULL,
+   rated BOOLEAN NOT NULL,
+
+   status SMALLINT NOT NULL,
+   rounds INTEGER NOT NULL,
+   current_round INTEGER DEFAULT 0 NOT NULL,
+
+   created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW() NOT NULL,
+   CONSTRAINT pk_tournament PRIMARY KEY (id),
+   CONSTRAINT fk_tournament_creator_id
+      FOREIGN KEY(creator_id)
+      REFERENCES account(id)
+);
+
+CREATE TABLE tournament_player (
+   tournament_id BIGINT NOT NULL,
+   user_id BIGINT NOT NULL,
+   registered_at TIMESTAMP WITH TIME ZONE DEFAULT NOW() NOT NULL,
+   CONSTRAINT pk_tournament_player PRIMARY KEY (tournament_id, user_id),
+   CONSTRAINT fk_tournament_player_tournament_id
+      FOREIGN KEY(tournament_id)
+      REFERENCES tournament(id),
+   CONSTRAINT fk_tournament_player_user_id
+      FOREIGN KEY(user_id)
+      REFERENCES account(id)
+);
+
+-- players left without an opponent in a round
+CREATE TABLE tournament_bye (
+   tournament_id BIGINT NOT NULL,
+   round INTEGER NOT NULL,
+   user_id BIGINT NOT NULL,
+   CONSTRAINT pk_tournament_bye PRIMARY KEY (tournament_id, round),
+   CONSTRAINT fk_tournament_bye_tournament_id
+      FOREIGN KEY(tournament_id)
+      REFERENCES tournament(id),
+   CONSTRAINT fk_tournament_bye_user_id
+      FOREIGN KEY(user_id)
+      REFERENCES account(id)
+);
+
+ALTER TABLE game
+ADD COLUMN tournament_id BIGINT,
+ADD COLUMN round INTEGER,
+ADD CONSTRAINT fk_game_tournament_id
+   FOREIGN KEY(tournament_id)
+   REFERENCES tournament(id);
+CREATE INDEX idx_game_tournament ON game (tournament_id, round) WHERE tournament_id IS NOT NULL;
@@ -0,0 +1,8 @@
+ALTER TABLE game
+ADD COLUMN rematch_of BIGINT,
+ADD CONSTRAINT fk_game_rematch_of
+   FOREIGN KEY(rematch_of)
+   REFERENCES game(id);
+
+-- a game has a single open or accepted rematch, declined ones may be offered again
+CREATE UNIQUE INDEX uc_game_rematch_of ON game (rematch_of) WHERE invitation IN (0, 1);
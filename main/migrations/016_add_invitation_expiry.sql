@@ -0,0 +1,7 @@
+-- invitation: 3 = expired, 4 = cancelled by the issuer
+ALTER TABLE game
+ADD COLUMN expires_at TIMESTAMP WITH TIME ZONE;
+UPDATE game SET expires_at = NOW() + INTERVAL '1 day'
+WHERE invitation = 0;
+
+CREATE INDEX idx_game_invitation_expiry ON game (expires_at) WHERE invitation = 0;
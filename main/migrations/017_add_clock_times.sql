@@ -0,0 +1,4 @@
+-- milliseconds left on the clocks, empty before the first move
+ALTER TABLE game
+ADD COLUMN user_time BIGINT,
+ADD COLUMN opponent_time BIGINT;
@@ -0,0 +1,3 @@
+-- time of the last takeback applied, redelivered takebacks are recognised by it
+ALTER TABLE game
+ADD COLUMN taken_back_at TIMESTAMP WITH TIME ZONE;
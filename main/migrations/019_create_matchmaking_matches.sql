@@ -0,0 +1,15 @@
+-- games found by matchmaking, kept for a while so a queued client can poll for its game
+CREATE TABLE matchmaking_match (
+   user_id BIGINT NOT NULL,
+   game_id BIGINT NOT NULL,
+   matched_at TIMESTAMP WITH TIME ZONE DEFAULT NOW() NOT NULL,
+   CONSTRAINT pk_matchmaking_match PRIMARY KEY (user_id),
+   CONSTRAINT fk_matchmaking_match_user_id
+      FOREIGN KEY(user_id)
+      REFERENCES account(id),
+   CONSTRAINT fk_matchmaking_match_game_id
+      FOREIGN KEY(game_id)
+      REFERENCES game(id)
+);
+
+CREATE INDEX idx_matchmaking_queue_joined ON matchmaking_queue (joined_at);
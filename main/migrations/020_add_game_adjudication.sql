@@ -0,0 +1,3 @@
+-- results set by a tournament creator, e.g. for a no-show; moves sent after them are not saved
+ALTER TABLE game
+ADD COLUMN adjudicated BOOLEAN DEFAULT FALSE NOT NULL;
@@ -0,0 +1,2 @@
+-- published messages are deleted after a while, this finds the old ones
+CREATE INDEX idx_outbox_published ON outbox (published_at) WHERE published_at IS NOT NULL;
@@ -0,0 +1,23 @@
+// Verifies and repairs saved games against a replay of their moves, see game/replay.rs.
+//
+// usage: replay verify [game id]
+//        replay repair <game id>
+//   e.g. cargo run -p main --bin replay -- verify 42
+//
+// The database is taken from the configuration of the server.
+
+use std::env;
+
+use main::{config::get_config, connect, game::replay::run};
+
+#[tokio::main]
+async fn main() {
+    let config = get_config();
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() {
+        return eprintln!("usage: replay verify [game id]\n       replay repair <game id>");
+    }
+
+    let pool = connect(&config.db).await;
+    run(&pool, args).await;
+}
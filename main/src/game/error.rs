@@ -1,5 +1,7 @@
 use axum::{http::StatusCode, response::{IntoResponse, Response}};
 
+use crate::outbox::error::OutboxError;
+
 #[derive(Debug)]
 pub enum GameError {
     Unknown,
@@ -7,6 +9,14 @@ pub enum GameError {
     NotOwner,
     AlreadyRejected,
     AlreadyAccepted,
+    WrongTimeControl,
+    RatedAIGame,
+    NotPlayer,
+    NotFinished,
+    NoRematch,
+    AlreadyExpired,
+    AlreadyCancelled,
+    AlreadyAnswered,
 }
 
 impl From<sqlx::Error> for GameError {
@@ -15,6 +25,21 @@ impl From<sqlx::Error> for GameError {
     }
 }
 
+impl From<MoveError> for GameError {
+    fn from(error: MoveError) -> Self {
+        match error {
+            MoveError::NoGame => GameError::NotFound,
+            _ => GameError::Unknown,
+        }
+    }
+}
+
+impl From<OutboxError> for GameError {
+    fn from(_error: OutboxError) -> Self {
+        return GameError::Unknown
+    }
+}
+
 impl IntoResponse for GameError {
     fn into_response(self) -> Response {
         // TODO
@@ -24,6 +49,14 @@ impl IntoResponse for GameError {
             GameError::NotOwner => (StatusCode::FORBIDDEN, "You cannot change this request!"),
             GameError::AlreadyRejected => (StatusCode::BAD_REQUEST, "Request already rejected!"),
             GameError::AlreadyAccepted => (StatusCode::BAD_REQUEST, "Request already acccepted!"),
+            GameError::WrongTimeControl => (StatusCode::BAD_REQUEST, "Time control must be positive!"),
+            GameError::RatedAIGame => (StatusCode::BAD_REQUEST, "Games against AI cannot be rated!"),
+            GameError::NotPlayer => (StatusCode::FORBIDDEN, "You are not playing this game!"),
+            GameError::NotFinished => (StatusCode::BAD_REQUEST, "Game is not finished yet!"),
+            GameError::NoRematch => (StatusCode::BAD_REQUEST, "No rematch offer to answer!"),
+            GameError::AlreadyExpired => (StatusCode::BAD_REQUEST, "Request already expired!"),
+            GameError::AlreadyCancelled => (StatusCode::BAD_REQUEST, "Request already cancelled!"),
+            GameError::AlreadyAnswered => (StatusCode::BAD_REQUEST, "Request already answered!"),
         }
         .into_response()
     }
@@ -33,6 +66,8 @@ impl IntoResponse for GameError {
 pub enum MoveError {
     Unknown,
     NoGame,
+    Duplicate,
+    OutOfOrder,
 }
 
 impl From<sqlx::Error> for MoveError {
@@ -41,16 +76,33 @@ impl From<sqlx::Error> for MoveError {
         if err.contains("fk_game_id") {
             return MoveError::NoGame
         }
+        if err.contains("uc_move_event_id") {
+            return MoveError::Duplicate
+        }
+        if err.contains("uc_move_game_ply") {
+            return MoveError::OutOfOrder
+        }
         return MoveError::Unknown
     }
 }
 
+impl From<GameError> for MoveError {
+    fn from(error: GameError) -> Self {
+        match error {
+            GameError::NotFound => MoveError::NoGame,
+            _ => MoveError::Unknown,
+        }
+    }
+}
+
 impl IntoResponse for MoveError {
     fn into_response(self) -> Response {
         // TODO
         match self {
             MoveError::Unknown => (StatusCode::INTERNAL_SERVER_ERROR, "Database error!"),
             MoveError::NoGame => (StatusCode::NOT_FOUND, "Game not found!"),
+            MoveError::Duplicate => (StatusCode::CONFLICT, "Move already saved!"),
+            MoveError::OutOfOrder => (StatusCode::CONFLICT, "Move out of order!"),
         }
         .into_response()
     }
@@ -0,0 +1,99 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use protocol::game::RematchStatus;
+use sqlx::PgPool;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info};
+
+use crate::{game::{error::GameError, rematch::publish_rematch, repository::{change_invitation_status, expire_invitations, get_game, lock_game, GameModel, InvitationStatus}}, AppState};
+
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+// time the opponent has to answer an invitation
+const INVITATION_TTL_HOURS: i64 = 24;
+
+pub fn invitation_expiry(invitation: InvitationStatus) -> Option<DateTime<Utc>> {
+    match invitation {
+        InvitationStatus::Issued => Some(Utc::now() + chrono::Duration::hours(INVITATION_TTL_HOURS)),
+        _ => None,
+    }
+}
+
+pub fn start_sweeper(state: Arc<AppState>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            match sweep_invitations(&state.db).await {
+                Ok(games) if games.is_empty() => {},
+                Ok(games) => {
+                    state.outbox.notify_one();
+                    debug!("Invitations to games {:?} expired", games)
+                },
+                Err(err) => error!("Expiring invitations failed: {:?}", err),
+            }
+        }
+    })
+}
+
+// expires invitations past their expiry, players of the original game see an expired rematch
+pub async fn sweep_invitations(db: &PgPool) -> Result<Vec<i64>, GameError> {
+    let mut tx = db.begin().await?;
+    let expired = expire_invitations(&mut *tx).await?;
+    for invitation in &expired {
+        if let Some(original_id) = invitation.rematch_of {
+            publish_rematch(&mut tx, &original_id, &invitation.id, &invitation.username, RematchStatus::Expired).await?;
+        }
+    }
+    tx.commit().await?;
+
+    Ok(expired.into_iter().map(|invitation| invitation.id).collect())
+}
+
+// invitation still waiting for an answer, those past expiry may not be swept yet
+pub fn check_issued(game: &GameModel) -> Result<(), GameError> {
+    match game.invitation {
+        InvitationStatus::Issued if game.expires_at.is_some_and(|at| at <= Utc::now()) => Err(GameError::AlreadyExpired),
+        InvitationStatus::Issued => Ok(()),
+        InvitationStatus::Accepted => Err(GameError::AlreadyAccepted),
+        InvitationStatus::Rejected => Err(GameError::AlreadyRejected),
+        InvitationStatus::Expired => Err(GameError::AlreadyExpired),
+        InvitationStatus::Cancelled => Err(GameError::AlreadyCancelled),
+    }
+}
+
+// accepts or rejects an invitation by its opponent, the row is locked so that it can't be
+// cancelled or swept meanwhile
+pub async fn answer_invitation(db: &PgPool, id: &i64, user_id: &i64, status: InvitationStatus) -> Result<(), GameError> {
+    let mut tx = db.begin().await?;
+    lock_game(&mut *tx, id).await?;
+    let game = get_game(&mut *tx, id).await?;
+    if game.opponent_id != Some(*user_id) {
+        return Err(GameError::NotOwner)
+    }
+    check_issued(&game)?;
+
+    change_invitation_status(&mut *tx, id, status).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+// withdraws an invitation by its issuer, players of the original game see a cancelled rematch
+pub async fn cancel_invitation(db: &PgPool, id: &i64, user_id: &i64, username: &String) -> Result<(), GameError> {
+    let mut tx = db.begin().await?;
+    lock_game(&mut *tx, id).await?;
+    let game = get_game(&mut *tx, id).await?;
+    if game.user_id != *user_id {
+        return Err(GameError::NotOwner)
+    }
+    check_issued(&game)?;
+
+    change_invitation_status(&mut *tx, id, InvitationStatus::Cancelled).await?;
+    if let Some(original_id) = game.rematch_of {
+        publish_rematch(&mut tx, &original_id, id, username, RematchStatus::Cancelled).await?;
+    }
+    tx.commit().await?;
+
+    info!("Invitation to game {} cancelled by {}", id, username);
+    Ok(())
+}
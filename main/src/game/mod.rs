@@ -1,10 +1,14 @@
 use serde::{Deserialize, Serialize};
 use validator::Validate;
+use protocol::game::RematchStatus;
 
 
 pub mod service;
 pub mod error;
 pub mod repository;
+pub mod replay;
+pub mod rematch;
+pub mod invitation;
 
 #[derive(Serialize, Deserialize)]
 pub enum GameType {
@@ -32,6 +36,21 @@ pub struct GameRequest {
     #[validate(required(message = "Rule set must be specified!"))]
     pub rules: Option<Rules>,
     pub ai_type: Option<AIType>,
+    pub time_control: Option<TimeControl>,
+    pub allow_spectators: Option<bool>,
+    pub spectator_chat: Option<bool>,
+    // casual unless set, games against AI are always casual
+    pub rated: Option<bool>,
+}
+
+// times are given in seconds
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TimeControl {
+    #[serde(rename_all = "camelCase")]
+    Fischer { initial: i64, increment: i64 },
+    #[serde(rename_all = "camelCase")]
+    PerMove { time: i64 },
 }
 
 #[derive(Serialize, Deserialize)]
@@ -40,6 +59,14 @@ pub struct NewGameResponse {
     pub game_id: i64,
 }
 
+// game offered as the rematch, it starts once status is Accepted
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RematchResponse {
+    pub game_id: i64,
+    pub status: RematchStatus,
+}
+
 #[derive(Serialize, Deserialize, Validate)]
 #[serde(rename_all = "camelCase")]
 pub struct AcceptRequest {
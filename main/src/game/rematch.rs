@@ -0,0 +1,106 @@
+use protocol::{envelope::encode, game::{RematchEvent, RematchStatus}};
+use sqlx::PgPool;
+use tracing::info;
+
+use crate::{game::{error::GameError, invitation::invitation_expiry, repository::{change_invitation_status, expire_rematch, get_game, get_rematch, lock_game, save_game, GameModel, GameStatus, GameType, InvitationStatus}}, outbox::repository::enqueue, rabbit::{PRODUCER, STATE_EXCHANGE}};
+
+// offers a rematch of the finished game or accepts the one offered by the other player,
+// the rematch is created by the player asking first, so colors are swapped for them
+pub async fn offer_rematch(db: &PgPool, id: &i64, user_id: &i64, username: &String) -> Result<(i64, RematchStatus), GameError> {
+    let mut tx = db.begin().await?;
+    lock_game(&mut *tx, id).await?;
+    let game = get_game(&mut *tx, id).await?;
+    check_player(&game, user_id)?;
+    if let Some(expired) = expire_rematch(&mut *tx, id).await? {
+        publish_rematch(&mut tx, id, &expired.id, &expired.username, RematchStatus::Expired).await?;
+    }
+
+    let (rematch_id, status) = match get_rematch(&mut *tx, id).await? {
+        Some(rematch) if rematch.invitation == InvitationStatus::Accepted => return Ok((rematch.id.unwrap_or_default(), RematchStatus::Accepted)),
+        Some(rematch) if rematch.user_id == *user_id => return Ok((rematch.id.unwrap_or_default(), RematchStatus::Offered)),
+        Some(rematch) => {
+            let rematch_id = rematch.id.unwrap_or_default();
+            change_invitation_status(&mut *tx, &rematch_id, InvitationStatus::Accepted).await?;
+            (rematch_id, RematchStatus::Accepted)
+        },
+        None => {
+            let was_white = (game.user_id == *user_id) == game.user_starts;
+            let opponent_id = match game.user_id == *user_id {
+                true => game.opponent_id,
+                false => Some(game.user_id),
+            };
+            let (invitation, status) = match game.game_type {
+                GameType::AI => (InvitationStatus::Accepted, RematchStatus::Accepted),
+                GameType::User => (InvitationStatus::Issued, RematchStatus::Offered),
+            };
+            let rematch_id = save_game(&mut *tx,
+                GameModel {
+                    user_id: *user_id,
+                    opponent_id,
+                    ruleset: game.ruleset,
+                    game_type: game.game_type,
+                    ai_type: game.ai_type,
+                    invitation,
+                    user_starts: !was_white,
+                    user_turn: !was_white,
+                    time_control: game.time_control,
+                    time_initial: game.time_initial,
+                    time_increment: game.time_increment,
+                    allow_spectators: game.allow_spectators,
+                    spectator_chat: game.spectator_chat,
+                    rated: game.rated,
+                    rematch_of: Some(*id),
+                    expires_at: invitation_expiry(invitation),
+                    ..Default::default()
+                }).await?;
+            (rematch_id, status)
+        },
+    };
+
+    publish_rematch(&mut tx, id, &rematch_id, username, status).await?;
+    tx.commit().await?;
+
+    info!("Rematch {} of game {} {:?} by {}", rematch_id, id, status, username);
+    Ok((rematch_id, status))
+}
+
+// declines the rematch offered by the other player
+pub async fn decline_rematch(db: &PgPool, id: &i64, user_id: &i64, username: &String) -> Result<(i64, RematchStatus), GameError> {
+    let mut tx = db.begin().await?;
+    lock_game(&mut *tx, id).await?;
+    let game = get_game(&mut *tx, id).await?;
+    check_player(&game, user_id)?;
+    if let Some(expired) = expire_rematch(&mut *tx, id).await? {
+        publish_rematch(&mut tx, id, &expired.id, &expired.username, RematchStatus::Expired).await?;
+    }
+
+    let rematch = match get_rematch(&mut *tx, id).await? {
+        Some(rematch) if rematch.invitation == InvitationStatus::Issued && rematch.user_id != *user_id => rematch,
+        _ => return Err(GameError::NoRematch),
+    };
+    let rematch_id = rematch.id.unwrap_or_default();
+    change_invitation_status(&mut *tx, &rematch_id, InvitationStatus::Rejected).await?;
+
+    publish_rematch(&mut tx, id, &rematch_id, username, RematchStatus::Declined).await?;
+    tx.commit().await?;
+
+    info!("Rematch {} of game {} declined by {}", rematch_id, id, username);
+    Ok((rematch_id, RematchStatus::Declined))
+}
+
+fn check_player(game: &GameModel, user_id: &i64) -> Result<(), GameError> {
+    if game.user_id != *user_id && game.opponent_id != Some(*user_id) {
+        return Err(GameError::NotPlayer)
+    }
+    if game.status == GameStatus::NotFinished {
+        return Err(GameError::NotFinished)
+    }
+    Ok(())
+}
+
+pub async fn publish_rematch(tx: &mut sqlx::Transaction<'_, sqlx::Postgres>, id: &i64, rematch_id: &i64, username: &str, status: RematchStatus) -> Result<(), GameError> {
+    let event = RematchEvent { game_id: *id as usize, rematch_id: *rematch_id as usize, player: username.into(), status };
+    let event = encode(event, PRODUCER, None);
+    enqueue(&mut **tx, STATE_EXCHANGE, "rematch", event).await?;
+    Ok(())
+}
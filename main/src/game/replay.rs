@@ -0,0 +1,211 @@
+// Rebuilds games from the initial position and their saved moves with the engine's rules,
+// and reports or repairs games whose saved state differs from the replay.
+//
+// usage: replay verify [game id]
+//        replay repair <game id>
+//
+// verify checks all games if no id is given. repair rewrites the game and the states of
+// its moves to the replayed ones; games with moves the rules reject are only reported,
+// as well as results of finished games the board doesn't decide, e.g. resignations.
+// Games adjudicated by a tournament creator are skipped.
+
+use std::fmt;
+
+use game_engine::replay::{replay, Position, INITIAL_STATE};
+use protocol::{Color, RuleSet};
+use sqlx::PgPool;
+
+use crate::game::{error::{GameError, MoveError}, repository::{self, get_game, get_game_ids, get_last_ply, get_moves, is_adjudicated, lock_game, update_game, update_move_state, GameModel, GameStatus}};
+
+#[derive(Debug, PartialEq)]
+pub enum Discrepancy {
+    IllegalMove { ply: i32, notation: String },
+    MoveState { ply: i32, stored: String, replayed: String },
+    CurrentState { stored: String, replayed: String },
+    UserTurn { stored: bool, replayed: bool },
+    NoncaptureMoves { stored: i64, replayed: i64 },
+    NonpromotingMoves { stored: i64, replayed: i64 },
+    Status { stored: GameStatus, replayed: GameStatus },
+}
+
+impl fmt::Display for Discrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Discrepancy::IllegalMove { ply, notation } => write!(f, "move {} ({}) is illegal", ply, notation),
+            Discrepancy::MoveState { ply, stored, replayed } => write!(f, "move {} left {}, replay gives {}", ply, stored, replayed),
+            Discrepancy::CurrentState { stored, replayed } => write!(f, "state is {}, replay gives {}", stored, replayed),
+            Discrepancy::UserTurn { stored, replayed } => write!(f, "user turn is {}, replay gives {}", stored, replayed),
+            Discrepancy::NoncaptureMoves { stored, replayed } => write!(f, "noncapture moves are {}, replay gives {}", stored, replayed),
+            Discrepancy::NonpromotingMoves { stored, replayed } => write!(f, "nonpromoting moves are {}, replay gives {}", stored, replayed),
+            Discrepancy::Status { stored, replayed } => write!(f, "status is {:?}, replay gives {:?}", stored, replayed),
+        }
+    }
+}
+
+pub struct Verification {
+    pub game_id: i64,
+    pub plies: i32,
+    pub discrepancies: Vec<Discrepancy>,
+    // result set by the tournament creator, the game isn't repaired
+    pub adjudicated: bool,
+    // game with the replayed values, none if the moves cannot be replayed
+    replayed: Option<GameModel>,
+}
+
+impl Verification {
+    pub fn is_consistent(&self) -> bool {
+        self.discrepancies.is_empty()
+    }
+}
+
+pub async fn verify_game(db: &PgPool, id: &i64) -> Result<Verification, GameError> {
+    let mut game = get_game(db, id).await?;
+    let moves = get_moves(db, id).await?;
+    let notation: Vec<String> = moves.iter().map(|mv| mv.notation.clone()).collect();
+    let ruleset = match game.ruleset {
+        repository::RuleSet::British => RuleSet::British,
+    };
+    let adjudicated = is_adjudicated(db, id).await?;
+    let mut verification = Verification { game_id: *id, plies: moves.len() as i32, discrepancies: Vec::new(), adjudicated, replayed: None };
+
+    let positions = match replay(ruleset, &notation) {
+        Ok(positions) => positions,
+        Err(err) => {
+            let ply = moves.get(err.ply - 1).map(|mv| mv.ply).unwrap_or(err.ply as i32);
+            verification.discrepancies.push(Discrepancy::IllegalMove { ply, notation: err.notation });
+            return Ok(verification)
+        },
+    };
+    for (mv, position) in moves.iter().zip(positions.iter()) {
+        if mv.current_state != position.current_state {
+            verification.discrepancies.push(Discrepancy::MoveState { ply: mv.ply, stored: mv.current_state.clone(), replayed: position.current_state.clone() });
+        }
+    }
+
+    let (current_state, noncaptures, nonpromotions) = match positions.last() {
+        Some(position) => (position.current_state.clone(), position.noncapture_moves as i64, position.nonpromoting_moves as i64),
+        None => (INITIAL_STATE.to_string(), 0, 0),
+    };
+    // white starts, and the user plays white when starting
+    let user_turn = game.user_starts == (positions.len() % 2 == 0);
+    if game.current_state != current_state {
+        verification.discrepancies.push(Discrepancy::CurrentState { stored: game.current_state.clone(), replayed: current_state.clone() });
+    }
+    if game.user_turn != user_turn {
+        verification.discrepancies.push(Discrepancy::UserTurn { stored: game.user_turn, replayed: user_turn });
+    }
+    if game.noncapture_moves != noncaptures {
+        verification.discrepancies.push(Discrepancy::NoncaptureMoves { stored: game.noncapture_moves, replayed: noncaptures });
+    }
+    if game.nonpromoting_moves != nonpromotions {
+        verification.discrepancies.push(Discrepancy::NonpromotingMoves { stored: game.nonpromoting_moves, replayed: nonpromotions });
+    }
+    let status = positions.last().and_then(|position| get_status(position, game.user_starts));
+    if let Some(status) = status {
+        if game.status != status {
+            verification.discrepancies.push(Discrepancy::Status { stored: game.status, replayed: status });
+        }
+        game.status = status;
+    }
+
+    game.current_state = current_state;
+    game.user_turn = user_turn;
+    game.noncapture_moves = noncaptures;
+    game.nonpromoting_moves = nonpromotions;
+    verification.replayed = Some(game);
+    Ok(verification)
+}
+
+// result decided on the board, seen from the user
+fn get_status(position: &Position, user_starts: bool) -> Option<GameStatus> {
+    let user_moved = (position.mover == Color::White) == user_starts;
+    match (position.won, position.drawn, user_moved) {
+        (true, _, true) => Some(GameStatus::Won),
+        (true, _, false) => Some(GameStatus::Lost),
+        (false, true, _) => Some(GameStatus::Drawn),
+        _ => None,
+    }
+}
+
+// fails with OutOfOrder if a move was saved since the verification, adjudicated games
+// are left as they are
+pub async fn repair_game(db: &PgPool, verification: Verification) -> Result<Verification, MoveError> {
+    let Some(game) = verification.replayed else {
+        return Ok(verification)
+    };
+    let mut tx = db.begin().await?;
+    lock_game(&mut *tx, &verification.game_id).await?;
+    if is_adjudicated(&mut *tx, &verification.game_id).await? {
+        return Ok(Verification { adjudicated: true, replayed: None, ..verification })
+    }
+    if get_last_ply(&mut *tx, &verification.game_id).await? != verification.plies {
+        return Err(MoveError::OutOfOrder)
+    }
+    for discrepancy in &verification.discrepancies {
+        if let Discrepancy::MoveState { ply, replayed, .. } = discrepancy {
+            update_move_state(&mut *tx, &verification.game_id, *ply, replayed).await?;
+        }
+    }
+    update_game(&mut *tx, game).await?;
+    tx.commit().await?;
+    Ok(Verification { replayed: None, ..verification })
+}
+
+pub async fn run(db: &PgPool, args: Vec<String>) {
+    let id: Option<i64> = args.get(1).and_then(|id| id.parse().ok());
+    match (args[0].as_str(), id) {
+        ("verify", Some(id)) => print_verification(verify_game(db, &id).await, id),
+        ("verify", None) => {
+            let ids = match get_game_ids(db).await {
+                Ok(ids) => ids,
+                Err(err) => return eprintln!("Failed to get games: {:?}", err),
+            };
+            let mut inconsistent = 0;
+            for id in &ids {
+                let verification = verify_game(db, id).await;
+                if !verification.as_ref().is_ok_and(|verification| verification.is_consistent()) {
+                    inconsistent += 1;
+                    print_verification(verification, *id);
+                }
+            }
+            println!("{} of {} games differ from their replay.", inconsistent, ids.len());
+        },
+        ("repair", Some(id)) => {
+            let verification = match verify_game(db, &id).await {
+                Ok(verification) => verification,
+                Err(err) => return eprintln!("Failed to verify game {}: {:?}", id, err),
+            };
+            if verification.is_consistent() {
+                return println!("Game {} is consistent.", id);
+            }
+            if verification.adjudicated {
+                return println!("Game {} was adjudicated, skipped.", id);
+            }
+            if verification.replayed.is_none() {
+                return print_verification(Ok(verification), id);
+            }
+            match repair_game(db, verification).await {
+                Ok(verification) if verification.adjudicated => println!("Game {} was adjudicated, skipped.", id),
+                Ok(verification) => {
+                    for discrepancy in &verification.discrepancies {
+                        println!("Repaired game {}: {}", id, discrepancy);
+                    }
+                },
+                Err(err) => eprintln!("Failed to repair game {}: {:?}", id, err),
+            }
+        },
+        _ => eprintln!("usage: replay verify [game id]\n       replay repair <game id>"),
+    }
+}
+
+fn print_verification(verification: Result<Verification, GameError>, id: i64) {
+    match verification {
+        Ok(verification) if verification.is_consistent() => println!("Game {} is consistent.", id),
+        Ok(verification) => {
+            for discrepancy in &verification.discrepancies {
+                println!("Game {}: {}", id, discrepancy);
+            }
+        },
+        Err(err) => eprintln!("Failed to verify game {}: {:?}", id, err),
+    }
+}
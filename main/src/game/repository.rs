@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use sqlx::{postgres::PgQueryResult, PgPool, Postgres};
+use sqlx::{postgres::PgQueryResult, Executor, PgPool, Postgres};
 use tracing::debug;
 use chrono;
 
@@ -42,7 +42,7 @@ pub async fn get_requests(db: &PgPool, user_id: &i64) -> Result<Vec<GameDetails>
                                           FROM game g 
                                           LEFT JOIN account a1 ON a1.id = g.user_id 
                                           LEFT JOIN account a2 ON a2.id = g.opponent_id 
-                                          WHERE g.opponent_id = $1 AND g.invitation = 0")
+                                          WHERE g.opponent_id = $1 AND g.invitation = 0 AND (g.expires_at IS NULL OR g.expires_at > NOW())")
         .bind(user_id)
         .fetch_all(db)
         .await
@@ -53,10 +53,58 @@ pub async fn get_requests(db: &PgPool, user_id: &i64) -> Result<Vec<GameDetails>
         })
 }
 
-pub async fn save_game(db: &PgPool, game: GameModel) -> Result<i64, GameError> {
+pub async fn get_sent_requests(db: &PgPool, user_id: &i64) -> Result<Vec<GameDetails>, GameError> {
+    sqlx::query_as::<Postgres, GameDetails>("SELECT g.*, a1.username AS user, a2.username AS opponent  
+                                          FROM game g 
+                                          LEFT JOIN account a1 ON a1.id = g.user_id 
+                                          LEFT JOIN account a2 ON a2.id = g.opponent_id 
+                                          WHERE g.user_id = $1 AND g.invitation = 0 AND (g.expires_at IS NULL OR g.expires_at > NOW())
+                                          ORDER BY g.expires_at")
+        .bind(user_id)
+        .fetch_all(db)
+        .await
+        .map_err(|err: sqlx::Error| { 
+            debug!("Cannot get sent requests from db!");
+            debug!("{}", err); 
+            GameError::from(err)
+        })
+}
+
+// rematch offer of the game past its expiry, so that a new one can be made
+pub async fn expire_rematch<'c, E: Executor<'c, Database = Postgres>>(db: E, id: &i64) -> Result<Option<ExpiredInvitation>, GameError> {
+    sqlx::query_as::<Postgres, ExpiredInvitation>("UPDATE game g SET invitation = 3
+                FROM account a
+                WHERE a.id = g.user_id AND g.rematch_of = $1 AND g.invitation = 0 AND g.expires_at <= NOW()
+                RETURNING g.id, g.rematch_of, a.username")
+        .bind(id)
+        .fetch_optional(db)
+        .await
+        .map_err(|err: sqlx::Error| { 
+            debug!("Cannot expire rematch in db!");
+            debug!("{}", err); 
+            GameError::from(err)
+        })
+}
+
+// marks invitations past their expiry, returns the expired games
+pub async fn expire_invitations<'c, E: Executor<'c, Database = Postgres>>(db: E) -> Result<Vec<ExpiredInvitation>, GameError> {
+    sqlx::query_as::<Postgres, ExpiredInvitation>("UPDATE game g SET invitation = 3
+                FROM account a
+                WHERE a.id = g.user_id AND g.invitation = 0 AND g.expires_at <= NOW()
+                RETURNING g.id, g.rematch_of, a.username")
+        .fetch_all(db)
+        .await
+        .map_err(|err: sqlx::Error| { 
+            debug!("Cannot expire invitations in db!");
+            debug!("{}", err); 
+            GameError::from(err)
+        })
+}
+
+pub async fn save_game<'c, E: Executor<'c, Database = Postgres>>(db: E, game: GameModel) -> Result<i64, GameError> {
     let result = sqlx::query_scalar("INSERT INTO game 
-                                    (user_id, opponent_id, invitation, game_type, ruleset, ai_type, status, current_state, user_starts, user_turn) 
-                                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) 
+                                    (user_id, opponent_id, invitation, game_type, ruleset, ai_type, status, current_state, user_starts, user_turn, time_control, time_initial, time_increment, allow_spectators, spectator_chat, rated, tournament_id, round, rematch_of, expires_at) 
+                                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20) 
                                     RETURNING id")
         .bind(&game.user_id)
         .bind(&game.opponent_id)
@@ -68,6 +116,16 @@ pub async fn save_game(db: &PgPool, game: GameModel) -> Result<i64, GameError> {
         .bind(&game.current_state)
         .bind(&game.user_starts)
         .bind(&game.user_turn)
+        .bind(&game.time_control)
+        .bind(&game.time_initial)
+        .bind(&game.time_increment)
+        .bind(&game.allow_spectators)
+        .bind(&game.spectator_chat)
+        .bind(&game.rated)
+        .bind(&game.tournament_id)
+        .bind(&game.round)
+        .bind(&game.rematch_of)
+        .bind(&game.expires_at)
         .fetch_one(db)
         .await
         .map_err(|err: sqlx::Error| { 
@@ -83,7 +141,7 @@ pub async fn save_game(db: &PgPool, game: GameModel) -> Result<i64, GameError> {
     }
 }
 
-pub async fn get_game(db: &PgPool, id: &i64) -> Result<GameModel, GameError> {
+pub async fn get_game<'c, E: Executor<'c, Database = Postgres>>(db: E, id: &i64) -> Result<GameModel, GameError> {
     let result = sqlx::query_as::<Postgres, GameModel>("SELECT * FROM game WHERE id = $1")
         .bind(id)
         .fetch_optional(db)
@@ -101,6 +159,17 @@ pub async fn get_game(db: &PgPool, id: &i64) -> Result<GameModel, GameError> {
     }
 }
 
+pub async fn get_game_ids(db: &PgPool) -> Result<Vec<i64>, GameError> {
+    sqlx::query_scalar("SELECT id FROM game ORDER BY id ASC")
+        .fetch_all(db)
+        .await
+        .map_err(|err: sqlx::Error| { 
+            debug!("Cannot get games from db!");
+            debug!("{}", err); 
+            GameError::from(err)
+        })
+}
+
 pub async fn get_game_details(db: &PgPool, id: &i64) -> Result<GameDetails, GameError> {
     let result = sqlx::query_as::<Postgres, GameDetails>("SELECT g.*, a1.username AS user, a2.username AS opponent  
                                           FROM game g 
@@ -123,8 +192,11 @@ pub async fn get_game_details(db: &PgPool, id: &i64) -> Result<GameDetails, Game
     }
 }
 
-pub async fn change_invitation_status(db: &PgPool, id: &i64, status: InvitationStatus) -> Result<PgQueryResult, GameError> {
-    sqlx::query("UPDATE game SET invitation = $1 WHERE id = $2")
+// only invitations still waiting for an answer change, so one answered, cancelled or
+// expired in the meantime isn't overwritten
+pub async fn change_invitation_status<'c, E: Executor<'c, Database = Postgres>>(db: E, id: &i64, status: InvitationStatus) -> Result<PgQueryResult, GameError> {
+    let result = sqlx::query("UPDATE game SET invitation = $1
+                WHERE id = $2 AND invitation = 0 AND (expires_at IS NULL OR expires_at > NOW())")
         .bind(status as i16)
         .bind(id)
         .execute(db)
@@ -133,14 +205,33 @@ pub async fn change_invitation_status(db: &PgPool, id: &i64, status: InvitationS
             debug!("Cannot save update to db!");
             debug!("{}", err); 
             GameError::from(err)
+        });
+
+    match result {
+        Ok(result) if result.rows_affected() == 0 => Err(GameError::AlreadyAnswered),
+        result => result,
+    }
+}
+
+// open or accepted rematch of the game
+pub async fn get_rematch<'c, E: Executor<'c, Database = Postgres>>(db: E, id: &i64) -> Result<Option<GameModel>, GameError> {
+    sqlx::query_as::<Postgres, GameModel>("SELECT * FROM game WHERE rematch_of = $1 AND invitation IN (0, 1)")
+        .bind(id)
+        .fetch_optional(db)
+        .await
+        .map_err(|err: sqlx::Error| { 
+            debug!("Cannot get rematch from db!");
+            debug!("{}", err); 
+            GameError::from(err)
         })
 }
 
-pub async fn get_moves(db: &PgPool, id: &i64) -> Result<Vec<MoveModel>, GameError> {
-    sqlx::query_as::<Postgres, MoveModel>("SELECT * 
-                                          FROM moves 
-                                          WHERE game_id = $1 
-                                          ORDER BY timestamp ASC")
+pub async fn get_moves(db: &PgPool, id: &i64) -> Result<Vec<MoveDetails>, GameError> {
+    sqlx::query_as::<Postgres, MoveDetails>("SELECT m.*, a.username AS mover 
+                                          FROM move m 
+                                          LEFT JOIN account a ON a.id = m.mover_id 
+                                          WHERE m.game_id = $1 
+                                          ORDER BY m.ply ASC")
         .bind(id)
         .fetch_all(db)
         .await
@@ -151,34 +242,47 @@ pub async fn get_moves(db: &PgPool, id: &i64) -> Result<Vec<MoveModel>, GameErro
         })
 }
 
-pub async fn update_game(db: &PgPool, game: GameModel) -> Result<PgQueryResult, GameError> {
-    sqlx::query("UPDATE game 
-                SET status = $2, current_state = $3, user_turn = $4, nonpromoting_moves = $5, noncapture_moves = $6  
-                WHERE id = $1")
+// adjudicated games keep their result, so they aren't found
+pub async fn update_game<'c, E: Executor<'c, Database = Postgres>>(db: E, game: GameModel) -> Result<PgQueryResult, GameError> {
+    let result = sqlx::query("UPDATE game 
+                SET status = $2, current_state = $3, user_turn = $4, nonpromoting_moves = $5, noncapture_moves = $6,
+                finished_at = CASE WHEN $2 = 0 THEN NULL ELSE COALESCE(finished_at, NOW()) END,
+                user_time = COALESCE($7, user_time), opponent_time = COALESCE($8, opponent_time)
+                WHERE id = $1 AND NOT adjudicated")
         .bind(&game.id)
         .bind(&game.status)
         .bind(&game.current_state)
         .bind(&game.user_turn)
         .bind(&game.nonpromoting_moves)
         .bind(&game.noncapture_moves)
+        .bind(&game.user_time)
+        .bind(&game.opponent_time)
         .execute(db)
         .await
         .map_err(|err: sqlx::Error| { 
             debug!("Cannot update game in db!");
             debug!("{}", err); 
             GameError::from(err)
-        })
+        });
+
+    match result {
+        Ok(result) if result.rows_affected() == 0 => Err(GameError::NotFound),
+        result => result,
+    }
 }
 
-pub async fn save_move(db: &PgPool, mv: MoveModel) -> Result<i64, MoveError> {
+// ply is numbered after the last saved move of the game
+pub async fn save_move<'c, E: Executor<'c, Database = Postgres>>(db: E, mv: MoveModel) -> Result<i32, MoveError> {
     let result = sqlx::query_scalar("INSERT INTO move 
-                                    (game_id, current_state, created_at, x, y) 
-                                    VALUES ($1, $2, $3, $4) 
-                                    RETURNING id")
+                                    (game_id, ply, mover_id, notation, current_state, created_at, event_id) 
+                                    SELECT $1, COALESCE(MAX(ply), 0) + 1, $2, $3, $4, $5, $6 FROM move WHERE game_id = $1 
+                                    RETURNING ply")
         .bind(&mv.game_id)
+        .bind(&mv.mover_id)
+        .bind(&mv.notation)
         .bind(&mv.current_state)
         .bind(&mv.created_at)
-        .bind(&mv.last_move)
+        .bind(&mv.event_id)
         .fetch_one(db)
         .await
         .map_err(|err: sqlx::Error| { 
@@ -189,11 +293,208 @@ pub async fn save_move(db: &PgPool, mv: MoveModel) -> Result<i64, MoveError> {
 
     match result {
         Ok(None) => Err(MoveError::Unknown),
-        Ok(Some(id)) => Ok(id),
+        Ok(Some(ply)) => Ok(ply),
         Err(err) => Err(err),
     }
 }
 
+// finishes an unfinished game with the given result, none is changed if it has already finished
+pub async fn adjudicate_game<'c, E: Executor<'c, Database = Postgres>>(db: E, id: &i64, status: GameStatus) -> Result<PgQueryResult, GameError> {
+    let result = sqlx::query("UPDATE game 
+                SET status = $2, adjudicated = TRUE, finished_at = NOW()
+                WHERE id = $1 AND status = 0")
+        .bind(id)
+        .bind(status)
+        .execute(db)
+        .await
+        .map_err(|err: sqlx::Error| { 
+            debug!("Cannot adjudicate game in db!");
+            debug!("{}", err); 
+            GameError::from(err)
+        });
+
+    match result {
+        Ok(result) if result.rows_affected() == 0 => Err(GameError::NotFound),
+        result => result,
+    }
+}
+
+pub async fn get_last_ply<'c, E: Executor<'c, Database = Postgres>>(db: E, game_id: &i64) -> Result<i32, MoveError> {
+    sqlx::query_scalar("SELECT COALESCE(MAX(ply), 0) FROM move WHERE game_id = $1")
+        .bind(game_id)
+        .fetch_one(db)
+        .await
+        .map_err(|err: sqlx::Error| { 
+            debug!("Cannot get last move from db!");
+            debug!("{}", err); 
+            MoveError::from(err)
+        })
+}
+
+pub async fn move_exists<'c, E: Executor<'c, Database = Postgres>>(db: E, event_id: &String) -> Result<bool, MoveError> {
+    sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM move WHERE event_id = $1)")
+        .bind(event_id)
+        .fetch_one(db)
+        .await
+        .map_err(|err: sqlx::Error| { 
+            debug!("Cannot check move in db!");
+            debug!("{}", err); 
+            MoveError::from(err)
+        })
+}
+
+// keeps updates of the same game in line until the transaction ends
+// result set by the tournament creator, replays don't change such games
+pub async fn is_adjudicated<'c, E: Executor<'c, Database = Postgres>>(db: E, game_id: &i64) -> Result<bool, GameError> {
+    sqlx::query_scalar("SELECT adjudicated FROM game WHERE id = $1")
+        .bind(game_id)
+        .fetch_optional(db)
+        .await
+        .map_err(|err: sqlx::Error| { 
+            debug!("Cannot get game from db!");
+            debug!("{}", err); 
+            GameError::from(err)
+        })?
+        .ok_or(GameError::NotFound)
+}
+
+pub async fn lock_game<'c, E: Executor<'c, Database = Postgres>>(db: E, game_id: &i64) -> Result<(), MoveError> {
+    let locked: Option<i64> = sqlx::query_scalar("SELECT id FROM game WHERE id = $1 FOR UPDATE")
+        .bind(game_id)
+        .fetch_optional(db)
+        .await
+        .map_err(|err: sqlx::Error| { 
+            debug!("Cannot lock game in db!");
+            debug!("{}", err); 
+            MoveError::from(err)
+        })?;
+    match locked {
+        Some(_) => Ok(()),
+        None => Err(MoveError::NoGame),
+    }
+}
+
+pub async fn update_move_state<'c, E: Executor<'c, Database = Postgres>>(db: E, game_id: &i64, ply: i32, current_state: &String) -> Result<PgQueryResult, MoveError> {
+    sqlx::query("UPDATE move SET current_state = $3 WHERE game_id = $1 AND ply = $2")
+        .bind(game_id)
+        .bind(ply)
+        .bind(current_state)
+        .execute(db)
+        .await
+        .map_err(|err: sqlx::Error| { 
+            debug!("Cannot update move in db!");
+            debug!("{}", err); 
+            MoveError::from(err)
+        })
+}
+
+// false when a takeback at the same time or later has already been applied
+pub async fn mark_taken_back<'c, E: Executor<'c, Database = Postgres>>(db: E, game_id: &i64, timestamp: chrono::DateTime<chrono::Utc>) -> Result<bool, MoveError> {
+    sqlx::query("UPDATE game SET taken_back_at = $2 
+                WHERE id = $1 AND (taken_back_at IS NULL OR taken_back_at < $2)")
+        .bind(game_id)
+        .bind(timestamp)
+        .execute(db)
+        .await
+        .map(|result| result.rows_affected() > 0)
+        .map_err(|err: sqlx::Error| { 
+            debug!("Cannot mark takeback in db!");
+            debug!("{}", err); 
+            MoveError::from(err)
+        })
+}
+
+pub async fn delete_moves_after<'c, E: Executor<'c, Database = Postgres>>(db: E, game_id: &i64, ply: i32) -> Result<PgQueryResult, MoveError> {
+    sqlx::query("DELETE FROM move WHERE game_id = $1 AND ply > $2")
+        .bind(game_id)
+        .bind(ply)
+        .execute(db)
+        .await
+        .map_err(|err: sqlx::Error| { 
+            debug!("Cannot delete moves from db!");
+            debug!("{}", err); 
+            MoveError::from(err)
+        })
+}
+
+pub async fn delete_last_moves<'c, E: Executor<'c, Database = Postgres>>(db: E, game_id: &i64, count: i64) -> Result<PgQueryResult, MoveError> {
+    sqlx::query("DELETE FROM move 
+                WHERE id IN (SELECT id FROM move WHERE game_id = $1 ORDER BY ply DESC LIMIT $2)")
+        .bind(game_id)
+        .bind(count)
+        .execute(db)
+        .await
+        .map_err(|err: sqlx::Error| { 
+            debug!("Cannot delete moves from db!");
+            debug!("{}", err); 
+            MoveError::from(err)
+        })
+}
+
+// game and its history are written in one transaction, so they cannot disagree;
+// the game row is locked first, which keeps ply numbers of concurrent moves apart.
+// A move already saved under its event id is a duplicate, and one that isn't next
+// after the saved moves is out of order. Ply 0 comes from updates without numbers.
+pub async fn update_game_with_move(db: &PgPool, game: GameModel, mv: MoveModel) -> Result<i32, MoveError> {
+    let mut tx = db.begin().await?;
+    lock_game(&mut *tx, &mv.game_id).await?;
+    if let Some(event_id) = &mv.event_id {
+        if move_exists(&mut *tx, event_id).await? {
+            return Err(MoveError::Duplicate)
+        }
+    }
+    if mv.ply != 0 && mv.ply != get_last_ply(&mut *tx, &mv.game_id).await? + 1 {
+        return Err(MoveError::OutOfOrder)
+    }
+    update_game(&mut *tx, game).await?;
+    let ply = save_move(&mut *tx, mv).await?;
+    tx.commit().await?;
+    Ok(ply)
+}
+
+// game changes without a move, e.g. resignation, apply once all moves before them are saved
+pub async fn update_game_at_ply(db: &PgPool, game: GameModel, ply: Option<i32>) -> Result<(), MoveError> {
+    let mut tx = db.begin().await?;
+    let game_id = game.id.unwrap_or_default();
+    lock_game(&mut *tx, &game_id).await?;
+    if let Some(ply) = ply {
+        if ply != get_last_ply(&mut *tx, &game_id).await? {
+            return Err(MoveError::OutOfOrder)
+        }
+    }
+    update_game(&mut *tx, game).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+// with the ply of the last remaining move, moves after it are removed, so applying
+// the same takeback again changes nothing
+// the taken back moves have to be saved first, a takeback older than the last one applied is a redelivery
+pub async fn update_game_with_takeback(db: &PgPool, game: GameModel, moves: i64, ply: Option<i32>, timestamp: chrono::DateTime<chrono::Utc>) -> Result<(), MoveError> {
+    let mut tx = db.begin().await?;
+    let game_id = game.id.unwrap_or_default();
+    lock_game(&mut *tx, &game_id).await?;
+    if !mark_taken_back(&mut *tx, &game_id, timestamp).await? {
+        return Err(MoveError::Duplicate)
+    }
+    match ply {
+        Some(ply) if ply as i64 + moves > get_last_ply(&mut *tx, &game_id).await? as i64 => return Err(MoveError::OutOfOrder),
+        Some(ply) => delete_moves_after(&mut *tx, &game_id, ply).await?,
+        None => delete_last_moves(&mut *tx, &game_id, moves).await?,
+    };
+    update_game(&mut *tx, game).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+// invitation swept past its expiry with the username of its issuer
+#[derive(sqlx::FromRow)]
+pub struct ExpiredInvitation {
+    pub id: i64,
+    pub rematch_of: Option<i64>,
+    pub username: String,
+}
+
 #[derive(Serialize, Deserialize, sqlx::Type, Clone, Copy)]
 #[repr(i16)]
 pub enum GameType {
@@ -215,6 +516,14 @@ pub enum AIType {
     Counting = 2,
 }
 
+#[derive(Serialize, Deserialize, PartialEq, sqlx::Type, Clone, Copy)]
+#[repr(i16)]
+pub enum TimeControl {
+    None = 0,
+    Fischer = 1,
+    PerMove = 2,
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, sqlx::Type, Clone, Copy)]
 #[sqlx(type_name = "smallint")]
 #[repr(i16)]
@@ -222,9 +531,11 @@ pub enum InvitationStatus {
     Issued = 0,
     Accepted = 1,
     Rejected = 2,
+    Expired = 3,
+    Cancelled = 4,
 }
 
-#[derive(Serialize, Deserialize, sqlx::Type, Clone, Copy)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, sqlx::Type, Clone, Copy)]
 #[repr(i16)]
 pub enum GameStatus {
     NotFinished = 0,
@@ -249,6 +560,18 @@ pub struct GameModel {
     pub opponent_id: Option<i64>,
     pub noncapture_moves: i64,
     pub nonpromoting_moves: i64,
+    pub time_control: TimeControl,
+    pub time_initial: i64,
+    pub time_increment: i64,
+    pub allow_spectators: bool,
+    pub spectator_chat: bool,
+    pub rated: bool,
+    pub tournament_id: Option<i64>,
+    pub round: Option<i32>,
+    pub rematch_of: Option<i64>,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub user_time: Option<i64>,
+    pub opponent_time: Option<i64>,
 }
 
 impl Default for GameModel {
@@ -267,6 +590,18 @@ impl Default for GameModel {
             opponent_id: None,
             noncapture_moves: 0,
             nonpromoting_moves: 0,
+            time_control: TimeControl::None,
+            time_initial: 0,
+            time_increment: 0,
+            allow_spectators: true,
+            spectator_chat: true,
+            rated: false,
+            tournament_id: None,
+            round: None,
+            rematch_of: None,
+            expires_at: None,
+            user_time: None,
+            opponent_time: None,
         } 
     } 
 }
@@ -289,30 +624,58 @@ pub struct GameDetails {
     pub opponent: Option<String>,
     pub noncapture_moves: i64,
     pub nonpromoting_moves: i64,
+    pub time_control: TimeControl,
+    pub time_initial: i64,
+    pub time_increment: i64,
+    pub allow_spectators: bool,
+    pub spectator_chat: bool,
+    pub rated: bool,
+    pub tournament_id: Option<i64>,
+    pub round: Option<i32>,
+    pub rematch_of: Option<i64>,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub user_time: Option<i64>,
+    pub opponent_time: Option<i64>,
 }
 
 #[derive(Serialize, Deserialize, sqlx::FromRow)]
 pub struct MoveModel {
-    pub id: Option<i32>,
-    pub last_move: String,
-    pub current_state: String,
-    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
-
+    pub id: Option<i64>,
     pub game_id: i64,
+    pub ply: i32,
+    pub mover_id: Option<i64>,
+    pub notation: String,
+    pub current_state: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub event_id: Option<String>,
 }
 
 impl Default for MoveModel {
     fn default() -> MoveModel {
         MoveModel { 
             id: None,
-            last_move: "".into(),
-            current_state: String::from("xxxxxxxxxxxx........oooooooooooo"),
             game_id: 0,
-            created_at: None,
+            ply: 0,
+            mover_id: None,
+            notation: "".into(),
+            current_state: String::from("xxxxxxxxxxxx........oooooooooooo"),
+            created_at: chrono::Utc::now(),
+            event_id: None,
         } 
     } 
 }
 
+// mover is empty for moves of the AI
+#[derive(Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct MoveDetails {
+    pub ply: i32,
+    pub mover: Option<String>,
+    pub notation: String,
+    pub current_state: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GameResponse {
@@ -330,6 +693,18 @@ pub struct GameResponse {
     pub user: String,
     pub opponent: String,
     pub current_state: Vec<Vec<Field>>,
+    pub time_control: TimeControl,
+    pub time_initial: i64,
+    pub time_increment: i64,
+    pub allow_spectators: bool,
+    pub spectator_chat: bool,
+    pub rated: bool,
+    pub tournament_id: Option<i64>,
+    pub round: Option<i32>,
+    pub rematch_of: Option<i64>,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub user_time: Option<i64>,
+    pub opponent_time: Option<i64>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -382,6 +757,18 @@ impl GameResponse {
                 None => "AI".into(),
             },
             current_state,
+            time_control: game.time_control,
+            time_initial: game.time_initial,
+            time_increment: game.time_increment,
+            allow_spectators: game.allow_spectators,
+            spectator_chat: game.spectator_chat,
+            rated: game.rated,
+            tournament_id: game.tournament_id,
+            round: game.round,
+            rematch_of: game.rematch_of,
+            expires_at: game.expires_at,
+            user_time: game.user_time,
+            opponent_time: game.opponent_time,
         }
     }
 }
@@ -4,9 +4,9 @@ use axum::{extract::{Path, State}, response::{IntoResponse, Response}, Json};
 use tracing::{debug, info};
 use rand::{rngs::StdRng, Rng, SeedableRng};
 
-use crate::{game::{self, error::GameError, repository::{change_invitation_status, get_finished_games, get_game, get_game_details, get_games, get_moves, get_requests, save_game, AIType, GameModel, GameResponse, GameType, InvitationStatus, RuleSet}, NewGameResponse}, security::UserData, user::repository::get_user, validation::ValidatedJson, AppState};
+use crate::{game::{self, error::GameError, invitation::{answer_invitation, cancel_invitation, check_issued, invitation_expiry}, rematch::{decline_rematch, offer_rematch}, repository::{get_finished_games, get_game, get_game_details, get_games, get_moves, get_requests, get_sent_requests, save_game, AIType, GameModel, GameResponse, GameType, InvitationStatus, RuleSet, TimeControl}, NewGameResponse}, security::UserData, user::repository::get_user, validation::ValidatedJson, AppState};
 
-use super::{AcceptRequest, GameRequest};
+use super::{AcceptRequest, GameRequest, RematchResponse};
 
 pub async fn games(State(state): State<Arc<AppState>>, user: UserData) -> Response {
     info!("List of active games requested…");
@@ -77,6 +77,29 @@ pub async fn requests(State(state): State<Arc<AppState>>, user: UserData) -> Res
     return Json(games).into_response()
 }
 
+pub async fn sent_requests(State(state): State<Arc<AppState>>, user: UserData) -> Response {
+    info!("List of sent game requests requested…");
+    let username = user.username;
+
+    debug!("Trying to get user {} from db…", username);
+    let query_result = get_user(&state.db, &username).await;
+
+    if let Err(err) = query_result {
+        return err.into_response()
+    }
+    let user = query_result.unwrap();
+
+    debug!("Trying to fetch game requests sent by user {} from db…", username);
+    let query_result = get_sent_requests(&state.db, &user.id).await;
+
+    if let Err(err) = query_result {
+        return err.into_response()
+    }
+    let games: Vec<GameResponse> = query_result.unwrap().iter().map(|game| GameResponse::from(game)).collect();
+
+    return Json(games).into_response()
+}
+
 pub async fn new_game(State(state): State<Arc<AppState>>, user: UserData, ValidatedJson(game): ValidatedJson<GameRequest>) -> Response {
     info!("Creating new game requested…");
     let username = user.username;
@@ -95,6 +118,14 @@ pub async fn new_game(State(state): State<Arc<AppState>>, user: UserData, Valida
             game::AIType::Counting => AIType::Counting,
         }
     };
+    let (time_control, time_initial, time_increment) = match get_time_control(game.time_control) {
+        Ok(time_control) => time_control,
+        Err(err) => return err.into_response(),
+    };
+    let rated = game.rated.unwrap_or(false);
+    if rated && matches!(game_type, GameType::AI) {
+        return GameError::RatedAIGame.into_response()
+    }
     let invitation = match game_type {
         GameType::AI => InvitationStatus::Accepted,
         GameType::User => InvitationStatus::Issued,
@@ -136,6 +167,13 @@ pub async fn new_game(State(state): State<Arc<AppState>>, user: UserData, Valida
             invitation,
             user_starts,
             user_turn: user_starts,
+            time_control,
+            time_initial,
+            time_increment,
+            allow_spectators: game.allow_spectators.unwrap_or(true),
+            spectator_chat: game.spectator_chat.unwrap_or(true),
+            rated,
+            expires_at: invitation_expiry(invitation),
             ..Default::default() 
         }).await;
 
@@ -149,6 +187,19 @@ pub async fn new_game(State(state): State<Arc<AppState>>, user: UserData, Valida
     return Json(NewGameResponse{game_id: id}).into_response()
 }
 
+// kind of the time control with its initial time and increment
+pub fn get_time_control(time_control: Option<game::TimeControl>) -> Result<(TimeControl, i64, i64), GameError> {
+    let (time_control, time_initial, time_increment) = match time_control {
+        None => (TimeControl::None, 0, 0),
+        Some(game::TimeControl::Fischer { initial, increment }) => (TimeControl::Fischer, initial, increment),
+        Some(game::TimeControl::PerMove { time }) => (TimeControl::PerMove, time, 0),
+    };
+    if time_initial < 0 || time_increment < 0 || (time_control != TimeControl::None && time_initial == 0) {
+        return Err(GameError::WrongTimeControl)
+    }
+    Ok((time_control, time_initial, time_increment))
+}
+
 pub async fn accept_request(State(state): State<Arc<AppState>>, user: UserData, Path(id): Path<i64>, ValidatedJson(request): ValidatedJson<AcceptRequest>) -> Response {
     info!("Creating new game requested…");
     let username = user.username;
@@ -178,16 +229,29 @@ pub async fn accept_request(State(state): State<Arc<AppState>>, user: UserData,
     if opponent_id != user.id {
         return GameError::NotOwner.into_response()
     }
-    if game.invitation != InvitationStatus::Issued {
-        return match game.invitation {
-            InvitationStatus::Accepted => GameError::AlreadyAccepted,
-            InvitationStatus::Rejected => GameError::AlreadyRejected,
-            InvitationStatus::Issued => unreachable!(),
-        }.into_response()
+    if let Err(err) = check_issued(&game) {
+        return err.into_response()
+    }
+    // answering a rematch offer goes through the finished game, so it's seen in its room
+    if let Some(original_id) = game.rematch_of {
+        debug!("Trying to answer rematch of game {}…", original_id);
+        let query_result = match status {
+            InvitationStatus::Accepted => offer_rematch(&state.db, &original_id, &user.id, &username).await,
+            _ => decline_rematch(&state.db, &original_id, &user.id, &username).await,
+        };
+
+        if let Err(err) = query_result {
+            return err.into_response()
+        }
+        state.outbox.notify_one();
+
+        info!("Rematch {} succesfully updated.", id);
+
+        return Json(NewGameResponse{game_id: id}).into_response()
     }
 
     debug!("Trying to update invitation status…");
-    let query_result = change_invitation_status(&state.db, &id, status).await;
+    let query_result = answer_invitation(&state.db, &id, &user.id, status).await;
 
     if let Err(err) = query_result {
         return err.into_response()
@@ -198,6 +262,55 @@ pub async fn accept_request(State(state): State<Arc<AppState>>, user: UserData,
     return Json(NewGameResponse{game_id: id}).into_response()
 }
 
+pub async fn cancel_request(State(state): State<Arc<AppState>>, user: UserData, Path(id): Path<i64>) -> Response {
+    info!("Cancelling game request requested…");
+    let username = user.username;
+
+    debug!("Trying to get user {} from db…", username);
+    let query_result = get_user(&state.db, &username).await;
+
+    if let Err(err) = query_result {
+        return err.into_response()
+    }
+    let user = query_result.unwrap();
+
+    debug!("Trying to cancel invitation to game {}…", id);
+    let query_result = cancel_invitation(&state.db, &id, &user.id, &username).await;
+
+    if let Err(err) = query_result {
+        return err.into_response()
+    }
+    state.outbox.notify_one();
+
+    info!("Game {} succesfully cancelled.", id);
+
+    return Json(NewGameResponse{game_id: id}).into_response()
+}
+
+pub async fn rematch(State(state): State<Arc<AppState>>, user: UserData, Path(id): Path<i64>) -> Response {
+    info!("Rematch requested…");
+    let username = user.username;
+
+    debug!("Trying to get user {} from db…", username);
+    let query_result = get_user(&state.db, &username).await;
+
+    if let Err(err) = query_result {
+        return err.into_response()
+    }
+    let user = query_result.unwrap();
+
+    debug!("Trying to offer rematch of game {}…", id);
+    let query_result = offer_rematch(&state.db, &id, &user.id, &username).await;
+
+    if let Err(err) = query_result {
+        return err.into_response()
+    }
+    let (game_id, status) = query_result.unwrap();
+    state.outbox.notify_one();
+
+    return Json(RematchResponse{game_id, status}).into_response()
+}
+
 pub async fn game(State(state): State<Arc<AppState>>, _user: UserData, Path(id): Path<i64>) -> Response {
     info!("Getting game requested…");
 
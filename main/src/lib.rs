@@ -0,0 +1,121 @@
+use std::sync::Arc;
+
+use axum::{routing::{delete, post, get}, Router};
+use sqlx::{postgres::PgPoolOptions, PgPool};
+use tokio::sync::Notify;
+use tracing::info;
+use serde::{Deserialize, Serialize};
+
+use crate::user::{service::{register, login, refresh_token}, AuthResponse};
+use crate::game::{invitation::start_sweeper, service::{games, archive, requests, sent_requests, new_game, accept_request, cancel_request, rematch, game, moves}};
+use crate::matchmaking::{pairing::start_matchmaker, service::{join, leave, status}};
+use crate::rating::service::profile;
+use crate::stats::service::{leaderboard, stats};
+use crate::tournament::service::{new_tournament, tournament, join_tournament, next_round, game_result};
+use crate::config::ConfigFin;
+use crate::rabbit::lapin_listen;
+use crate::outbox::start_cleaner;
+
+mod security;
+mod user;
+mod validation;
+pub mod game;
+pub mod config;
+mod rabbit;
+mod outbox;
+mod matchmaking;
+mod rating;
+mod stats;
+mod tournament;
+
+#[cfg(test)]
+mod test;
+
+pub async fn connect(db_url: &str) -> PgPool {
+    info!("Connecting to database…");
+    let pool = PgPoolOptions::new()
+        .max_connections(5)
+        .connect(db_url)
+        .await
+        .unwrap();
+    info!("Connection to database established.");
+    
+    info!("Applying migrations…");
+    sqlx::migrate!()
+        .run(&pool)
+        .await
+        .unwrap();
+    pool
+}
+
+pub async fn serve(pool: PgPool, config: ConfigFin) {
+    let state = AppState { db: pool, jwt: config.jwt_secret, outbox: Notify::new() };
+    let state = Arc::new(state);
+    let lapin_state = state.clone();
+    start_matchmaker(state.clone());
+    start_sweeper(state.clone());
+    start_cleaner(state.clone());
+
+    let mut cfg = deadpool_lapin::Config::default();
+    cfg.url = Some(config.rabbit.into());
+    let lapin_pool = cfg.create_pool(Some(deadpool_lapin::Runtime::Tokio1)).unwrap();
+    tokio::spawn(async move {lapin_listen(lapin_pool.clone(), lapin_state).await});
+
+    let app = get_router(state);
+
+    info!("Initializing router…");
+    let host = "0.0.0.0";
+    let port = config.port;
+    let listener = tokio::net::TcpListener::bind(format!("{}:{}", host, port))
+        .await
+        .unwrap();
+    info!("Router initialized. Listening on port {}.", port);
+
+    axum::serve(listener, app)
+        .await
+        .unwrap();
+}
+
+pub fn get_router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/register", post(register))
+        .route("/authenticate", post(login))
+        .route("/refresh", post(refresh_token))
+        .route("/game", get(games))
+        .route("/game/archive", get(archive))
+        .route("/game/request", get(requests))
+        .route("/game/request/sent", get(sent_requests))
+        .route("/game", post(new_game))
+        .route("/game/:id/request", post(accept_request))
+        .route("/game/:id/request", delete(cancel_request))
+        .route("/game/:id/rematch", post(rematch))
+        .route("/game/:id", get(game))
+        .route("/game/:id/history", get(moves))
+        .route("/matchmaking", post(join))
+        .route("/matchmaking", delete(leave))
+        .route("/matchmaking", get(status))
+        .route("/user/:username/ratings", get(profile))
+        .route("/user/:username/stats", get(stats))
+        .route("/leaderboard", get(leaderboard))
+        .route("/tournament", post(new_tournament))
+        .route("/tournament/:id", get(tournament))
+        .route("/tournament/:id/register", post(join_tournament))
+        .route("/tournament/:id/round", post(next_round))
+        .route("/tournament/:id/game/:game_id/result", post(game_result))
+        .with_state(state)
+}
+
+pub struct AppState {
+    db: PgPool,
+    jwt: String,
+    // notified after messages are committed to the outbox
+    outbox: Notify,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow)]
+#[allow(non_snake_case)]
+struct UserModel {
+    id: i64,
+    username: String,
+    password: String,
+}
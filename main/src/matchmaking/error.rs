@@ -0,0 +1,48 @@
+use axum::{http::StatusCode, response::{IntoResponse, Response}};
+
+use crate::{game::error::GameError, outbox::error::OutboxError, rating::error::RatingError};
+
+#[derive(Debug)]
+pub enum MatchmakingError {
+    Unknown,
+    NotQueued,
+    WrongTimeControl,
+}
+
+impl From<sqlx::Error> for MatchmakingError {
+    fn from(_error: sqlx::Error) -> Self {
+        return MatchmakingError::Unknown
+    }
+}
+
+impl From<GameError> for MatchmakingError {
+    fn from(error: GameError) -> Self {
+        match error {
+            GameError::WrongTimeControl => MatchmakingError::WrongTimeControl,
+            _ => MatchmakingError::Unknown,
+        }
+    }
+}
+
+impl From<OutboxError> for MatchmakingError {
+    fn from(_error: OutboxError) -> Self {
+        return MatchmakingError::Unknown
+    }
+}
+
+impl From<RatingError> for MatchmakingError {
+    fn from(_error: RatingError) -> Self {
+        return MatchmakingError::Unknown
+    }
+}
+
+impl IntoResponse for MatchmakingError {
+    fn into_response(self) -> Response {
+        match self {
+            MatchmakingError::Unknown => (StatusCode::INTERNAL_SERVER_ERROR, "Database error!"),
+            MatchmakingError::NotQueued => (StatusCode::NOT_FOUND, "You are not waiting for a game!"),
+            MatchmakingError::WrongTimeControl => (StatusCode::BAD_REQUEST, "Time control must be positive!"),
+        }
+        .into_response()
+    }
+}
@@ -0,0 +1,26 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::game::{Rules, TimeControl};
+
+pub mod service;
+pub mod error;
+pub mod repository;
+pub mod pairing;
+
+// players are paired with others asking for the same rules and time control
+#[derive(Serialize, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchRequest {
+    #[validate(required(message = "Rule set must be specified!"))]
+    pub rules: Option<Rules>,
+    pub time_control: Option<TimeControl>,
+}
+
+// game is empty while waiting, players are told about it over the game WebSocket
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchResponse {
+    pub queued: bool,
+    pub game_id: Option<i64>,
+}
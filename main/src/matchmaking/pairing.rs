@@ -0,0 +1,93 @@
+use std::{sync::Arc, time::Duration};
+
+use protocol::{envelope::encode, game::MatchEvent};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use sqlx::PgPool;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info};
+
+use crate::{game::repository::{save_game, AIType, GameModel, GameType, InvitationStatus}, matchmaking::{error::MatchmakingError, repository::{expire_entries, find_opponent, get_queue, lock_entry, remove_entries, save_match, RatingRange}}, outbox::repository::enqueue, rabbit::{PRODUCER, STATE_EXCHANGE}, AppState};
+
+const PAIRING_INTERVAL: Duration = Duration::from_secs(2);
+// rating difference allowed right after joining, widening by the second up to the maximum
+const RATING_RANGE: RatingRange = RatingRange { initial: 100, per_second: 10, max: 1000 };
+// players waiting longer are dropped from the queue, found games are kept as long for polling
+const ENTRY_TTL_MINUTES: i64 = 10;
+
+pub fn start_matchmaker(state: Arc<AppState>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PAIRING_INTERVAL);
+        loop {
+            interval.tick().await;
+            match pair_players(&state.db).await {
+                Ok(0) => {},
+                Ok(games) => {
+                    debug!("Matchmaking created {} games", games);
+                    state.outbox.notify_one();
+                },
+                Err(err) => error!("Matchmaking failed: {:?}", err),
+            }
+        }
+    })
+}
+
+// tries every waiting player, returns the number of created games, an entry that
+// fails is left for the next sweep so it doesn't hold up the others
+pub async fn pair_players(db: &PgPool) -> Result<usize, MatchmakingError> {
+    let cutoff = chrono::Utc::now() - chrono::Duration::minutes(ENTRY_TTL_MINUTES);
+    match expire_entries(db, &cutoff).await {
+        Ok(0) => {},
+        Ok(expired) => info!("Removed {} expired matchmaking entries", expired),
+        Err(err) => error!("Cannot expire matchmaking entries: {:?}", err),
+    }
+    let mut games = 0;
+    for id in get_queue(db).await? {
+        match try_pair(db, &id).await {
+            Ok(Some(_)) => games += 1,
+            Ok(None) => {},
+            Err(err) => error!("Cannot pair matchmaking entry {}: {:?}", id, err),
+        }
+    }
+    Ok(games)
+}
+
+// creates the game and the message for both players in one transaction, so a
+// player is never left out of the queue without a game or the other way round
+pub async fn try_pair(db: &PgPool, id: &i64) -> Result<Option<i64>, MatchmakingError> {
+    let mut tx = db.begin().await?;
+    let Some(entry) = lock_entry(&mut *tx, id).await? else {
+        return Ok(None)
+    };
+    let Some(opponent) = find_opponent(&mut *tx, &entry, &RATING_RANGE).await? else {
+        return Ok(None)
+    };
+
+    let seed = chrono::Utc::now().timestamp_micros() as u64;
+    let user_starts = StdRng::seed_from_u64(seed).gen_bool(0.5);
+    let game_id = save_game(&mut *tx,
+        GameModel {
+            user_id: entry.user_id,
+            opponent_id: Some(opponent.user_id),
+            ruleset: entry.ruleset,
+            game_type: GameType::User,
+            ai_type: AIType::None,
+            invitation: InvitationStatus::Accepted,
+            user_starts,
+            user_turn: user_starts,
+            time_control: entry.time_control,
+            time_initial: entry.time_initial,
+            time_increment: entry.time_increment,
+            rated: true,
+            ..Default::default()
+        }).await?;
+    remove_entries(&mut *tx, vec![entry.id, opponent.id]).await?;
+    save_match(&mut *tx, &game_id, vec![entry.user_id, opponent.user_id]).await?;
+
+    let event = MatchEvent { game_id: game_id as usize, user: entry.username.clone(), opponent: opponent.username.clone() };
+    let event = encode(event, PRODUCER, None);
+    enqueue(&mut *tx, STATE_EXCHANGE, "match", event).await?;
+    tx.commit().await?;
+
+    info!("Paired {} with {} in game {}", entry.username, opponent.username, game_id);
+    Ok(Some(game_id))
+}
@@ -0,0 +1,222 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{postgres::PgQueryResult, Executor, PgPool, Postgres};
+use tracing::debug;
+
+use crate::{game::repository::{RuleSet, TimeControl}, matchmaking::error::MatchmakingError};
+
+// joining again replaces the preferences and restarts the wait
+pub async fn join_queue(db: &PgPool, entry: QueueModel) -> Result<i64, MatchmakingError> {
+    sqlx::query_scalar("INSERT INTO matchmaking_queue
+                        (user_id, ruleset, time_control, time_initial, time_increment, rating)
+                        VALUES ($1, $2, $3, $4, $5, $6)
+                        ON CONFLICT (user_id) DO UPDATE
+                        SET ruleset = $2, time_control = $3, time_initial = $4, time_increment = $5, rating = $6, joined_at = NOW()
+                        RETURNING id")
+        .bind(&entry.user_id)
+        .bind(&entry.ruleset)
+        .bind(&entry.time_control)
+        .bind(&entry.time_initial)
+        .bind(&entry.time_increment)
+        .bind(&entry.rating)
+        .fetch_one(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot add user to matchmaking queue!");
+            debug!("{}", err);
+            MatchmakingError::from(err)
+        })
+}
+
+pub async fn leave_queue(db: &PgPool, user_id: &i64) -> Result<PgQueryResult, MatchmakingError> {
+    let result = sqlx::query("DELETE FROM matchmaking_queue WHERE user_id = $1")
+        .bind(user_id)
+        .execute(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot remove user from matchmaking queue!");
+            debug!("{}", err);
+            MatchmakingError::from(err)
+        });
+
+    match result {
+        Ok(result) if result.rows_affected() == 0 => Err(MatchmakingError::NotQueued),
+        result => result,
+    }
+}
+
+// longest waiting first
+pub async fn get_queue(db: &PgPool) -> Result<Vec<i64>, MatchmakingError> {
+    sqlx::query_scalar("SELECT id FROM matchmaking_queue ORDER BY joined_at ASC")
+        .fetch_all(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot get matchmaking queue from db!");
+            debug!("{}", err);
+            MatchmakingError::from(err)
+        })
+}
+
+// none if the entry is gone or another pairing holds it
+pub async fn lock_entry<'c, E: Executor<'c, Database = Postgres>>(db: E, id: &i64) -> Result<Option<QueueDetails>, MatchmakingError> {
+    sqlx::query_as::<Postgres, QueueDetails>("SELECT q.*, a.username
+                                             FROM matchmaking_queue q
+                                             JOIN account a ON a.id = q.user_id
+                                             WHERE q.id = $1
+                                             FOR UPDATE OF q SKIP LOCKED")
+        .bind(id)
+        .fetch_optional(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot lock matchmaking entry!");
+            debug!("{}", err);
+            MatchmakingError::from(err)
+        })
+}
+
+// closest rating with the same preferences, the allowed difference grows with the longer wait of the two
+pub async fn find_opponent<'c, E: Executor<'c, Database = Postgres>>(db: E, entry: &QueueDetails, range: &RatingRange) -> Result<Option<QueueDetails>, MatchmakingError> {
+    sqlx::query_as::<Postgres, QueueDetails>("SELECT q.*, a.username
+                                             FROM matchmaking_queue q
+                                             JOIN account a ON a.id = q.user_id
+                                             WHERE q.id <> $1 AND q.ruleset = $2 AND q.time_control = $3
+                                             AND q.time_initial = $4 AND q.time_increment = $5
+                                             AND ABS(q.rating - $6) <= LEAST($9, $7 + $8 * EXTRACT(EPOCH FROM NOW() - LEAST(q.joined_at, $10)))
+                                             ORDER BY ABS(q.rating - $6) ASC, q.joined_at ASC
+                                             LIMIT 1
+                                             FOR UPDATE OF q SKIP LOCKED")
+        .bind(&entry.id)
+        .bind(&entry.ruleset)
+        .bind(&entry.time_control)
+        .bind(&entry.time_initial)
+        .bind(&entry.time_increment)
+        .bind(&entry.rating)
+        .bind(&range.initial)
+        .bind(&range.per_second)
+        .bind(&range.max)
+        .bind(&entry.joined_at)
+        .fetch_optional(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot find opponent in matchmaking queue!");
+            debug!("{}", err);
+            MatchmakingError::from(err)
+        })
+}
+
+pub async fn remove_entries<'c, E: Executor<'c, Database = Postgres>>(db: E, ids: Vec<i64>) -> Result<PgQueryResult, MatchmakingError> {
+    sqlx::query("DELETE FROM matchmaking_queue WHERE id = ANY($1)")
+        .bind(ids)
+        .execute(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot remove entries from matchmaking queue!");
+            debug!("{}", err);
+            MatchmakingError::from(err)
+        })
+}
+
+// entries and matches older than the cutoff are dropped, returns the number of entries
+pub async fn expire_entries(db: &PgPool, cutoff: &chrono::DateTime<chrono::Utc>) -> Result<u64, MatchmakingError> {
+    sqlx::query("DELETE FROM matchmaking_match WHERE matched_at < $1")
+        .bind(cutoff)
+        .execute(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot expire matchmaking matches!");
+            debug!("{}", err);
+            MatchmakingError::from(err)
+        })?;
+    sqlx::query("DELETE FROM matchmaking_queue WHERE joined_at < $1")
+        .bind(cutoff)
+        .execute(db)
+        .await
+        .map(|result| result.rows_affected())
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot expire matchmaking entries!");
+            debug!("{}", err);
+            MatchmakingError::from(err)
+        })
+}
+
+// remembers the game for both players until they join again or it expires
+pub async fn save_match<'c, E: Executor<'c, Database = Postgres>>(db: E, game_id: &i64, user_ids: Vec<i64>) -> Result<PgQueryResult, MatchmakingError> {
+    sqlx::query("INSERT INTO matchmaking_match (user_id, game_id)
+                SELECT UNNEST($1::BIGINT[]), $2
+                ON CONFLICT (user_id) DO UPDATE
+                SET game_id = $2, matched_at = NOW()")
+        .bind(user_ids)
+        .bind(game_id)
+        .execute(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot save matchmaking match!");
+            debug!("{}", err);
+            MatchmakingError::from(err)
+        })
+}
+
+pub async fn forget_match(db: &PgPool, user_id: &i64) -> Result<PgQueryResult, MatchmakingError> {
+    sqlx::query("DELETE FROM matchmaking_match WHERE user_id = $1")
+        .bind(user_id)
+        .execute(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot remove matchmaking match!");
+            debug!("{}", err);
+            MatchmakingError::from(err)
+        })
+}
+
+pub async fn is_queued(db: &PgPool, user_id: &i64) -> Result<bool, MatchmakingError> {
+    sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM matchmaking_queue WHERE user_id = $1)")
+        .bind(user_id)
+        .fetch_one(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot get matchmaking entry from db!");
+            debug!("{}", err);
+            MatchmakingError::from(err)
+        })
+}
+
+pub async fn get_match(db: &PgPool, user_id: &i64) -> Result<Option<i64>, MatchmakingError> {
+    sqlx::query_scalar("SELECT game_id FROM matchmaking_match WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_optional(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot get matchmaking match from db!");
+            debug!("{}", err);
+            MatchmakingError::from(err)
+        })
+}
+
+pub struct RatingRange {
+    pub initial: i32,
+    pub per_second: i32,
+    pub max: i32,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow)]
+pub struct QueueModel {
+    pub id: Option<i64>,
+    pub user_id: i64,
+    pub ruleset: RuleSet,
+    pub time_control: TimeControl,
+    pub time_initial: i64,
+    pub time_increment: i64,
+    pub rating: i32,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow)]
+pub struct QueueDetails {
+    pub id: i64,
+    pub user_id: i64,
+    pub username: String,
+    pub ruleset: RuleSet,
+    pub time_control: TimeControl,
+    pub time_initial: i64,
+    pub time_increment: i64,
+    pub rating: i32,
+    pub joined_at: chrono::DateTime<chrono::Utc>,
+}
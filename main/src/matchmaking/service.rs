@@ -0,0 +1,118 @@
+use std::sync::Arc;
+
+use axum::{extract::State, response::{IntoResponse, Response}, Json};
+use tracing::{debug, info};
+
+use crate::{game::{self, repository::RuleSet, service::get_time_control}, matchmaking::{error::MatchmakingError, pairing::try_pair, repository::{forget_match, get_match, is_queued, join_queue, leave_queue, QueueModel}, MatchRequest, MatchResponse}, rating::{glicko::Rating, repository::get_rating}, security::UserData, user::repository::get_user, validation::ValidatedJson, AppState};
+
+pub async fn join(State(state): State<Arc<AppState>>, user: UserData, ValidatedJson(request): ValidatedJson<MatchRequest>) -> Response {
+    info!("Joining matchmaking requested…");
+    let username = user.username;
+    let ruleset = match request.rules.unwrap() {
+        game::Rules::British => RuleSet::British,
+    };
+    let (time_control, time_initial, time_increment) = match get_time_control(request.time_control) {
+        Ok(time_control) => time_control,
+        Err(err) => return MatchmakingError::from(err).into_response(),
+    };
+
+    debug!("Trying to get user {} from db…", username);
+    let query_result = get_user(&state.db, &username).await;
+
+    if let Err(err) = query_result {
+        return err.into_response()
+    }
+    let user = query_result.unwrap();
+
+    debug!("Trying to get rating of user {} from db…", username);
+    let query_result = get_rating(&state.db, &user.id, ruleset).await;
+
+    if let Err(err) = query_result {
+        return MatchmakingError::from(err).into_response()
+    }
+    let rating = query_result.unwrap().map_or(Rating::default(), |rating| rating.glicko());
+
+    debug!("Trying to remove previous match of user {}…", username);
+    let query_result = forget_match(&state.db, &user.id).await;
+
+    if let Err(err) = query_result {
+        return err.into_response()
+    }
+
+    debug!("Trying to add user {} to matchmaking queue…", username);
+    let entry = QueueModel { id: None, user_id: user.id, ruleset, time_control, time_initial, time_increment, rating: rating.rating.round() as i32 };
+    let query_result = join_queue(&state.db, entry).await;
+
+    if let Err(err) = query_result {
+        return err.into_response()
+    }
+    let id = query_result.unwrap();
+
+    debug!("Looking for opponent for user {}…", username);
+    let query_result = try_pair(&state.db, &id).await;
+
+    if let Err(err) = query_result {
+        return err.into_response()
+    }
+    let game_id = query_result.unwrap();
+    if game_id.is_some() {
+        state.outbox.notify_one();
+    }
+
+    return Json(MatchResponse { queued: game_id.is_none(), game_id }).into_response()
+}
+
+pub async fn leave(State(state): State<Arc<AppState>>, user: UserData) -> Response {
+    info!("Leaving matchmaking requested…");
+    let username = user.username;
+
+    debug!("Trying to get user {} from db…", username);
+    let query_result = get_user(&state.db, &username).await;
+
+    if let Err(err) = query_result {
+        return err.into_response()
+    }
+    let user = query_result.unwrap();
+
+    debug!("Trying to remove user {} from matchmaking queue…", username);
+    let query_result = leave_queue(&state.db, &user.id).await;
+
+    if let Err(err) = query_result {
+        return err.into_response()
+    }
+
+    return Json(MatchResponse { queued: false, game_id: None }).into_response()
+}
+
+// for clients that missed the match message, e.g. after reconnecting
+pub async fn status(State(state): State<Arc<AppState>>, user: UserData) -> Response {
+    info!("Matchmaking status requested…");
+    let username = user.username;
+
+    debug!("Trying to get user {} from db…", username);
+    let query_result = get_user(&state.db, &username).await;
+
+    if let Err(err) = query_result {
+        return err.into_response()
+    }
+    let user = query_result.unwrap();
+
+    debug!("Trying to get matchmaking entry of user {} from db…", username);
+    let query_result = is_queued(&state.db, &user.id).await;
+
+    if let Err(err) = query_result {
+        return err.into_response()
+    }
+    if query_result.unwrap() {
+        return Json(MatchResponse { queued: true, game_id: None }).into_response()
+    }
+
+    debug!("Trying to get match of user {} from db…", username);
+    let query_result = get_match(&state.db, &user.id).await;
+
+    match query_result {
+        Ok(Some(game_id)) => Json(MatchResponse { queued: false, game_id: Some(game_id) }).into_response(),
+        Ok(None) => MatchmakingError::NotQueued.into_response(),
+        Err(err) => err.into_response(),
+    }
+}
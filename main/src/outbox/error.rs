@@ -0,0 +1,11 @@
+#[derive(Debug)]
+pub enum OutboxError {
+    Unknown,
+    Unpublished,
+}
+
+impl From<sqlx::Error> for OutboxError {
+    fn from(_error: sqlx::Error) -> Self {
+        return OutboxError::Unknown
+    }
+}
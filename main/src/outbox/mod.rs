@@ -0,0 +1,83 @@
+// Messages are stored in the outbox table in the same transaction as the change they
+// announce, and the relay publishes them in order, so none are lost while RabbitMQ is down.
+
+use std::{sync::Arc, time::Duration};
+
+use protocol::broker::Broker;
+use sqlx::PgPool;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, warn};
+
+use crate::{outbox::{error::OutboxError, repository::{delete_published, get_pending, mark_failed, mark_published}}, AppState};
+
+pub mod error;
+pub mod repository;
+
+const BATCH_SIZE: i64 = 100;
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(3600);
+// published messages are kept this long for debugging, then deleted
+const RETENTION_HOURS: i64 = 24;
+
+// woken up by AppState::outbox after a commit, polls in case a notification was missed
+pub fn start_relay(broker: Arc<dyn Broker>, state: Arc<AppState>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut retry_interval = POLL_INTERVAL;
+        loop {
+            match relay_pending(&state.db, broker.as_ref()).await {
+                Ok(count) if count as i64 == BATCH_SIZE => continue,
+                Ok(_) => {
+                    retry_interval = POLL_INTERVAL;
+                    tokio::select! {
+                        _ = state.outbox.notified() => {},
+                        _ = tokio::time::sleep(POLL_INTERVAL) => {},
+                    }
+                },
+                Err(err) => {
+                    warn!("Cannot relay outbox messages, retrying in {:?}: {:?}", retry_interval, err);
+                    tokio::time::sleep(retry_interval).await;
+                    retry_interval = (retry_interval * 2).min(MAX_RETRY_INTERVAL);
+                },
+            }
+        }
+    })
+}
+
+// a message is marked published only once the broker confirmed it, and the relay stops at
+// the first one that fails, so the ones after it aren't published ahead of it
+pub async fn relay_pending(db: &PgPool, broker: &dyn Broker) -> Result<usize, OutboxError> {
+    let mut tx = db.begin().await?;
+    let messages = get_pending(&mut *tx, BATCH_SIZE).await?;
+    let mut published = 0;
+    for message in messages {
+        if let Err(err) = broker.publish(&message.exchange, &message.routing_key, message.payload).await {
+            warn!("Cannot publish outbox message {}: {}", message.id, err);
+            mark_failed(&mut *tx, &message.id, &err.to_string()).await?;
+            tx.commit().await?;
+            return Err(OutboxError::Unpublished)
+        }
+        mark_published(&mut *tx, &message.id).await?;
+        published += 1;
+    }
+    tx.commit().await?;
+    if published > 0 {
+        debug!("Published {} outbox messages", published);
+    }
+    Ok(published)
+}
+
+pub fn start_cleaner(state: Arc<AppState>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+        loop {
+            interval.tick().await;
+            let cutoff = chrono::Utc::now() - chrono::Duration::hours(RETENTION_HOURS);
+            match delete_published(&state.db, &cutoff).await {
+                Ok(0) => {},
+                Ok(deleted) => debug!("Deleted {} published outbox messages", deleted),
+                Err(err) => error!("Cannot delete published outbox messages: {:?}", err),
+            }
+        }
+    })
+}
@@ -0,0 +1,88 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{postgres::PgQueryResult, Executor, Postgres};
+use tracing::debug;
+
+use crate::outbox::error::OutboxError;
+
+// pass the transaction of the domain change, so the message is stored only if the change is
+pub async fn enqueue<'c, E: Executor<'c, Database = Postgres>>(db: E, exchange: &str, routing_key: &str, payload: Vec<u8>) -> Result<i64, OutboxError> {
+    sqlx::query_scalar("INSERT INTO outbox (exchange, routing_key, payload) VALUES ($1, $2, $3) RETURNING id")
+        .bind(exchange)
+        .bind(routing_key)
+        .bind(payload)
+        .fetch_one(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot add message to outbox!");
+            debug!("{}", err);
+            OutboxError::from(err)
+        })
+}
+
+// rows stay locked until the transaction ends, other relays skip them
+pub async fn get_pending<'c, E: Executor<'c, Database = Postgres>>(db: E, limit: i64) -> Result<Vec<OutboxModel>, OutboxError> {
+    sqlx::query_as::<Postgres, OutboxModel>("SELECT * FROM outbox
+                                            WHERE published_at IS NULL
+                                            ORDER BY id ASC
+                                            LIMIT $1
+                                            FOR UPDATE SKIP LOCKED")
+        .bind(limit)
+        .fetch_all(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot get messages from outbox!");
+            debug!("{}", err);
+            OutboxError::from(err)
+        })
+}
+
+pub async fn mark_published<'c, E: Executor<'c, Database = Postgres>>(db: E, id: &i64) -> Result<PgQueryResult, OutboxError> {
+    sqlx::query("UPDATE outbox SET published_at = NOW(), attempts = attempts + 1, last_error = NULL WHERE id = $1")
+        .bind(id)
+        .execute(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot mark outbox message as published!");
+            debug!("{}", err);
+            OutboxError::from(err)
+        })
+}
+
+pub async fn mark_failed<'c, E: Executor<'c, Database = Postgres>>(db: E, id: &i64, error: &String) -> Result<PgQueryResult, OutboxError> {
+    sqlx::query("UPDATE outbox SET attempts = attempts + 1, last_error = $2 WHERE id = $1")
+        .bind(id)
+        .bind(error)
+        .execute(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot mark outbox message as failed!");
+            debug!("{}", err);
+            OutboxError::from(err)
+        })
+}
+
+// unpublished messages are kept however old they are
+pub async fn delete_published<'c, E: Executor<'c, Database = Postgres>>(db: E, cutoff: &chrono::DateTime<chrono::Utc>) -> Result<u64, OutboxError> {
+    sqlx::query("DELETE FROM outbox WHERE published_at < $1")
+        .bind(cutoff)
+        .execute(db)
+        .await
+        .map(|result| result.rows_affected())
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot delete published outbox messages!");
+            debug!("{}", err);
+            OutboxError::from(err)
+        })
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow)]
+pub struct OutboxModel {
+    pub id: i64,
+    pub exchange: String,
+    pub routing_key: String,
+    pub payload: Vec<u8>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub published_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+}
@@ -1,134 +1,47 @@
 use std::sync::Arc;
 
-use lapin::{message::{Delivery, DeliveryResult}, options::BasicAckOptions, Channel};
-use ::serde::{Deserialize, Serialize};
-use tracing::{info, error};
-
-use crate::{game::repository::{self, get_game_details}, rabbit::STATE_EXCHANGE, AppState};
-
-use super::update_consumer::GameStatus;
-
-
-pub fn set_game_delegate(consumer: lapin::Consumer, channel: Channel, state: Arc<AppState>) {
-    consumer.set_delegate({
-        move |delivery: DeliveryResult| {
-            info!("New game message");
-            let channel = channel.clone();
-            let state = state.clone();
-            async move {
-                let channel = channel.clone();
-                let state = state.clone();
-                let delivery = match delivery {
-                    Ok(Some(delivery)) => delivery,
-                    Ok(None) => return,
-                    Err(error) => {
-                        error!("Failed to consume queue message {}", error);
-                        return;
-                    }
-                };
-
-                if let Ok(update) = get_event_from_message(&delivery) {
-                    process_message(update, state, channel).await;
-                }
-
-
-                delivery
-                    .ack(BasicAckOptions::default())
-                    .await
-                    .expect("Failed to acknowledge message");
-            }
+use protocol::{broker::{handler, Broker, BrokerError, Delivery, HandlerResult, Rejection}, envelope::{encode, Envelope}, game::{GameEvent, StateEvent}, queues::GAMES_QUEUE, AIType, GameStatus, GameType, RuleSet, TimeControl};
+use tracing::info;
+
+use crate::{game::{error::GameError, repository::{self, get_game_details, get_last_ply}}, outbox::repository::enqueue, rabbit::{PRODUCER, STATE_EXCHANGE}, AppState};
+
+pub async fn set_game_delegate(broker: Arc<dyn Broker>, state: Arc<AppState>) -> Result<(), BrokerError> {
+    broker.consume(GAMES_QUEUE, "games_main_consumer", handler(move |delivery| {
+        info!("New game message");
+        let state = state.clone();
+        async move {
+            let event = get_event_from_message(&delivery)?;
+            process_message(event.payload, event.correlation_id, state).await
         }
-    }
-    );
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct GameEvent {
-    game_id: i64,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct StateEvent {
-    game_id: i64,
-    error: bool,
-    error_message: Option<String>,
-
-    user: String,
-    opponent: String,
-    user_turn: bool,
-    user_starts: bool,
-    current_state: String,
-    game_type: GameType,
-    ruleset: RuleSet,
-    ai_type: AIType,
-    status: GameStatus,
-    noncapture_moves: i64,
-    nonpromoting_moves: i64,
+    })).await
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub enum GameType {
-    User, AI,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-pub enum RuleSet {
-    British,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-pub enum AIType {
-    None, Random, Counting,
-}
-
-impl Default for StateEvent {
-    fn default() -> StateEvent {
-        StateEvent { 
-            game_id: 0,
-            error: false,
-            error_message: None,
-            user: "".into(),
-            opponent: "AI".into(),
-            user_turn: true,
-            user_starts: true,
-            current_state: "".into(),
-            game_type: GameType::User,
-            ruleset: RuleSet::British,
-            ai_type: AIType::None,
-            status: GameStatus::NotFinished,
-            noncapture_moves: 0,
-            nonpromoting_moves: 0,
-        } 
-    } 
-}
-
-fn get_event_from_message(delivery: &Delivery) -> Result<GameEvent, ()> {
-    let message = std::str::from_utf8(&delivery.data).unwrap();
-    let message: GameEvent = match serde_json::from_str(message) {
-        Ok(msg) => msg,
-        Err(err) => {
-            error!("Failed to deserialize game event: {:?}", err);
-            return Err(());
-        }
-    };
+fn get_event_from_message(delivery: &Delivery) -> Result<Envelope<GameEvent>, String> {
+    let message: Envelope<GameEvent> = delivery.parse()?;
     info!("Received message: {:?}", &message);
     return Ok(message);
 }
 
-async fn process_message(message: GameEvent, state: Arc<AppState>, channel: Channel) {
-    let game = get_game_details(&state.db, &message.game_id).await;
+// a state with the wrong ply would get every later update rejected, so failures on the
+// database are retried, only a missing game is answered with an error
+async fn process_message(message: GameEvent, correlation_id: Option<String>, state: Arc<AppState>) -> HandlerResult {
+    let game = match get_game_details(&state.db, &(message.game_id as i64)).await {
+        Ok(game) => Some(game),
+        Err(GameError::NotFound) => None,
+        Err(err) => return Err(Rejection::Retry(format!("Cannot get game {}: {:?}", message.game_id, err))),
+    };
+    let ply = get_last_ply(&state.db, &(message.game_id as i64)).await
+        .map_err(|err| Rejection::Retry(format!("Cannot get last ply of game {}: {:?}", message.game_id, err)))?;
 
     let response = match game {
-        Err(_) => StateEvent { 
+        None => StateEvent { 
             game_id: message.game_id, 
             error: true,
             error_message: Some("".into()),
             ..Default::default()
         },
-        Ok(game) => StateEvent {
-            game_id: game.id,
+        Some(game) => StateEvent {
+            game_id: game.id as usize,
             user: game.user,
             opponent: match game.opponent {
                 None => "AI".into(),
@@ -155,23 +68,28 @@ async fn process_message(message: GameEvent, state: Arc<AppState>, channel: Chan
                 repository::GameStatus::Won => GameStatus::Won,
                 repository::GameStatus::Drawn => GameStatus::Drawn,
             },
-            nonpromoting_moves: game.nonpromoting_moves,
-            noncapture_moves: game.noncapture_moves,
+            nonpromoting_moves: game.nonpromoting_moves as usize,
+            noncapture_moves: game.noncapture_moves as usize,
+            time_control: match game.time_control {
+                repository::TimeControl::None => TimeControl::None,
+                repository::TimeControl::Fischer => TimeControl::Fischer,
+                repository::TimeControl::PerMove => TimeControl::PerMove,
+            },
+            time_initial: game.time_initial,
+            time_increment: game.time_increment,
+            allow_spectators: game.allow_spectators,
+            spectator_chat: game.spectator_chat,
+            ply: ply as usize,
+            user_time: game.user_time,
+            opponent_time: game.opponent_time,
             ..Default::default()
         },
     };
     info!("Response: {:?}", &response);
-    let response = serde_json::to_string(&response).unwrap();
+    let response = encode(response, PRODUCER, correlation_id);
 
-    if let Err(err) = channel
-        .basic_publish(
-            STATE_EXCHANGE,
-            "state",
-            Default::default(),
-            response.into_bytes().as_slice(),
-            Default::default(),
-            )
-            .await {
-                error!("Failed to publish message to destination exchange: {:?}", err);
-            };
+    enqueue(&state.db, STATE_EXCHANGE, "state", response).await
+        .map_err(|err| Rejection::Retry(format!("Failed to add message to outbox: {:?}", err)))?;
+    state.outbox.notify_one();
+    Ok(())
 }
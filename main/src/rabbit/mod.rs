@@ -1,21 +1,23 @@
 use std::{sync::Arc, time::Duration};
 
-use deadpool_lapin::lapin::types::FieldTable;
-use lapin::{options::{BasicConsumeOptions, ExchangeDeclareOptions, QueueBindOptions, QueueDeclareOptions}, ExchangeKind};
+use protocol::{broker::{Broker, BrokerError, LapinBroker}, queues::{GAMES_QUEUE, REMATCHES_QUEUE, UPDATES_QUEUE}};
+use tokio::task::JoinHandle;
 use tracing::{debug, info};
 
-use crate::{rabbit::{game_consumer::set_game_delegate, update_consumer::set_update_delegate}, AppState};
+use crate::{outbox::start_relay, rabbit::{game_consumer::set_game_delegate, rematch_consumer::set_rematch_delegate, update_consumer::set_update_delegate}, AppState};
 
 mod game_consumer;
 mod update_consumer;
+mod rematch_consumer;
 
 pub const STATE_EXCHANGE: &str = "checkers.state.topic";
 
-const UPDATES_EXCHANGE: &str = "checkers.updates.topic";
-const UPDATES_QUEUE: &str = "checkers.updates.queue";
+pub const UPDATES_EXCHANGE: &str = "checkers.updates.topic";
 
-const GAMES_EXCHANGE: &str = "checkers.games.topic";
-const GAMES_QUEUE: &str = "checkers.games.queue";
+pub const GAMES_EXCHANGE: &str = "checkers.games.topic";
+
+// producer named in envelopes of published messages
+pub const PRODUCER: &str = "main";
 
 pub async fn lapin_listen(pool: deadpool_lapin::Pool, state: Arc<AppState>) {
     let mut retry_interval = tokio::time::interval(Duration::from_secs(5));
@@ -36,108 +38,34 @@ async fn init_lapin_listen(pool: deadpool_lapin::Pool, state: Arc<AppState>) ->
         e
     })?;
     let channel = rmq_con.create_channel().await?;
+    let broker: Arc<dyn Broker> = Arc::new(LapinBroker::new(channel).await?);
 
-    channel
-        .exchange_declare(
-            STATE_EXCHANGE,
-            ExchangeKind::Topic,
-            ExchangeDeclareOptions {
-                durable: true,
-                ..Default::default()
-            },
-            FieldTable::default(),
-            )
-        .await?;
-    debug!("Declared exchange {:?}", STATE_EXCHANGE);
-
-    channel
-        .exchange_declare(
-            GAMES_EXCHANGE,
-            ExchangeKind::Topic,
-            ExchangeDeclareOptions {
-                durable: true,
-                ..Default::default()
-            },
-            FieldTable::default(),
-            )
-        .await?;
-    debug!("Declared exchange {:?}", GAMES_EXCHANGE);
-
-    channel
-        .exchange_declare(
-            UPDATES_EXCHANGE,
-            ExchangeKind::Topic,
-            ExchangeDeclareOptions {
-                durable: true,
-                ..Default::default()
-            },
-            FieldTable::default(),
-            )
-        .await?;
-    debug!("Declared exchange {:?}", UPDATES_EXCHANGE);
-
-    channel.queue_declare(
-        UPDATES_QUEUE,
-        QueueDeclareOptions::default(),
-        Default::default(),
-        )
-        .await?;
-    debug!("Declared queue {:?}", UPDATES_QUEUE);
-
-    channel
-        .queue_bind(
-            UPDATES_QUEUE,
-            UPDATES_EXCHANGE,
-            "update",
-            QueueBindOptions::default(),
-            FieldTable::default(),
-            )
-        .await?;
-    debug!("Declared bind {:?} -> {:?}", UPDATES_EXCHANGE, UPDATES_QUEUE);
-
-    channel.queue_declare(
-        GAMES_QUEUE,
-        QueueDeclareOptions::default(),
-        Default::default(),
-        )
-        .await?;
-    debug!("Declared queue {:?}", GAMES_QUEUE);
-
-    channel
-        .queue_bind(
-            GAMES_QUEUE,
-            GAMES_EXCHANGE,
-            "game",
-            QueueBindOptions::default(),
-            FieldTable::default(),
-            )
-        .await?;
-    debug!("Declared bind {:?} -> {:?}", GAMES_EXCHANGE, GAMES_QUEUE);
-
-    let game_consumer = channel.basic_consume(
-        GAMES_QUEUE,
-        "games_main_consumer",
-        BasicConsumeOptions::default(),
-        FieldTable::default())
-        .await?;
-
-    let update_consumer = channel.basic_consume(
-        UPDATES_QUEUE,
-        "updates_main_consumer",
-        BasicConsumeOptions::default(),
-        FieldTable::default())
-        .await?;
-
-    debug!("Consumer connected, waiting for messages");
-    set_game_delegate(game_consumer, channel.clone(), state.clone());
-    set_update_delegate(update_consumer, state.clone());
+    let relay = listen(broker.clone(), state).await?;
     let mut test_interval = tokio::time::interval(Duration::from_secs(5));
     loop {
         test_interval.tick().await;
-        match channel.status().connected() {
+        match broker.is_connected() {
             false => break,
             true => {},
         }
     }
+    relay.abort();
     Ok(())
 }
+
+// returns the outbox relay publishing through the broker
+pub async fn listen(broker: Arc<dyn Broker>, state: Arc<AppState>) -> Result<JoinHandle<()>, BrokerError> {
+    broker.declare_exchange(STATE_EXCHANGE).await?;
+    broker.declare_exchange(GAMES_EXCHANGE).await?;
+    broker.declare_exchange(UPDATES_EXCHANGE).await?;
+    broker.declare_queue(UPDATES_QUEUE, UPDATES_EXCHANGE, "update").await?;
+    broker.declare_queue(UPDATES_QUEUE, UPDATES_EXCHANGE, "takeback").await?;
+    broker.declare_queue(GAMES_QUEUE, GAMES_EXCHANGE, "game").await?;
+    broker.declare_queue(REMATCHES_QUEUE, GAMES_EXCHANGE, "rematch").await?;
+
+    set_game_delegate(broker.clone(), state.clone()).await?;
+    set_update_delegate(broker.clone(), state.clone()).await?;
+    set_rematch_delegate(broker.clone(), state.clone()).await?;
+    debug!("Consumer connected, waiting for messages");
+    Ok(start_relay(broker, state))
+}
@@ -0,0 +1,51 @@
+use std::sync::Arc;
+
+use protocol::{broker::{handler, Broker, BrokerError, Delivery, HandlerResult, Rejection}, game::RematchRequestEvent, queues::REMATCHES_QUEUE};
+use tracing::{error, info};
+
+use crate::{game::{error::GameError, rematch::{decline_rematch, offer_rematch}}, user::{error::FetchUserError, repository::get_user}, AppState};
+
+pub async fn set_rematch_delegate(broker: Arc<dyn Broker>, state: Arc<AppState>) -> Result<(), BrokerError> {
+    broker.consume(REMATCHES_QUEUE, "rematches_main_consumer", handler(move |delivery| {
+        info!("New rematch message");
+        let state = state.clone();
+        async move {
+            let rematch = get_event_from_message(&delivery)?;
+            process_message(rematch, state).await
+        }
+    })).await
+}
+
+fn get_event_from_message(delivery: &Delivery) -> Result<RematchRequestEvent, String> {
+    let message: RematchRequestEvent = delivery.parse()?.payload;
+    info!("Received message: {:?}", &message);
+    return Ok(message);
+}
+
+// the result of the game may not be saved yet, as it comes through the updates queue, so
+// requests for unfinished games are retried, like those failing on the database; those
+// already answered or not allowed are only logged, the room gets no update for them
+async fn process_message(message: RematchRequestEvent, state: Arc<AppState>) -> HandlerResult {
+    let user = match get_user(&state.db, &message.player).await {
+        Ok(user) => user,
+        Err(FetchUserError::NoUser) => {
+            error!("Cannot find player {} asking for rematch", message.player);
+            return Ok(())
+        },
+        Err(err) => return Err(Rejection::Retry(format!("Cannot get player {} asking for rematch: {:?}", message.player, err))),
+    };
+    let game_id = message.game_id as i64;
+    let result = match message.accepted {
+        true => offer_rematch(&state.db, &game_id, &user.id, &user.username).await,
+        false => decline_rematch(&state.db, &game_id, &user.id, &user.username).await,
+    };
+    match result {
+        Ok(_) => state.outbox.notify_one(),
+        Err(GameError::NotFinished) => return Err(Rejection::Retry(format!("Game {} of rematch is not finished yet", message.game_id))),
+        Err(err @ (GameError::AlreadyAnswered | GameError::NotPlayer | GameError::NoRematch | GameError::NotFound)) => {
+            error!("Cannot answer rematch of game {}: {:?}", message.game_id, err)
+        },
+        Err(err) => return Err(Rejection::Retry(format!("Cannot answer rematch of game {}: {:?}", message.game_id, err))),
+    }
+    Ok(())
+}
@@ -1,98 +1,135 @@
 use std::sync::Arc;
 
-use lapin::{message::{Delivery, DeliveryResult}, options::BasicAckOptions};
-use serde::{Deserialize, Serialize};
-use tracing::{info, error};
+use protocol::{broker::{handler, Broker, BrokerError, Delivery, HandlerResult, Rejection}, envelope::Message, game::{TakebackEvent, UpdateEvent}, queues::UPDATES_QUEUE, GameStatus};
+use tracing::{debug, error, info};
 
-use crate::{game::repository::{self, get_game, save_move, update_game, GameModel, MoveModel}, AppState};
+use crate::{game::{error::{GameError, MoveError}, repository::{self, get_game, update_game_at_ply, update_game_with_move, update_game_with_takeback, GameModel, MoveModel}}, rating::{error::RatingError, update::rate_game}, AppState};
 
-pub fn set_update_delegate(consumer: lapin::Consumer, state: Arc<AppState>) {
-    consumer.set_delegate({
-        move |delivery: DeliveryResult| {
-            info!("New update message");
-            let state = state.clone();
-            async move {
-                let _state = state.clone();
-                let delivery = match delivery {
-                    Ok(Some(delivery)) => delivery,
-                    Ok(None) => return,
-                    Err(error) => {
-                        error!("Failed to consume queue message {}", error);
-                        return; 
-                    }
-                };
-
-                if let Ok(update) = get_event_from_message(&delivery) {
-                    process_message(update, state).await;
-                }
-
-                delivery
-                    .ack(BasicAckOptions::default())
-                    .await
-                    .expect("Failed to acknowledge message");
+pub async fn set_update_delegate(broker: Arc<dyn Broker>, state: Arc<AppState>) -> Result<(), BrokerError> {
+    broker.consume(UPDATES_QUEUE, "updates_main_consumer", handler(move |delivery| {
+        info!("New update message");
+        let state = state.clone();
+        async move {
+            // takebacks share the queue with moves, so they're applied in the order they were made
+            if delivery.message_type().as_deref() == Some(TakebackEvent::TYPE) {
+                let takeback = get_event_from_message::<TakebackEvent>(&delivery)?;
+                return process_takeback(takeback, state).await
             }
+            let update = get_event_from_message::<UpdateEvent>(&delivery)?;
+            process_message(update, state).await
         }
-    }
-    );
+    })).await
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct UpdateEvent {
-    game_id: i64,
-    status: GameStatus,
-    current_state: String,
-    user_turn: bool,
-    timestamp: chrono::DateTime<chrono::Utc>,
-    last_move: String,
-    noncapture_moves: i64,
-    nonpromoting_moves: i64,
+fn get_event_from_message<T: Message + std::fmt::Debug>(delivery: &Delivery) -> Result<T, String> {
+    let message: T = delivery.parse()?.payload;
+    info!("Received message: {:?}", &message);
+    return Ok(message);
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub enum GameStatus {
-    NotFinished, Won, Lost, Drawn,
+// redelivered updates are acknowledged without changes, out of order ones and those failing
+// on the database are retried
+async fn process_message(message: UpdateEvent, state: Arc<AppState>) -> HandlerResult {
+    let game = match get_game(&state.db, &(message.game_id as i64)).await {
+        Ok(game) => game,
+        Err(GameError::NotFound) => {
+            error!("Cannot save move for game {}: game not found", message.game_id);
+            return Ok(());
+        },
+        Err(err) => return Err(Rejection::Retry(format!("Cannot get game {}: {:?}", message.game_id, err))),
+    };
+    // turn has already passed to the other player
+    let mover_id = match message.user_turn {
+        true => game.opponent_id,
+        false => Some(game.user_id),
+    };
+    let game = GameModel {
+        id: game.id, 
+        status: match message.status {
+            GameStatus::NotFinished => repository::GameStatus::NotFinished,
+            GameStatus::Won => repository::GameStatus::Won,
+            GameStatus::Lost => repository::GameStatus::Lost,
+            GameStatus::Drawn => repository::GameStatus::Drawn,
+        },
+        current_state: message.current_state.clone(),
+        user_turn: message.user_turn,
+        noncapture_moves: message.noncapture_moves as i64,
+        nonpromoting_moves: message.nonpromoting_moves as i64,
+        user_time: message.user_time,
+        opponent_time: message.opponent_time,
+        ..Default::default()
+    };
+    // game ended without a move, e.g. by resignation
+    if message.last_move.is_empty() {
+        let result = update_game_at_ply(&state.db, game, message.ply.map(|ply| ply as i32)).await;
+        handle_result(result.map(|_| message.ply.unwrap_or_default() as i32), &message)?;
+        return rate_finished_game(&message, &state).await;
+    }
+    let mv = MoveModel {
+        game_id: message.game_id as i64,
+        ply: message.ply.unwrap_or_default() as i32,
+        mover_id,
+        notation: message.last_move.clone(),
+        current_state: message.current_state.clone(),
+        created_at: message.timestamp,
+        event_id: message.event_id.clone(),
+        ..Default::default()
+    };
+    let result = update_game_with_move(&state.db, game, mv).await;
+    handle_result(result, &message)?;
+    rate_finished_game(&message, &state).await
 }
 
-fn get_event_from_message(delivery: &Delivery) -> Result<UpdateEvent, ()> {
-    let message = std::str::from_utf8(&delivery.data).unwrap();
-    let message: UpdateEvent = match serde_json::from_str(message) {
-        Ok(msg) => msg,
-        Err(err) => {
-            error!("Failed to deserialize update event: {:?}", err);
-            return Err(());
-        }
-    };
-    info!("Received message: {:?}", &message);
-    return Ok(message);
+// also runs for redelivered results, which completes ratings that failed before
+async fn rate_finished_game(message: &UpdateEvent, state: &Arc<AppState>) -> HandlerResult {
+    if message.status == GameStatus::NotFinished {
+        return Ok(());
+    }
+    match rate_game(&state.db, &(message.game_id as i64)).await {
+        Ok(true) => info!("Updated ratings after game {}", message.game_id),
+        Ok(false) => debug!("Game {} doesn't change ratings", message.game_id),
+        Err(RatingError::NoGame) => error!("Cannot update ratings after game {}: game not found", message.game_id),
+        Err(err) => return Err(Rejection::Retry(format!("Cannot update ratings after game {}: {:?}", message.game_id, err))),
+    }
+    Ok(())
 }
 
-async fn process_message(message: UpdateEvent, state: Arc<AppState>) {
-    let game = get_game(&state.db, &message.game_id).await;
-    if let Ok(game) = game {
-        let game = GameModel {
-            id: game.id, 
-            status: match message.status {
-                GameStatus::NotFinished => repository::GameStatus::NotFinished,
-                GameStatus::Won => repository::GameStatus::Won,
-                GameStatus::Lost => repository::GameStatus::Lost,
-                GameStatus::Drawn => repository::GameStatus::Drawn,
-            },
-            current_state: message.current_state.clone(),
-            user_turn: message.user_turn,
-            noncapture_moves: message.noncapture_moves,
-            nonpromoting_moves: message.nonpromoting_moves,
-            ..Default::default()
-        };
-        if let Ok(_) = update_game(&state.db, game).await {
-            let mv = MoveModel {
-                game_id: message.game_id,
-                current_state: message.current_state,
-                created_at: Some(message.timestamp),
-                last_move: message.last_move,
-                ..Default::default()
-            };
-            _ = save_move(&state.db, mv)
-        }
+fn handle_result(result: Result<i32, MoveError>, message: &UpdateEvent) -> HandlerResult {
+    match result {
+        Ok(ply) => debug!("Saved update {} of game {}", ply, message.game_id),
+        Err(MoveError::Duplicate) => info!("Update {:?} of game {} already saved", message.event_id, message.game_id),
+        Err(MoveError::OutOfOrder) => return Err(Rejection::Retry(format!("Update {:?} of game {} is out of order", message.ply, message.game_id))),
+        Err(MoveError::NoGame) => error!("Cannot save update for game {}: game not found", message.game_id),
+        Err(err) => return Err(Rejection::Retry(format!("Cannot save update {:?} of game {}: {:?}", message.ply, message.game_id, err))),
+    }
+    Ok(())
+}
+
+// takebacks of moves not saved yet are retried, like those failing on the database
+async fn process_takeback(message: TakebackEvent, state: Arc<AppState>) -> HandlerResult {
+    let game = match get_game(&state.db, &(message.game_id as i64)).await {
+        Ok(game) => game,
+        Err(GameError::NotFound) => {
+            error!("Cannot take back moves in game {}: game not found", message.game_id);
+            return Ok(());
+        },
+        Err(err) => return Err(Rejection::Retry(format!("Cannot get game {}: {:?}", message.game_id, err))),
+    };
+    let game = GameModel {
+        id: game.id, 
+        status: game.status,
+        current_state: message.current_state.clone(),
+        user_turn: message.user_turn,
+        noncapture_moves: message.noncapture_moves as i64,
+        nonpromoting_moves: message.nonpromoting_moves as i64,
+        ..Default::default()
+    };
+    match update_game_with_takeback(&state.db, game, message.moves as i64, message.ply.map(|ply| ply as i32), message.timestamp).await {
+        Ok(()) => debug!("Took back {} moves in game {}", message.moves, message.game_id),
+        Err(MoveError::Duplicate) => info!("Takeback in game {} already applied", message.game_id),
+        Err(MoveError::OutOfOrder) => return Err(Rejection::Retry(format!("Takeback to {:?} in game {} is out of order", message.ply, message.game_id))),
+        Err(MoveError::NoGame) => error!("Cannot take back moves in game {}: game not found", message.game_id),
+        Err(err) => return Err(Rejection::Retry(format!("Cannot take back moves in game {}: {:?}", message.game_id, err))),
     }
+    Ok(())
 }
@@ -0,0 +1,34 @@
+use axum::{http::StatusCode, response::{IntoResponse, Response}};
+
+use crate::game::error::MoveError;
+
+#[derive(Debug)]
+pub enum RatingError {
+    Unknown,
+    NoGame,
+}
+
+impl From<sqlx::Error> for RatingError {
+    fn from(_error: sqlx::Error) -> Self {
+        return RatingError::Unknown
+    }
+}
+
+impl From<MoveError> for RatingError {
+    fn from(error: MoveError) -> Self {
+        match error {
+            MoveError::NoGame => RatingError::NoGame,
+            _ => RatingError::Unknown,
+        }
+    }
+}
+
+impl IntoResponse for RatingError {
+    fn into_response(self) -> Response {
+        match self {
+            RatingError::Unknown => (StatusCode::INTERNAL_SERVER_ERROR, "Database error!"),
+            RatingError::NoGame => (StatusCode::NOT_FOUND, "Game not found!"),
+        }
+        .into_response()
+    }
+}
@@ -0,0 +1,91 @@
+// Glicko-2 as described by Glickman, with every rated game as its own rating period.
+
+use std::f64::consts::PI;
+
+// conversion between the Glicko and the Glicko-2 scale
+const SCALE: f64 = 173.7178;
+const BASE: f64 = 1500.0;
+// constrains the change of volatility over time
+const TAU: f64 = 0.5;
+const EPSILON: f64 = 0.000001;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rating {
+    pub rating: f64,
+    pub deviation: f64,
+    pub volatility: f64,
+}
+
+impl Default for Rating {
+    fn default() -> Rating {
+        Rating { rating: BASE, deviation: 350.0, volatility: 0.06 }
+    }
+}
+
+// score is 1 for a win, 0.5 for a draw and 0 for a loss
+pub fn rate(player: &Rating, results: &[(Rating, f64)]) -> Rating {
+    let mu = (player.rating - BASE) / SCALE;
+    let phi = player.deviation / SCALE;
+    if results.is_empty() {
+        let phi = (phi.powi(2) + player.volatility.powi(2)).sqrt();
+        return Rating { deviation: (phi * SCALE).min(Rating::default().deviation), ..*player }
+    }
+
+    let outcomes: Vec<(f64, f64, f64)> = results.iter().map(|(opponent, score)| {
+        let g = g((opponent.deviation) / SCALE);
+        let expected = 1.0 / (1.0 + (-g * (mu - (opponent.rating - BASE) / SCALE)).exp());
+        (g, expected, *score)
+    }).collect();
+    let variance = 1.0 / outcomes.iter().map(|(g, e, _)| g.powi(2) * e * (1.0 - e)).sum::<f64>();
+    let improvement: f64 = outcomes.iter().map(|(g, e, s)| g * (s - e)).sum();
+    let delta = variance * improvement;
+
+    let volatility = volatility(phi, player.volatility, variance, delta);
+    let phi_star = (phi.powi(2) + volatility.powi(2)).sqrt();
+    let phi = 1.0 / (1.0 / phi_star.powi(2) + 1.0 / variance).sqrt();
+    let mu = mu + phi.powi(2) * improvement;
+
+    Rating {
+        rating: mu * SCALE + BASE,
+        deviation: (phi * SCALE).min(Rating::default().deviation),
+        volatility,
+    }
+}
+
+fn g(phi: f64) -> f64 {
+    1.0 / (1.0 + 3.0 * phi.powi(2) / PI.powi(2)).sqrt()
+}
+
+// new volatility by the Illinois algorithm
+fn volatility(phi: f64, sigma: f64, variance: f64, delta: f64) -> f64 {
+    let a = sigma.powi(2).ln();
+    let f = |x: f64| {
+        let ex = x.exp();
+        ex * (delta.powi(2) - phi.powi(2) - variance - ex) / (2.0 * (phi.powi(2) + variance + ex).powi(2)) - (x - a) / TAU.powi(2)
+    };
+
+    let mut a_bound = a;
+    let mut b_bound = if delta.powi(2) > phi.powi(2) + variance {
+        (delta.powi(2) - phi.powi(2) - variance).ln()
+    } else {
+        let mut k = 1.0;
+        while f(a - k * TAU) < 0.0 {
+            k += 1.0;
+        }
+        a - k * TAU
+    };
+    let (mut f_a, mut f_b) = (f(a_bound), f(b_bound));
+    while (b_bound - a_bound).abs() > EPSILON {
+        let c = a_bound + (a_bound - b_bound) * f_a / (f_b - f_a);
+        let f_c = f(c);
+        if f_c * f_b <= 0.0 {
+            a_bound = b_bound;
+            f_a = f_b;
+        } else {
+            f_a /= 2.0;
+        }
+        b_bound = c;
+        f_b = f_c;
+    }
+    (a_bound / 2.0).exp()
+}
@@ -0,0 +1,18 @@
+use serde::{Deserialize, Serialize};
+
+use crate::rating::repository::{HistoryDetails, RatingModel};
+
+pub mod service;
+pub mod error;
+pub mod repository;
+pub mod glicko;
+pub mod update;
+
+// current rating for each rule set the user played rated games in, and its changes
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileResponse {
+    pub username: String,
+    pub ratings: Vec<RatingModel>,
+    pub history: Vec<HistoryDetails>,
+}
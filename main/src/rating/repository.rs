@@ -0,0 +1,173 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{postgres::PgQueryResult, Executor, PgPool, Postgres};
+use tracing::debug;
+
+use crate::{game::repository::{GameModel, RuleSet}, rating::{error::RatingError, glicko::Rating}};
+
+pub async fn get_rating<'c, E: Executor<'c, Database = Postgres>>(db: E, user_id: &i64, ruleset: RuleSet) -> Result<Option<RatingModel>, RatingError> {
+    sqlx::query_as::<Postgres, RatingModel>("SELECT * FROM rating WHERE user_id = $1 AND ruleset = $2")
+        .bind(user_id)
+        .bind(ruleset)
+        .fetch_optional(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot get rating from db!");
+            debug!("{}", err);
+            RatingError::from(err)
+        })
+}
+
+pub async fn get_ratings(db: &PgPool, user_id: &i64) -> Result<Vec<RatingModel>, RatingError> {
+    sqlx::query_as::<Postgres, RatingModel>("SELECT * FROM rating WHERE user_id = $1 ORDER BY ruleset ASC")
+        .bind(user_id)
+        .fetch_all(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot get ratings from db!");
+            debug!("{}", err);
+            RatingError::from(err)
+        })
+}
+
+// creates the default rating of a new player, and keeps the row locked until the transaction ends
+pub async fn lock_rating<'c, E: Executor<'c, Database = Postgres>>(db: E, user_id: &i64, ruleset: RuleSet) -> Result<RatingModel, RatingError> {
+    let rating = Rating::default();
+    sqlx::query_as::<Postgres, RatingModel>("INSERT INTO rating
+                                            (user_id, ruleset, rating, deviation, volatility, games)
+                                            VALUES ($1, $2, $3, $4, $5, 0)
+                                            ON CONFLICT (user_id, ruleset) DO UPDATE SET user_id = rating.user_id
+                                            RETURNING *")
+        .bind(user_id)
+        .bind(ruleset)
+        .bind(rating.rating)
+        .bind(rating.deviation)
+        .bind(rating.volatility)
+        .fetch_one(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot lock rating in db!");
+            debug!("{}", err);
+            RatingError::from(err)
+        })
+}
+
+pub async fn update_rating<'c, E: Executor<'c, Database = Postgres>>(db: E, rating: &RatingModel) -> Result<PgQueryResult, RatingError> {
+    sqlx::query("UPDATE rating
+                SET rating = $3, deviation = $4, volatility = $5, games = $6, updated_at = NOW()
+                WHERE user_id = $1 AND ruleset = $2")
+        .bind(&rating.user_id)
+        .bind(&rating.ruleset)
+        .bind(&rating.rating)
+        .bind(&rating.deviation)
+        .bind(&rating.volatility)
+        .bind(&rating.games)
+        .execute(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot update rating in db!");
+            debug!("{}", err);
+            RatingError::from(err)
+        })
+}
+
+pub async fn save_history<'c, E: Executor<'c, Database = Postgres>>(db: E, entry: HistoryModel) -> Result<PgQueryResult, RatingError> {
+    sqlx::query("INSERT INTO rating_history
+                (user_id, game_id, ruleset, rating, deviation, volatility, change)
+                VALUES ($1, $2, $3, $4, $5, $6, $7)")
+        .bind(&entry.user_id)
+        .bind(&entry.game_id)
+        .bind(&entry.ruleset)
+        .bind(&entry.rating)
+        .bind(&entry.deviation)
+        .bind(&entry.volatility)
+        .bind(&entry.change)
+        .execute(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot add rating history to db!");
+            debug!("{}", err);
+            RatingError::from(err)
+        })
+}
+
+pub async fn is_game_rated<'c, E: Executor<'c, Database = Postgres>>(db: E, game_id: &i64) -> Result<bool, RatingError> {
+    sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM rating_history WHERE game_id = $1)")
+        .bind(game_id)
+        .fetch_one(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot check rating history in db!");
+            debug!("{}", err);
+            RatingError::from(err)
+        })
+}
+
+pub async fn get_finished_game<'c, E: Executor<'c, Database = Postgres>>(db: E, game_id: &i64) -> Result<Option<GameModel>, RatingError> {
+    sqlx::query_as::<Postgres, GameModel>("SELECT * FROM game WHERE id = $1 AND status > 0")
+        .bind(game_id)
+        .fetch_optional(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot get game from db!");
+            debug!("{}", err);
+            RatingError::from(err)
+        })
+}
+
+// newest first
+pub async fn get_history(db: &PgPool, user_id: &i64) -> Result<Vec<HistoryDetails>, RatingError> {
+    sqlx::query_as::<Postgres, HistoryDetails>("SELECT h.*, a.username AS opponent
+                                             FROM rating_history h
+                                             JOIN game g ON g.id = h.game_id
+                                             LEFT JOIN account a ON a.id = CASE WHEN g.user_id = h.user_id THEN g.opponent_id ELSE g.user_id END
+                                             WHERE h.user_id = $1
+                                             ORDER BY h.created_at DESC, h.id DESC")
+        .bind(user_id)
+        .fetch_all(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot get rating history from db!");
+            debug!("{}", err);
+            RatingError::from(err)
+        })
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct RatingModel {
+    pub user_id: i64,
+    pub ruleset: RuleSet,
+    pub rating: f64,
+    pub deviation: f64,
+    pub volatility: f64,
+    pub games: i32,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl RatingModel {
+    pub fn glicko(&self) -> Rating {
+        Rating { rating: self.rating, deviation: self.deviation, volatility: self.volatility }
+    }
+}
+
+pub struct HistoryModel {
+    pub user_id: i64,
+    pub game_id: i64,
+    pub ruleset: RuleSet,
+    pub rating: f64,
+    pub deviation: f64,
+    pub volatility: f64,
+    pub change: f64,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryDetails {
+    pub game_id: i64,
+    pub ruleset: RuleSet,
+    pub opponent: Option<String>,
+    pub rating: f64,
+    pub deviation: f64,
+    pub change: f64,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
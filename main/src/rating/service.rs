@@ -0,0 +1,36 @@
+use std::sync::Arc;
+
+use axum::{extract::{Path, State}, response::{IntoResponse, Response}, Json};
+use tracing::{debug, info};
+
+use crate::{rating::{repository::{get_history, get_ratings}, ProfileResponse}, security::UserData, user::repository::get_user, AppState};
+
+pub async fn profile(State(state): State<Arc<AppState>>, _user: UserData, Path(username): Path<String>) -> Response {
+    info!("Ratings of user {} requested…", username);
+
+    debug!("Trying to get user {} from db…", username);
+    let query_result = get_user(&state.db, &username).await;
+
+    if let Err(err) = query_result {
+        return err.into_response()
+    }
+    let user = query_result.unwrap();
+
+    debug!("Trying to fetch ratings for user {} from db…", username);
+    let query_result = get_ratings(&state.db, &user.id).await;
+
+    if let Err(err) = query_result {
+        return err.into_response()
+    }
+    let ratings = query_result.unwrap();
+
+    debug!("Trying to fetch rating history for user {} from db…", username);
+    let query_result = get_history(&state.db, &user.id).await;
+
+    if let Err(err) = query_result {
+        return err.into_response()
+    }
+    let history = query_result.unwrap();
+
+    return Json(ProfileResponse { username: user.username, ratings, history }).into_response()
+}
@@ -0,0 +1,60 @@
+use sqlx::PgPool;
+use tracing::info;
+
+use crate::{game::repository::{lock_game, GameStatus, GameType}, rating::{error::RatingError, glicko::rate, repository::{get_finished_game, is_game_rated, lock_rating, save_history, update_rating, HistoryModel, RatingModel}}};
+
+// rates both players of a finished rated game in one transaction; the history keeps
+// a game from being rated twice, so redelivered results can call it again.
+// Returns whether the ratings changed.
+pub async fn rate_game(db: &PgPool, game_id: &i64) -> Result<bool, RatingError> {
+    let mut tx = db.begin().await?;
+    lock_game(&mut *tx, game_id).await?;
+    let Some(game) = get_finished_game(&mut *tx, game_id).await? else {
+        return Ok(false)
+    };
+    let Some(opponent_id) = game.opponent_id else {
+        return Ok(false)
+    };
+    if !game.rated || !matches!(game.game_type, GameType::User) || is_game_rated(&mut *tx, game_id).await? {
+        return Ok(false)
+    }
+
+    // rows are locked in the order of ids, so games of the same players don't deadlock
+    let (first, second) = (game.user_id.min(opponent_id), game.user_id.max(opponent_id));
+    let first = lock_rating(&mut *tx, &first, game.ruleset).await?;
+    let second = lock_rating(&mut *tx, &second, game.ruleset).await?;
+    let (user, opponent) = match first.user_id == game.user_id {
+        true => (first, second),
+        false => (second, first),
+    };
+    let score = match game.status {
+        GameStatus::Won => 1.0,
+        GameStatus::Lost => 0.0,
+        _ => 0.5,
+    };
+
+    let user_rating = rate(&user.glicko(), &[(opponent.glicko(), score)]);
+    let opponent_rating = rate(&opponent.glicko(), &[(user.glicko(), 1.0 - score)]);
+    for (player, rating) in [(user, user_rating), (opponent, opponent_rating)] {
+        save_history(&mut *tx, HistoryModel {
+            user_id: player.user_id,
+            game_id: *game_id,
+            ruleset: player.ruleset,
+            rating: rating.rating,
+            deviation: rating.deviation,
+            volatility: rating.volatility,
+            change: rating.rating - player.rating,
+        }).await?;
+        update_rating(&mut *tx, &RatingModel {
+            rating: rating.rating,
+            deviation: rating.deviation,
+            volatility: rating.volatility,
+            games: player.games + 1,
+            ..player
+        }).await?;
+    }
+    tx.commit().await?;
+
+    info!("Rated game {}", game_id);
+    Ok(true)
+}
@@ -0,0 +1,23 @@
+use axum::{http::StatusCode, response::{IntoResponse, Response}};
+
+#[derive(Debug)]
+pub enum StatsError {
+    Unknown,
+    WrongPage,
+}
+
+impl From<sqlx::Error> for StatsError {
+    fn from(_error: sqlx::Error) -> Self {
+        return StatsError::Unknown
+    }
+}
+
+impl IntoResponse for StatsError {
+    fn into_response(self) -> Response {
+        match self {
+            StatsError::Unknown => (StatusCode::INTERNAL_SERVER_ERROR, "Database error!"),
+            StatsError::WrongPage => (StatusCode::BAD_REQUEST, "Page cannot be negative and size must be between 1 and 100!"),
+        }
+        .into_response()
+    }
+}
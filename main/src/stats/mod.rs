@@ -0,0 +1,64 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{game::Rules, stats::repository::{LeaderboardEntry, Streak}};
+
+pub mod service;
+pub mod error;
+pub mod repository;
+
+const DEFAULT_PAGE_SIZE: i64 = 20;
+const MAX_PAGE_SIZE: i64 = 100;
+
+// pages are counted from 0, British rules unless given
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LeaderboardQuery {
+    pub ruleset: Option<Rules>,
+    pub page: Option<i64>,
+    pub size: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LeaderboardResponse {
+    pub page: i64,
+    pub size: i64,
+    pub total: i64,
+    pub players: Vec<LeaderboardEntry>,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct Record {
+    pub games: i64,
+    pub wins: i64,
+    pub draws: i64,
+    pub losses: i64,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub enum OpponentType {
+    Human, Random, Counting,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpponentRecord {
+    pub opponent: OpponentType,
+    #[serde(flatten)]
+    pub record: Record,
+}
+
+// average length is counted in plies
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatsResponse {
+    pub username: String,
+    #[serde(flatten)]
+    pub total: Record,
+    pub white: Record,
+    pub red: Record,
+    pub opponents: Vec<OpponentRecord>,
+    pub average_length: f64,
+    pub streak: Option<Streak>,
+}
@@ -0,0 +1,115 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres};
+use tracing::debug;
+
+use crate::{game::repository::{AIType, GameStatus, RuleSet}, stats::error::StatsError};
+
+// finished games of the player, with the results seen from the player; the user plays
+// white when starting, and games against AI are counted under their AI type
+const PLAYER_GAMES: &str = "SELECT g.id, g.finished_at,
+                                   (g.user_id = $1) = g.user_starts AS white,
+                                   CASE WHEN g.game_type = 1 THEN g.ai_type ELSE 0 END AS ai_type,
+                                   CASE WHEN g.status = 3 THEN 3 WHEN (g.status = 1) = (g.user_id = $1) THEN 1 ELSE 2 END AS status
+                            FROM game g
+                            WHERE (g.user_id = $1 OR g.opponent_id = $1) AND g.invitation = 1 AND g.status > 0";
+
+// one row for each color and opponent type the player had
+pub async fn get_records(db: &PgPool, user_id: &i64) -> Result<Vec<RecordRow>, StatsError> {
+    sqlx::query_as::<Postgres, RecordRow>(&format!("WITH games AS ({})
+                                                   SELECT white, ai_type::SMALLINT AS ai_type,
+                                                   COUNT(*) AS games,
+                                                   COUNT(*) FILTER (WHERE status = 1) AS wins,
+                                                   COUNT(*) FILTER (WHERE status = 3) AS draws,
+                                                   COUNT(*) FILTER (WHERE status = 2) AS losses,
+                                                   COALESCE(SUM((SELECT COUNT(*) FROM move m WHERE m.game_id = games.id)), 0)::BIGINT AS plies
+                                                   FROM games
+                                                   GROUP BY white, ai_type", PLAYER_GAMES))
+        .bind(user_id)
+        .fetch_all(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot get game statistics from db!");
+            debug!("{}", err);
+            StatsError::from(err)
+        })
+}
+
+// results since the last game that ended differently, none without finished games
+pub async fn get_streak(db: &PgPool, user_id: &i64) -> Result<Option<Streak>, StatsError> {
+    sqlx::query_as::<Postgres, Streak>(&format!("WITH games AS ({}),
+                                                results AS (SELECT status, ROW_NUMBER() OVER (ORDER BY finished_at DESC NULLS LAST, id DESC) AS n FROM games)
+                                                SELECT status::SMALLINT AS status, COUNT(*) AS games
+                                                FROM results
+                                                WHERE n < COALESCE((SELECT MIN(n) FROM results WHERE status <> (SELECT status FROM results WHERE n = 1)), n + 1)
+                                                GROUP BY status", PLAYER_GAMES))
+        .bind(user_id)
+        .fetch_optional(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot get streak from db!");
+            debug!("{}", err);
+            StatsError::from(err)
+        })
+}
+
+// pages too far for the offset to fit are rejected
+pub async fn get_leaderboard(db: &PgPool, ruleset: RuleSet, page: i64, size: i64) -> Result<Vec<LeaderboardEntry>, StatsError> {
+    let offset = page.checked_mul(size).ok_or(StatsError::WrongPage)?;
+    sqlx::query_as::<Postgres, LeaderboardEntry>("SELECT ROW_NUMBER() OVER (ORDER BY r.rating DESC, r.user_id ASC) AS rank,
+                                                 a.username, r.rating, r.deviation, r.games
+                                                 FROM rating r
+                                                 JOIN account a ON a.id = r.user_id
+                                                 WHERE r.ruleset = $1 AND r.games > 0
+                                                 ORDER BY r.rating DESC, r.user_id ASC
+                                                 LIMIT $2 OFFSET $3")
+        .bind(ruleset)
+        .bind(size)
+        .bind(offset)
+        .fetch_all(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot get leaderboard from db!");
+            debug!("{}", err);
+            StatsError::from(err)
+        })
+}
+
+pub async fn count_rated_players(db: &PgPool, ruleset: RuleSet) -> Result<i64, StatsError> {
+    sqlx::query_scalar("SELECT COUNT(*) FROM rating WHERE ruleset = $1 AND games > 0")
+        .bind(ruleset)
+        .fetch_one(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot count rated players in db!");
+            debug!("{}", err);
+            StatsError::from(err)
+        })
+}
+
+#[derive(sqlx::FromRow)]
+pub struct RecordRow {
+    pub white: bool,
+    pub ai_type: AIType,
+    pub games: i64,
+    pub wins: i64,
+    pub draws: i64,
+    pub losses: i64,
+    pub plies: i64,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct Streak {
+    pub status: GameStatus,
+    pub games: i64,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct LeaderboardEntry {
+    pub rank: i64,
+    pub username: String,
+    pub rating: f64,
+    pub deviation: f64,
+    pub games: i32,
+}
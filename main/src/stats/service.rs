@@ -0,0 +1,105 @@
+use std::sync::Arc;
+
+use axum::{extract::{Path, Query, State}, response::{IntoResponse, Response}, Json};
+use tracing::{debug, info};
+
+use crate::{game::{self, repository::{AIType, RuleSet}}, security::UserData, stats::{error::StatsError, repository::{count_rated_players, get_leaderboard, get_records, get_streak, RecordRow, Streak}, LeaderboardQuery, LeaderboardResponse, OpponentRecord, OpponentType, Record, StatsResponse, DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE}, user::repository::get_user, AppState};
+
+pub async fn leaderboard(State(state): State<Arc<AppState>>, _user: UserData, Query(query): Query<LeaderboardQuery>) -> Response {
+    info!("Leaderboard requested…");
+    let ruleset = match query.ruleset {
+        None | Some(game::Rules::British) => RuleSet::British,
+    };
+    let page = query.page.unwrap_or(0);
+    let size = query.size.unwrap_or(DEFAULT_PAGE_SIZE);
+    if page < 0 || !(1..=MAX_PAGE_SIZE).contains(&size) {
+        return StatsError::WrongPage.into_response()
+    }
+
+    debug!("Trying to count rated players in db…");
+    let query_result = count_rated_players(&state.db, ruleset).await;
+
+    if let Err(err) = query_result {
+        return err.into_response()
+    }
+    let total = query_result.unwrap();
+
+    debug!("Trying to fetch page {} of leaderboard from db…", page);
+    let query_result = get_leaderboard(&state.db, ruleset, page, size).await;
+
+    if let Err(err) = query_result {
+        return err.into_response()
+    }
+    let players = query_result.unwrap();
+
+    return Json(LeaderboardResponse { page, size, total, players }).into_response()
+}
+
+pub async fn stats(State(state): State<Arc<AppState>>, _user: UserData, Path(username): Path<String>) -> Response {
+    info!("Statistics of user {} requested…", username);
+
+    debug!("Trying to get user {} from db…", username);
+    let query_result = get_user(&state.db, &username).await;
+
+    if let Err(err) = query_result {
+        return err.into_response()
+    }
+    let user = query_result.unwrap();
+
+    debug!("Trying to fetch game statistics for user {} from db…", username);
+    let query_result = get_records(&state.db, &user.id).await;
+
+    if let Err(err) = query_result {
+        return err.into_response()
+    }
+    let rows = query_result.unwrap();
+
+    debug!("Trying to fetch streak for user {} from db…", username);
+    let query_result = get_streak(&state.db, &user.id).await;
+
+    if let Err(err) = query_result {
+        return err.into_response()
+    }
+    let streak = query_result.unwrap();
+
+    return Json(get_stats(user.username, rows, streak)).into_response()
+}
+
+fn get_stats(username: String, rows: Vec<RecordRow>, streak: Option<Streak>) -> StatsResponse {
+    let mut stats = StatsResponse {
+        username,
+        total: Record::default(),
+        white: Record::default(),
+        red: Record::default(),
+        opponents: Vec::new(),
+        average_length: 0.0,
+        streak,
+    };
+    let mut plies = 0;
+    for row in rows {
+        let record = Record { games: row.games, wins: row.wins, draws: row.draws, losses: row.losses };
+        let opponent = match row.ai_type {
+            AIType::None => OpponentType::Human,
+            AIType::Random => OpponentType::Random,
+            AIType::Counting => OpponentType::Counting,
+        };
+        add(&mut stats.total, &record);
+        add(if row.white { &mut stats.white } else { &mut stats.red }, &record);
+        match stats.opponents.iter_mut().find(|entry| entry.opponent == opponent) {
+            Some(entry) => add(&mut entry.record, &record),
+            None => stats.opponents.push(OpponentRecord { opponent, record }),
+        }
+        plies += row.plies;
+    }
+    if stats.total.games > 0 {
+        stats.average_length = plies as f64 / stats.total.games as f64;
+    }
+    stats
+}
+
+fn add(record: &mut Record, other: &Record) {
+    record.games += other.games;
+    record.wins += other.wins;
+    record.draws += other.draws;
+    record.losses += other.losses;
+}
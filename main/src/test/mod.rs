@@ -1,14 +1,18 @@
-use std::{env, sync::{Arc, Mutex}};
+use std::{collections::HashSet, env, sync::{Arc, Mutex}, time::Duration};
 
 use axum::{extract::Request, body::{Body, to_bytes}, http::StatusCode, Router};
 use once_cell::sync::Lazy;
 use tower::ServiceExt;
-
-use sqlx::{postgres::PgPoolOptions, PgPool};
 use testcontainers::{runners::SyncRunner, Container};
 use testcontainers_modules::postgres;
 
-use crate::{config::get_config, AppState};
+use chrono::Utc;
+use game_engine::replay::replay;
+use protocol::{broker::{handler, Broker, Delivery, MemoryBroker}, envelope::encode, game::{GameEvent, MatchEvent, RematchEvent, RematchRequestEvent, RematchStatus, StateEvent, TakebackEvent, UpdateEvent}, GameStatus, RuleSet};
+use sqlx::{postgres::PgPoolOptions, PgPool};
+use tokio::sync::{mpsc::{unbounded_channel, UnboundedReceiver}, Notify};
+
+use crate::{config::get_config, game::{error::GameError, replay::{repair_game, verify_game, Discrepancy}, invitation::{check_issued, sweep_invitations}, repository::{get_game, get_moves, save_game, AIType, GameModel, GameStatus as GameModelStatus, GameType, InvitationStatus, RuleSet as GameRuleSet, TimeControl}}, matchmaking::{pairing::{pair_players, try_pair}, repository::{join_queue, QueueModel}}, outbox::repository::{delete_published, enqueue}, rabbit::{self, GAMES_EXCHANGE, STATE_EXCHANGE, UPDATES_EXCHANGE}, rating::{glicko::{rate, Rating}, repository::get_rating}, security::get_token, tournament::pairing::{game_key, round_robin, round_robin_rounds, swiss, SwissPlayer}, AppState};
 
 static DB: Lazy<Mutex<Container<postgres::Postgres>>> = Lazy::new(|| { config() });
 
@@ -50,7 +54,12 @@ async fn get_app() -> (PgPool, Router) {
         .run(&pool)
         .await
         .unwrap();
-    let state = Arc::from(AppState {jwt: config.jwt_secret, db: pool.clone() });
+    // a relay stopped with the previous test leaves its messages pending, they'd reach this test's subscribers
+    sqlx::query("UPDATE outbox SET published_at = NOW() WHERE published_at IS NULL")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let state = Arc::from(AppState {jwt: config.jwt_secret, db: pool.clone(), outbox: Notify::new() });
     (pool, crate::get_router(state))
 }
 
@@ -91,3 +100,1169 @@ async fn username_should_be_unique() {
     let content = std::str::from_utf8(&*bytes).unwrap();
     assert!(content.contains("username exists"));
 }
+
+// consumers of the service listening on an in-memory broker
+async fn get_broker(db: &PgPool) -> Arc<dyn Broker> {
+    listen_on(db, MemoryBroker::default()).await
+}
+
+async fn listen_on(db: &PgPool, broker: MemoryBroker) -> Arc<dyn Broker> {
+    let config = get_config();
+    let state = Arc::from(AppState {jwt: config.jwt_secret, db: db.clone(), outbox: Notify::new() });
+    let broker: Arc<dyn Broker> = Arc::new(broker);
+    rabbit::listen(broker.clone(), state).await.unwrap();
+    broker
+}
+
+// deliveries of a test queue bound to the exchange
+async fn subscribe(broker: &Arc<dyn Broker>, exchange: &str, routing_key: &str) -> UnboundedReceiver<Delivery> {
+    let queue = format!("test.{}.queue", routing_key);
+    let (tx, rx) = unbounded_channel();
+    broker.declare_queue(queue.as_str(), exchange, routing_key).await.unwrap();
+    broker.consume(queue.as_str(), "test", handler(move |delivery| {
+        let tx = tx.clone();
+        async move {
+            let _ = tx.send(delivery);
+            Ok(())
+        }
+    })).await.unwrap();
+    rx
+}
+
+async fn create_account(db: &PgPool, username: &str) -> i64 {
+    sqlx::query_scalar("INSERT INTO account (username, password) VALUES ($1, $2) RETURNING id")
+        .bind(username)
+        .bind("password")
+        .fetch_one(db)
+        .await
+        .unwrap()
+}
+
+async fn create_game(db: &PgPool, user: &str, opponent: &str) -> i64 {
+    let user_id = create_account(db, user).await;
+    let opponent_id = create_account(db, opponent).await;
+    let game = GameModel { user_id, opponent_id: Some(opponent_id), invitation: InvitationStatus::Accepted, ..Default::default() };
+    save_game(db, game).await.unwrap()
+}
+
+fn get_update(game_id: i64, mov: &str, current_state: &str, user_turn: bool) -> UpdateEvent {
+    UpdateEvent {
+        game_id: game_id as usize,
+        status: GameStatus::NotFinished,
+        current_state: current_state.into(),
+        user_turn,
+        timestamp: Utc::now(),
+        last_move: mov.into(),
+        noncapture_moves: 0,
+        nonpromoting_moves: 0,
+        event_id: None,
+        ply: None,
+        user_time: None,
+        opponent_time: None,
+    }
+}
+
+async fn publish_update(broker: &Arc<dyn Broker>, game_id: i64, mov: &str, current_state: &str, user_turn: bool) {
+    let event = get_update(game_id, mov, current_state, user_turn);
+    broker.publish(UPDATES_EXCHANGE, "update", encode(event, "game", None)).await.unwrap();
+}
+
+async fn publish_numbered_update(broker: &Arc<dyn Broker>, game_id: i64, ply: usize, mov: &str, current_state: &str, user_turn: bool) {
+    let event = UpdateEvent {
+        event_id: Some(format!("{}-{}", game_id, ply)),
+        ply: Some(ply),
+        ..get_update(game_id, mov, current_state, user_turn)
+    };
+    broker.publish(UPDATES_EXCHANGE, "update", encode(event, "game", None)).await.unwrap();
+}
+
+async fn wait_for_moves(db: &PgPool, game_id: i64, count: usize) {
+    for _ in 0..50 {
+        if get_moves(db, &game_id).await.unwrap().len() == count {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("Game {} doesn't have {} moves", game_id, count);
+}
+
+#[tokio::test]
+async fn moves_should_be_saved_with_game_update() {
+    let _config = DB.lock().unwrap();
+    let (db, _app) = get_app().await;
+    let broker = get_broker(&db).await;
+    let id = create_game(&db, "history_user", "history_opponent").await;
+
+    publish_update(&broker, id, "11-15", "xxxxxxxx.xxxx.......oooooooooooo", false).await;
+    publish_update(&broker, id, "22-18", "xxxxxxxx.xxxx....o..ooo.oooooooo", true).await;
+    wait_for_moves(&db, id, 2).await;
+
+    let moves = get_moves(&db, &id).await.unwrap();
+    assert_eq!(moves[0].ply, 1);
+    assert_eq!(moves[0].notation, "11-15");
+    assert_eq!(moves[0].mover, Some("history_user".into()));
+    assert_eq!(moves[1].ply, 2);
+    assert_eq!(moves[1].mover, Some("history_opponent".into()));
+    let game = get_game(&db, &id).await.unwrap();
+    assert_eq!(game.current_state, moves[1].current_state);
+    assert!(game.user_turn);
+}
+
+#[tokio::test]
+async fn taken_back_moves_should_be_removed_from_history() {
+    let _config = DB.lock().unwrap();
+    let (db, _app) = get_app().await;
+    let broker = get_broker(&db).await;
+    let id = create_game(&db, "takeback_user", "takeback_opponent").await;
+
+    publish_update(&broker, id, "11-15", "xxxxxxxx.xxxx.......oooooooooooo", false).await;
+    publish_update(&broker, id, "22-18", "xxxxxxxx.xxxx....o..ooo.oooooooo", true).await;
+    wait_for_moves(&db, id, 2).await;
+    let event = TakebackEvent {
+        game_id: id as usize,
+        moves: 1,
+        current_state: "xxxxxxxx.xxxx.......oooooooooooo".into(),
+        user_turn: false,
+        timestamp: Utc::now(),
+        noncapture_moves: 0,
+        nonpromoting_moves: 0,
+        ply: None,
+    };
+    broker.publish(UPDATES_EXCHANGE, "takeback", encode(event, "game", None)).await.unwrap();
+    wait_for_moves(&db, id, 1).await;
+
+    let game = get_game(&db, &id).await.unwrap();
+    assert_eq!(game.current_state, "xxxxxxxx.xxxx.......oooooooooooo");
+    publish_update(&broker, id, "23-18", "xxxxxxxx.xxxx....o..oooo.ooooooo", true).await;
+    wait_for_moves(&db, id, 2).await;
+    assert_eq!(get_moves(&db, &id).await.unwrap()[1].notation, "23-18");
+}
+
+#[tokio::test]
+async fn history_should_be_returned_in_order() {
+    let _config = DB.lock().unwrap();
+    let (db, app) = get_app().await;
+    let broker = get_broker(&db).await;
+    let id = create_game(&db, "endpoint_user", "endpoint_opponent").await;
+    publish_update(&broker, id, "11-15", "xxxxxxxx.xxxx.......oooooooooooo", false).await;
+    wait_for_moves(&db, id, 1).await;
+    let token = get_token(&"endpoint_user".into(), false, &get_config().jwt_secret).unwrap();
+
+    let response = app
+        .oneshot(
+            Request::builder()
+            .method("GET")
+            .header("Authorization", format!("Bearer {}", token))
+            .uri(format!("/game/{}/history", id))
+            .body(Body::empty())
+            .unwrap()
+            )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let bytes = to_bytes(response.into_body(), 10000).await.unwrap();
+    let history: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(history[0]["ply"], 1);
+    assert_eq!(history[0]["notation"], "11-15");
+    assert_eq!(history[0]["mover"], "endpoint_user");
+    assert_eq!(history[0]["currentState"], "xxxxxxxx.xxxx.......oooooooooooo");
+}
+
+#[tokio::test]
+async fn redelivered_update_should_be_saved_once() {
+    let _config = DB.lock().unwrap();
+    let (db, _app) = get_app().await;
+    let broker = get_broker(&db).await;
+    let id = create_game(&db, "redelivery_user", "redelivery_opponent").await;
+
+    publish_numbered_update(&broker, id, 1, "11-15", "xxxxxxxx.xxxx.......oooooooooooo", false).await;
+    publish_numbered_update(&broker, id, 1, "11-15", "xxxxxxxx.xxxx.......oooooooooooo", false).await;
+    publish_numbered_update(&broker, id, 2, "22-18", "xxxxxxxx.xxxx....o..ooo.oooooooo", true).await;
+    wait_for_moves(&db, id, 2).await;
+
+    let moves = get_moves(&db, &id).await.unwrap();
+    assert_eq!(moves[0].notation, "11-15");
+    assert_eq!(moves[1].notation, "22-18");
+    assert!(get_game(&db, &id).await.unwrap().user_turn);
+}
+
+#[tokio::test]
+async fn out_of_order_update_should_be_retried() {
+    let _config = DB.lock().unwrap();
+    let (db, _app) = get_app().await;
+    let broker = get_broker(&db).await;
+    let id = create_game(&db, "order_user", "order_opponent").await;
+
+    publish_numbered_update(&broker, id, 2, "22-18", "xxxxxxxx.xxxx....o..ooo.oooooooo", true).await;
+    publish_numbered_update(&broker, id, 1, "11-15", "xxxxxxxx.xxxx.......oooooooooooo", false).await;
+    wait_for_moves(&db, id, 2).await;
+
+    let moves = get_moves(&db, &id).await.unwrap();
+    assert_eq!(moves[0].notation, "11-15");
+    assert_eq!(moves[1].notation, "22-18");
+    assert_eq!(get_game(&db, &id).await.unwrap().current_state, "xxxxxxxx.xxxx....o..ooo.oooooooo");
+}
+
+#[tokio::test]
+async fn update_failing_on_database_should_be_retried() {
+    let _config = DB.lock().unwrap();
+    let (db, _app) = get_app().await;
+    let broker = get_broker(&db).await;
+    let id = create_game(&db, "failing_user", "failing_opponent").await;
+    sqlx::query("CREATE OR REPLACE FUNCTION fail_move() RETURNS trigger AS $$ BEGIN RAISE EXCEPTION 'database unavailable'; END $$ LANGUAGE plpgsql")
+        .execute(&db)
+        .await
+        .unwrap();
+    sqlx::query(&format!("CREATE TRIGGER fail_move BEFORE INSERT ON move FOR EACH ROW WHEN (NEW.game_id = {}) EXECUTE FUNCTION fail_move()", id))
+        .execute(&db)
+        .await
+        .unwrap();
+
+    publish_numbered_update(&broker, id, 1, "11-15", "xxxxxxxx.xxxx.......oooooooooooo", false).await;
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    assert!(get_moves(&db, &id).await.unwrap().is_empty());
+
+    sqlx::query("DROP TRIGGER fail_move ON move").execute(&db).await.unwrap();
+    wait_for_moves(&db, id, 1).await;
+    assert_eq!(get_game(&db, &id).await.unwrap().current_state, "xxxxxxxx.xxxx.......oooooooooooo");
+}
+
+#[tokio::test]
+async fn takeback_racing_move_should_wait_for_it() {
+    let _config = DB.lock().unwrap();
+    let (db, _app) = get_app().await;
+    let broker = get_broker(&db).await;
+    let id = create_game(&db, "race_user", "race_opponent").await;
+    publish_numbered_update(&broker, id, 1, "11-15", "xxxxxxxx.xxxx.......oooooooooooo", false).await;
+    wait_for_moves(&db, id, 1).await;
+    let takeback = TakebackEvent {
+        game_id: id as usize,
+        moves: 1,
+        current_state: "xxxxxxxx.xxxx....o..ooo.oooooooo".into(),
+        user_turn: true,
+        timestamp: Utc::now(),
+        noncapture_moves: 0,
+        nonpromoting_moves: 0,
+        ply: Some(2),
+    };
+
+    // the move taken back arrives after the takeback
+    publish_numbered_update(&broker, id, 3, "9-13", "xxxxxxxx.xxx.x...o..ooo.oooooooo", false).await;
+    publish_numbered_update(&broker, id, 2, "22-18", "xxxxxxxx.xxxx....o..ooo.oooooooo", true).await;
+    broker.publish(UPDATES_EXCHANGE, "takeback", encode(takeback.clone(), "game", None)).await.unwrap();
+    for _ in 0..50 {
+        let taken_back: bool = sqlx::query_scalar("SELECT taken_back_at IS NOT NULL FROM game WHERE id = $1").bind(id).fetch_one(&db).await.unwrap();
+        if taken_back {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    let moves = get_moves(&db, &id).await.unwrap();
+    assert_eq!(moves.len(), 2);
+    assert_eq!(moves[1].notation, "22-18");
+    let game = get_game(&db, &id).await.unwrap();
+    assert_eq!(game.current_state, "xxxxxxxx.xxxx....o..ooo.oooooooo");
+    assert!(game.user_turn);
+
+    // a redelivered takeback doesn't remove the move made after it
+    broker.publish(UPDATES_EXCHANGE, "takeback", encode(takeback, "game", None)).await.unwrap();
+    publish_numbered_update(&broker, id, 3, "10-14", "xxxxxxxx.x.xx...xo..ooo.oooooooo", false).await;
+    wait_for_moves(&db, id, 3).await;
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    let moves = get_moves(&db, &id).await.unwrap();
+    assert_eq!(moves.len(), 3);
+    assert_eq!(moves[2].notation, "10-14");
+}
+
+#[tokio::test]
+async fn state_should_be_published_through_outbox() {
+    let _config = DB.lock().unwrap();
+    let (db, _app) = get_app().await;
+    let broker = get_broker(&db).await;
+    let id = create_game(&db, "state_user", "state_opponent").await;
+    let mut rx = subscribe(&broker, STATE_EXCHANGE, "state").await;
+
+    broker.publish(GAMES_EXCHANGE, "game", encode(GameEvent { game_id: id as usize }, "game", Some("7".into()))).await.unwrap();
+
+    let delivery = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+    let state = delivery.parse::<StateEvent>().unwrap();
+    assert_eq!(state.producer, "main");
+    assert_eq!(state.correlation_id, Some("7".into()));
+    assert_eq!(state.payload.game_id, id as usize);
+    assert_eq!(state.payload.user, "state_user");
+}
+
+#[tokio::test]
+async fn clock_times_should_be_sent_back_with_state() {
+    let _config = DB.lock().unwrap();
+    let (db, _app) = get_app().await;
+    let broker = get_broker(&db).await;
+    let id = create_game(&db, "clock_user", "clock_opponent").await;
+    let mut rx = subscribe(&broker, STATE_EXCHANGE, "state").await;
+
+    let event = UpdateEvent { user_time: Some(61000), opponent_time: Some(58000), ..get_update(id, "11-15", "xxxxxxxx.xxxx.......oooooooooooo", false) };
+    broker.publish(UPDATES_EXCHANGE, "update", encode(event, "game", None)).await.unwrap();
+    wait_for_moves(&db, id, 1).await;
+    let event = UpdateEvent { ply: Some(2), ..get_update(id, "22-18", "xxxxxxxx.xxxx....o..ooo.oooooooo", true) };
+    broker.publish(UPDATES_EXCHANGE, "update", encode(event, "game", None)).await.unwrap();
+    wait_for_moves(&db, id, 2).await;
+    broker.publish(GAMES_EXCHANGE, "game", encode(GameEvent { game_id: id as usize }, "game", None)).await.unwrap();
+
+    let delivery = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+    let state = delivery.parse::<StateEvent>().unwrap().payload;
+    assert_eq!(state.ply, 2);
+    assert_eq!(state.user_time, Some(61000));
+    assert_eq!(state.opponent_time, Some(58000));
+}
+
+#[tokio::test]
+async fn state_request_failing_on_database_should_be_retried() {
+    let _config = DB.lock().unwrap();
+    let (db, _app) = get_app().await;
+    let broker = get_broker(&db).await;
+    let id = create_game(&db, "retried_state_user", "retried_state_opponent").await;
+    let mut rx = subscribe(&broker, STATE_EXCHANGE, "state").await;
+    publish_numbered_update(&broker, id, 1, "11-15", "xxxxxxxx.xxxx.......oooooooooooo", false).await;
+    wait_for_moves(&db, id, 1).await;
+
+    // moves can't be read, so the last ply isn't known
+    sqlx::query("ALTER TABLE move RENAME TO move_unavailable").execute(&db).await.unwrap();
+    broker.publish(GAMES_EXCHANGE, "game", encode(GameEvent { game_id: id as usize }, "game", None)).await.unwrap();
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    let early = rx.try_recv();
+    sqlx::query("ALTER TABLE move_unavailable RENAME TO move").execute(&db).await.unwrap();
+    assert!(early.is_err());
+
+    let delivery = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+    let state = delivery.parse::<StateEvent>().unwrap().payload;
+    assert_eq!(state.game_id, id as usize);
+    assert_eq!(state.ply, 1);
+}
+
+#[tokio::test]
+async fn outbox_messages_should_wait_for_broker() {
+    let _config = DB.lock().unwrap();
+    let (db, _app) = get_app().await;
+    let memory = MemoryBroker::default();
+    let broker = listen_on(&db, memory.clone()).await;
+    let mut rx = subscribe(&broker, "test.outbox.topic", "outbox").await;
+    memory.set_connected(false);
+
+    let id = enqueue(&db, "test.outbox.topic", "outbox", b"kept".to_vec()).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    let (attempts, published): (i32, bool) = sqlx::query_as("SELECT attempts, published_at IS NOT NULL FROM outbox WHERE id = $1")
+        .bind(id)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert!(attempts > 0);
+    assert!(!published);
+    assert!(rx.try_recv().is_err());
+
+    memory.set_connected(true);
+    let delivery = tokio::time::timeout(Duration::from_secs(10), rx.recv()).await.unwrap().unwrap();
+    assert_eq!(delivery.data, b"kept");
+}
+
+#[tokio::test]
+async fn old_published_messages_should_be_deleted() {
+    let _config = DB.lock().unwrap();
+    let (db, _app) = get_app().await;
+    let old = enqueue(&db, "test.cleanup.topic", "cleanup", b"old".to_vec()).await.unwrap();
+    let recent = enqueue(&db, "test.cleanup.topic", "cleanup", b"recent".to_vec()).await.unwrap();
+    let pending = enqueue(&db, "test.cleanup.topic", "cleanup", b"pending".to_vec()).await.unwrap();
+    sqlx::query("UPDATE outbox SET published_at = NOW() - INTERVAL '2 days' WHERE id = $1").bind(old).execute(&db).await.unwrap();
+    sqlx::query("UPDATE outbox SET published_at = NOW() WHERE id = $1").bind(recent).execute(&db).await.unwrap();
+
+    let cutoff = Utc::now() - chrono::Duration::days(1);
+    assert!(delete_published(&db, &cutoff).await.unwrap() >= 1);
+
+    let left: Vec<i64> = sqlx::query_scalar("SELECT id FROM outbox WHERE id = ANY($1) ORDER BY id")
+        .bind(vec![old, recent, pending])
+        .fetch_all(&db)
+        .await
+        .unwrap();
+    assert_eq!(left, vec![recent, pending]);
+}
+
+#[tokio::test]
+async fn replay_should_flag_and_repair_corrupted_game() {
+    let _config = DB.lock().unwrap();
+    let (db, _app) = get_app().await;
+    let broker = get_broker(&db).await;
+    let id = create_game(&db, "replay_user", "replay_opponent").await;
+    let positions = replay(RuleSet::British, &["11-15".into(), "22-18".into()]).unwrap();
+    publish_update(&broker, id, "11-15", &positions[0].current_state, false).await;
+    publish_update(&broker, id, "22-18", "xxxxxxxxxxxx........oooooooooooo", true).await;
+    wait_for_moves(&db, id, 2).await;
+
+    let verification = verify_game(&db, &id).await.unwrap();
+    assert!(verification.discrepancies.contains(&Discrepancy::MoveState {
+        ply: 2,
+        stored: "xxxxxxxxxxxx........oooooooooooo".into(),
+        replayed: positions[1].current_state.clone(),
+    }));
+    assert!(verification.discrepancies.contains(&Discrepancy::NoncaptureMoves { stored: 0, replayed: 2 }));
+    assert!(!verification.discrepancies.iter().any(|discrepancy| matches!(discrepancy, Discrepancy::UserTurn { .. })));
+
+    repair_game(&db, verification).await.unwrap();
+    assert!(verify_game(&db, &id).await.unwrap().is_consistent());
+    let game = get_game(&db, &id).await.unwrap();
+    assert_eq!(game.current_state, positions[1].current_state);
+    assert_eq!(get_moves(&db, &id).await.unwrap()[1].current_state, positions[1].current_state);
+}
+
+#[tokio::test]
+async fn replay_should_report_illegal_moves() {
+    let _config = DB.lock().unwrap();
+    let (db, _app) = get_app().await;
+    let broker = get_broker(&db).await;
+    let id = create_game(&db, "illegal_user", "illegal_opponent").await;
+    publish_update(&broker, id, "11-15", "xxxxxxxxxx.x..x.....oooooooooooo", false).await;
+    publish_update(&broker, id, "15-19", "xxxxxxxxxx.x......x.oooooooooooo", true).await;
+    wait_for_moves(&db, id, 2).await;
+
+    let verification = verify_game(&db, &id).await.unwrap();
+    assert_eq!(verification.discrepancies, vec![Discrepancy::IllegalMove { ply: 2, notation: "15-19".into() }]);
+    repair_game(&db, verification).await.unwrap();
+    assert_eq!(get_game(&db, &id).await.unwrap().current_state, "xxxxxxxxxx.x......x.oooooooooooo");
+}
+
+#[tokio::test]
+async fn replay_should_skip_adjudicated_game() {
+    let _config = DB.lock().unwrap();
+    let (db, _app) = get_app().await;
+    let broker = get_broker(&db).await;
+    let id = create_game(&db, "adjudicated_replay_user", "adjudicated_replay_opponent").await;
+    publish_update(&broker, id, "11-15", "xxxxxxxxxxxx........oooooooooooo", false).await;
+    wait_for_moves(&db, id, 1).await;
+    sqlx::query("UPDATE game SET status = 1, adjudicated = TRUE WHERE id = $1").bind(id).execute(&db).await.unwrap();
+
+    let verification = verify_game(&db, &id).await.unwrap();
+    assert!(verification.adjudicated);
+    assert!(!verification.is_consistent());
+    let verification = repair_game(&db, verification).await.unwrap();
+    assert!(verification.adjudicated);
+    let game = get_game(&db, &id).await.unwrap();
+    assert_eq!(game.current_state, "xxxxxxxxxxxx........oooooooooooo");
+    assert_eq!(game.status, GameModelStatus::Won);
+}
+
+async fn queue_player(db: &PgPool, username: &str, time_control: TimeControl, time_initial: i64, rating: i32) -> i64 {
+    let user_id = create_account(db, username).await;
+    let entry = QueueModel { id: None, user_id, ruleset: GameRuleSet::British, time_control, time_initial, time_increment: 5, rating };
+    join_queue(db, entry).await.unwrap()
+}
+
+#[tokio::test]
+async fn queued_players_should_be_paired_into_accepted_game() {
+    let _config = DB.lock().unwrap();
+    let (db, _app) = get_app().await;
+    let broker = get_broker(&db).await;
+    let mut rx = subscribe(&broker, STATE_EXCHANGE, "match").await;
+    let id = queue_player(&db, "match_user", TimeControl::Fischer, 901, 1500).await;
+    queue_player(&db, "match_opponent", TimeControl::Fischer, 901, 1550).await;
+
+    let game_id = try_pair(&db, &id).await.unwrap().unwrap();
+
+    let game = get_game(&db, &game_id).await.unwrap();
+    assert_eq!(game.invitation, InvitationStatus::Accepted);
+    assert!(game.opponent_id.is_some());
+    let queued: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM matchmaking_queue q JOIN account a ON a.id = q.user_id WHERE a.username LIKE 'match_%'")
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(queued, 0);
+    let delivery = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+    let event = delivery.parse::<MatchEvent>().unwrap().payload;
+    assert_eq!(event.game_id, game_id as usize);
+    assert_eq!(event.user, "match_user");
+    assert_eq!(event.opponent, "match_opponent");
+}
+
+#[tokio::test]
+async fn rating_range_should_widen_with_waiting_time() {
+    let _config = DB.lock().unwrap();
+    let (db, _app) = get_app().await;
+    let id = queue_player(&db, "range_user", TimeControl::Fischer, 902, 1500).await;
+    queue_player(&db, "range_other_control", TimeControl::PerMove, 902, 1500).await;
+    let distant = queue_player(&db, "range_distant", TimeControl::Fischer, 902, 2300).await;
+
+    assert_eq!(try_pair(&db, &id).await.unwrap(), None);
+
+    sqlx::query("UPDATE matchmaking_queue SET joined_at = NOW() - INTERVAL '2 minutes' WHERE id = $1")
+        .bind(distant)
+        .execute(&db)
+        .await
+        .unwrap();
+    let game_id = try_pair(&db, &id).await.unwrap().unwrap();
+    let opponent: String = sqlx::query_scalar("SELECT a.username FROM game g JOIN account a ON a.id = g.opponent_id WHERE g.id = $1")
+        .bind(game_id)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(opponent, "range_distant");
+}
+
+#[tokio::test]
+async fn queued_player_should_poll_match_until_entry_expires() {
+    let _config = DB.lock().unwrap();
+    let (db, app) = get_app().await;
+    let broker = get_broker(&db).await;
+    let mut rx = subscribe(&broker, STATE_EXCHANGE, "match").await;
+    queue_player(&db, "poll_user", TimeControl::Fischer, 903, 1500).await;
+    queue_player(&db, "poll_opponent", TimeControl::Fischer, 903, 1500).await;
+    let expired = queue_player(&db, "poll_expired", TimeControl::Fischer, 904, 1500).await;
+    queue_player(&db, "poll_waiting", TimeControl::Fischer, 905, 1500).await;
+    sqlx::query("UPDATE matchmaking_queue SET joined_at = NOW() - INTERVAL '11 minutes' WHERE id = $1")
+        .bind(expired)
+        .execute(&db)
+        .await
+        .unwrap();
+
+    let games = pair_players(&db).await.unwrap();
+    assert!(games >= 1);
+    for _ in 0..games {
+        tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+    }
+
+    let (status, user) = get_json(app.clone(), "poll_user", "/matchmaking").await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, opponent) = get_json(app.clone(), "poll_opponent", "/matchmaking").await;
+    assert!(user["gameId"].is_i64());
+    assert_eq!(user["gameId"], opponent["gameId"]);
+    assert_eq!(user["queued"], false);
+    let (_, waiting) = get_json(app.clone(), "poll_waiting", "/matchmaking").await;
+    assert_eq!(waiting["queued"], true);
+    let (status, _) = get_json(app, "poll_expired", "/matchmaking").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn glicko_should_match_reference_example() {
+    // example from Glickman's description of Glicko-2
+    let player = Rating { rating: 1500.0, deviation: 200.0, volatility: 0.06 };
+    let results = [
+        (Rating { rating: 1400.0, deviation: 30.0, volatility: 0.06 }, 1.0),
+        (Rating { rating: 1550.0, deviation: 100.0, volatility: 0.06 }, 0.0),
+        (Rating { rating: 1700.0, deviation: 300.0, volatility: 0.06 }, 0.0),
+    ];
+
+    let rating = rate(&player, &results);
+    assert!((rating.rating - 1464.06).abs() < 0.01);
+    assert!((rating.deviation - 151.52).abs() < 0.01);
+    assert!((rating.volatility - 0.05999).abs() < 0.00001);
+}
+
+async fn create_rated_game(db: &PgPool, user: &str, opponent: &str, rated: bool) -> (i64, i64, i64) {
+    let user_id = create_account(db, user).await;
+    let opponent_id = create_account(db, opponent).await;
+    let game = GameModel { user_id, opponent_id: Some(opponent_id), invitation: InvitationStatus::Accepted, rated, ..Default::default() };
+    (save_game(db, game).await.unwrap(), user_id, opponent_id)
+}
+
+async fn publish_result(broker: &Arc<dyn Broker>, game_id: i64, status: GameStatus) {
+    let event = UpdateEvent { status, ..get_update(game_id, "", &GameModel::default().current_state, true) };
+    broker.publish(UPDATES_EXCHANGE, "update", encode(event, "game", None)).await.unwrap();
+}
+
+async fn count_rating_history(db: &PgPool, game_id: i64) -> i64 {
+    sqlx::query_scalar("SELECT COUNT(*) FROM rating_history WHERE game_id = $1")
+        .bind(game_id)
+        .fetch_one(db)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn finished_rated_game_should_update_ratings_once() {
+    let _config = DB.lock().unwrap();
+    let (db, app) = get_app().await;
+    let broker = get_broker(&db).await;
+    let (id, user_id, opponent_id) = create_rated_game(&db, "rated_user", "rated_opponent", true).await;
+
+    publish_result(&broker, id, GameStatus::Won).await;
+    publish_result(&broker, id, GameStatus::Won).await;
+    for _ in 0..50 {
+        if count_rating_history(&db, id).await == 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    assert_eq!(count_rating_history(&db, id).await, 2);
+    let winner = get_rating(&db, &user_id, GameRuleSet::British).await.unwrap().unwrap();
+    let loser = get_rating(&db, &opponent_id, GameRuleSet::British).await.unwrap().unwrap();
+    assert_eq!(winner.games, 1);
+    assert!(winner.rating > 1500.0);
+    assert!(loser.rating < 1500.0);
+    assert!(winner.deviation < Rating::default().deviation);
+
+    let token = get_token(&"rated_opponent".into(), false, &get_config().jwt_secret).unwrap();
+    let response = app
+        .oneshot(
+            Request::builder()
+            .method("GET")
+            .header("Authorization", format!("Bearer {}", token))
+            .uri("/user/rated_user/ratings")
+            .body(Body::empty())
+            .unwrap()
+            )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let bytes = to_bytes(response.into_body(), 10000).await.unwrap();
+    let profile: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(profile["ratings"][0]["games"], 1);
+    assert_eq!(profile["history"][0]["gameId"], id);
+    assert_eq!(profile["history"][0]["opponent"], "rated_opponent");
+    assert!(profile["history"][0]["change"].as_f64().unwrap() > 0.0);
+}
+
+#[tokio::test]
+async fn casual_game_should_not_change_ratings() {
+    let _config = DB.lock().unwrap();
+    let (db, _app) = get_app().await;
+    let broker = get_broker(&db).await;
+    let (id, user_id, _) = create_rated_game(&db, "casual_user", "casual_opponent", false).await;
+
+    publish_result(&broker, id, GameStatus::Drawn).await;
+    for _ in 0..50 {
+        if get_game(&db, &id).await.unwrap().status != GameModel::default().status {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    assert_eq!(count_rating_history(&db, id).await, 0);
+    assert!(get_rating(&db, &user_id, GameRuleSet::British).await.unwrap().is_none());
+}
+
+async fn create_finished_game(db: &PgPool, game: GameModel, plies: i32, finished_ago: i32) -> i64 {
+    let id = save_game(db, game).await.unwrap();
+    sqlx::query("UPDATE game SET finished_at = NOW() - make_interval(mins => $2) WHERE id = $1")
+        .bind(id)
+        .bind(finished_ago)
+        .execute(db)
+        .await
+        .unwrap();
+    for ply in 1..=plies {
+        sqlx::query("INSERT INTO move (game_id, ply, notation, current_state) VALUES ($1, $2, '11-15', '')")
+            .bind(id)
+            .bind(ply)
+            .execute(db)
+            .await
+            .unwrap();
+    }
+    id
+}
+
+async fn get_json(app: Router, username: &str, uri: &str) -> (StatusCode, serde_json::Value) {
+    send_json(app, "GET", username, uri).await
+}
+
+async fn send_json(app: Router, method: &str, username: &str, uri: &str) -> (StatusCode, serde_json::Value) {
+    let token = get_token(&username.into(), false, &get_config().jwt_secret).unwrap();
+    let response = app
+        .oneshot(
+            Request::builder()
+            .method(method)
+            .header("Authorization", format!("Bearer {}", token))
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap()
+            )
+        .await
+        .unwrap();
+    let status = response.status();
+    let bytes = to_bytes(response.into_body(), 100000).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap_or_default())
+}
+
+#[tokio::test]
+async fn stats_should_split_results_by_color_and_opponent() {
+    let _config = DB.lock().unwrap();
+    let (db, app) = get_app().await;
+    let user_id = create_account(&db, "stats_user").await;
+    let opponent_id = create_account(&db, "stats_opponent").await;
+    let finished = || GameModel { invitation: InvitationStatus::Accepted, status: GameModelStatus::Won, ..Default::default() };
+    // white win against a human, red loss in the opponent's game, red win against
+    // the random AI and the latest, white win against the counting AI
+    create_finished_game(&db, GameModel { user_id, opponent_id: Some(opponent_id), user_starts: true, ..finished() }, 3, 3).await;
+    create_finished_game(&db, GameModel { user_id: opponent_id, opponent_id: Some(user_id), user_starts: true, ..finished() }, 1, 2).await;
+    create_finished_game(&db, GameModel { user_id, game_type: GameType::AI, ai_type: AIType::Random, user_starts: false, ..finished() }, 2, 1).await;
+    create_finished_game(&db, GameModel { user_id, game_type: GameType::AI, ai_type: AIType::Counting, user_starts: true, ..finished() }, 0, 0).await;
+    save_game(&db, GameModel { user_id, opponent_id: Some(opponent_id), invitation: InvitationStatus::Accepted, ..Default::default() }).await.unwrap();
+
+    let (status, stats) = get_json(app, "stats_opponent", "/user/stats_user/stats").await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(stats["games"], 4);
+    assert_eq!(stats["wins"], 3);
+    assert_eq!(stats["losses"], 1);
+    assert_eq!(stats["white"]["wins"], 2);
+    assert_eq!(stats["red"]["games"], 2);
+    assert_eq!(stats["red"]["losses"], 1);
+    let opponents = stats["opponents"].as_array().unwrap();
+    let human = opponents.iter().find(|record| record["opponent"] == "Human").unwrap();
+    assert_eq!(human["games"], 2);
+    assert_eq!(human["losses"], 1);
+    let random = opponents.iter().find(|record| record["opponent"] == "Random").unwrap();
+    assert_eq!(random["wins"], 1);
+    assert_eq!(stats["averageLength"], 1.5);
+    assert_eq!(stats["streak"]["status"], "Won");
+    assert_eq!(stats["streak"]["games"], 2);
+}
+
+#[tokio::test]
+async fn leaderboard_should_be_paginated_by_rating() {
+    let _config = DB.lock().unwrap();
+    let (db, app) = get_app().await;
+    for (username, rating) in [("leader_first", 3300.0), ("leader_second", 3200.0), ("leader_third", 3100.0)] {
+        let user_id = create_account(&db, username).await;
+        sqlx::query("INSERT INTO rating (user_id, ruleset, rating, deviation, volatility, games) VALUES ($1, 0, $2, 60, 0.06, 10)")
+            .bind(user_id)
+            .bind(rating)
+            .execute(&db)
+            .await
+            .unwrap();
+    }
+
+    let (status, first) = get_json(app.clone(), "leader_first", "/leaderboard?ruleset=British&size=2").await;
+    let (_, second) = get_json(app.clone(), "leader_first", "/leaderboard?ruleset=British&size=2&page=1").await;
+    let (wrong_status, _) = get_json(app.clone(), "leader_first", "/leaderboard?size=1000").await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert!(first["total"].as_i64().unwrap() >= 3);
+    assert_eq!(first["players"][0]["username"], "leader_first");
+    assert_eq!(first["players"][1]["rank"], 2);
+    assert_eq!(second["players"][0]["username"], "leader_third");
+    assert_eq!(second["players"][0]["rank"], 3);
+    assert_eq!(wrong_status, StatusCode::BAD_REQUEST);
+
+    let (overflowing_status, _) = get_json(app, "leader_first", &format!("/leaderboard?size=100&page={}", i64::MAX)).await;
+    assert_eq!(overflowing_status, StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn round_robin_should_pair_everyone_once() {
+    let players = [1, 2, 3, 4, 5];
+    let mut played = HashSet::new();
+    let mut byes = Vec::new();
+
+    for round in 1..=round_robin_rounds(players.len()) {
+        let pairing = round_robin(&players, round);
+        assert_eq!(pairing.games.len(), 2);
+        for (white, red) in pairing.games {
+            assert!(played.insert(game_key(white, red)));
+        }
+        byes.push(pairing.bye.unwrap());
+    }
+    assert_eq!(played.len(), 10);
+    byes.sort();
+    assert_eq!(byes, players);
+}
+
+#[test]
+fn swiss_should_avoid_rematches() {
+    let player = |id, points, color_balance, had_bye| SwissPlayer { id, points, rating: 1500.0, color_balance, had_bye };
+    let players = [player(1, 1.0, 1, false), player(2, 0.0, -1, false), player(3, 1.0, 1, false), player(4, 0.0, -1, false), player(5, 2.0, 0, true)];
+    let played = HashSet::from([game_key(1, 2), game_key(3, 4), game_key(5, 1)]);
+
+    let pairing = swiss(&players, &played);
+
+    assert_eq!(pairing.bye, Some(4));
+    // 5 against 3 would leave the rematch of 1 and 2
+    assert_eq!(pairing.games, vec![(2, 5), (1, 3)]);
+}
+
+#[test]
+fn swiss_should_give_up_avoiding_rematches_when_none_is_possible() {
+    // two odd groups that have played everyone of the other group can't be paired without a rematch
+    let players: Vec<SwissPlayer> = (0..22).map(|id| SwissPlayer { id, points: 0.0, rating: 1500.0 - id as f64, color_balance: 0, had_bye: false }).collect();
+    let played: HashSet<(i64, i64)> = (0..22).flat_map(|a| (0..22).map(move |b| (a, b)))
+        .filter(|(a, b)| a < b && a % 2 != b % 2)
+        .collect();
+
+    let pairing = swiss(&players, &played);
+
+    assert_eq!(pairing.bye, None);
+    assert_eq!(pairing.games.len(), 11);
+}
+
+async fn post_json(app: Router, username: &str, uri: &str, body: serde_json::Value) -> (StatusCode, serde_json::Value) {
+    let token = get_token(&username.into(), false, &get_config().jwt_secret).unwrap();
+    let response = app
+        .oneshot(
+            Request::builder()
+            .method("POST")
+            .header("Authorization", format!("Bearer {}", token))
+            .header("Content-Type", "application/json")
+            .uri(uri)
+            .body(Body::from(body.to_string()))
+            .unwrap()
+            )
+        .await
+        .unwrap();
+    let status = response.status();
+    let bytes = to_bytes(response.into_body(), 100000).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap_or_default())
+}
+
+async fn finish_game(db: &PgPool, game_id: &serde_json::Value, status: GameModelStatus) {
+    sqlx::query("UPDATE game SET status = $2 WHERE id = $1")
+        .bind(game_id.as_i64().unwrap())
+        .bind(status)
+        .execute(db)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn swiss_tournament_should_pair_rounds_and_rank_players() {
+    let _config = DB.lock().unwrap();
+    let (db, app) = get_app().await;
+    for username in ["swiss_first", "swiss_second", "swiss_third"] {
+        create_account(&db, username).await;
+    }
+    let request = serde_json::json!({ "name": "Weekly", "rules": "British", "pairing": "Swiss", "rounds": 2 });
+    let (status, created) = post_json(app.clone(), "swiss_first", "/tournament", request).await;
+    assert_eq!(status, StatusCode::OK);
+    let uri = format!("/tournament/{}", created["tournamentId"]);
+    for username in ["swiss_first", "swiss_second", "swiss_third"] {
+        let (status, _) = post_json(app.clone(), username, &format!("{}/register", uri), serde_json::json!({})).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let (status, _) = post_json(app.clone(), "swiss_second", &format!("{}/register", uri), serde_json::json!({})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = post_json(app.clone(), "swiss_second", &format!("{}/round", uri), serde_json::json!({})).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (_, first) = post_json(app.clone(), "swiss_first", &format!("{}/round", uri), serde_json::json!({})).await;
+    assert_eq!(first["round"], 1);
+    assert_eq!(first["bye"], "swiss_third");
+    let game = get_game(&db, &first["games"][0].as_i64().unwrap()).await.unwrap();
+    assert_eq!(game.invitation, InvitationStatus::Accepted);
+    assert_eq!(game.round, Some(1));
+    let (status, _) = post_json(app.clone(), "swiss_first", &format!("{}/round", uri), serde_json::json!({})).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    finish_game(&db, &first["games"][0], GameModelStatus::Won).await;
+
+    let (_, second) = post_json(app.clone(), "swiss_first", &format!("{}/round", uri), serde_json::json!({})).await;
+    assert_eq!(second["round"], 2);
+    assert_eq!(second["bye"], "swiss_second");
+    finish_game(&db, &second["games"][0], GameModelStatus::Drawn).await;
+    let (_, last) = post_json(app.clone(), "swiss_first", &format!("{}/round", uri), serde_json::json!({})).await;
+    assert_eq!(last["finished"], true);
+
+    let (_, tournament) = get_json(app, "swiss_second", &uri).await;
+    assert_eq!(tournament["status"], "Finished");
+    let standings = tournament["standings"].as_array().unwrap();
+    let ranking: Vec<&str> = standings.iter().map(|standing| standing["username"].as_str().unwrap()).collect();
+    assert_eq!(ranking, vec!["swiss_first", "swiss_third", "swiss_second"]);
+    assert_eq!(standings[0]["points"], 1.5);
+    assert_eq!(standings[0]["buchholz"], 2.5);
+    assert_eq!(standings[2]["byes"], 1);
+}
+
+#[tokio::test]
+async fn creator_should_set_result_of_game_holding_up_round() {
+    let _config = DB.lock().unwrap();
+    let (db, app) = get_app().await;
+    let broker = get_broker(&db).await;
+    for username in ["noshow_creator", "noshow_player"] {
+        create_account(&db, username).await;
+    }
+    let request = serde_json::json!({ "name": "Evening", "rules": "British", "pairing": "RoundRobin" });
+    let (_, created) = post_json(app.clone(), "noshow_creator", "/tournament", request).await;
+    let uri = format!("/tournament/{}", created["tournamentId"]);
+    for username in ["noshow_creator", "noshow_player"] {
+        post_json(app.clone(), username, &format!("{}/register", uri), serde_json::json!({})).await;
+    }
+    let (_, first) = post_json(app.clone(), "noshow_creator", &format!("{}/round", uri), serde_json::json!({})).await;
+    let game_id = first["games"][0].as_i64().unwrap();
+    let result_uri = format!("{}/game/{}/result", uri, game_id);
+
+    let (status, _) = post_json(app.clone(), "noshow_player", &result_uri, serde_json::json!({ "result": "RedWon" })).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = post_json(app.clone(), "noshow_creator", &result_uri, serde_json::json!({ "result": "WhiteWon" })).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = post_json(app.clone(), "noshow_creator", &result_uri, serde_json::json!({ "result": "Drawn" })).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // a move made after the result doesn't reopen the game
+    publish_update(&broker, game_id, "11-15", "xxxxxxxx.xxxx.......oooooooooooo", false).await;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(get_moves(&db, &game_id).await.unwrap().is_empty());
+    assert_eq!(get_game(&db, &game_id).await.unwrap().status, GameModelStatus::Won);
+    let (_, last) = post_json(app.clone(), "noshow_creator", &format!("{}/round", uri), serde_json::json!({})).await;
+    assert_eq!(last["finished"], true);
+    let (_, tournament) = get_json(app, "noshow_player", &uri).await;
+    assert_eq!(tournament["standings"][0]["points"], 1.0);
+}
+
+#[tokio::test]
+async fn rematch_request_should_wait_for_game_result() {
+    let _config = DB.lock().unwrap();
+    let (db, _app) = get_app().await;
+    let broker = get_broker(&db).await;
+    let mut rx = subscribe(&broker, STATE_EXCHANGE, "rematch").await;
+    let id = create_played_game(&db, "early_rematch_user", Some("early_rematch_opponent"), GameModelStatus::NotFinished).await;
+
+    let request = RematchRequestEvent { game_id: id as usize, player: "early_rematch_user".into(), accepted: true };
+    broker.publish(GAMES_EXCHANGE, "rematch", encode(request, "game", None)).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    finish_game(&db, &serde_json::json!(id), GameModelStatus::Won).await;
+
+    let offered = next_rematch(&mut rx).await;
+    assert_eq!(offered.status, RematchStatus::Offered);
+    assert_eq!(offered.game_id, id as usize);
+}
+
+#[tokio::test]
+async fn rematch_request_failing_on_database_should_be_retried() {
+    let _config = DB.lock().unwrap();
+    let (db, _app) = get_app().await;
+    let broker = get_broker(&db).await;
+    let mut rx = subscribe(&broker, STATE_EXCHANGE, "rematch").await;
+    let id = create_played_game(&db, "failing_rematch_user", Some("failing_rematch_opponent"), GameModelStatus::Won).await;
+    sqlx::query("CREATE OR REPLACE FUNCTION fail_rematch() RETURNS trigger AS $$ BEGIN RAISE EXCEPTION 'database unavailable'; END $$ LANGUAGE plpgsql")
+        .execute(&db)
+        .await
+        .unwrap();
+    sqlx::query(&format!("CREATE TRIGGER fail_rematch BEFORE INSERT ON game FOR EACH ROW WHEN (NEW.rematch_of = {}) EXECUTE FUNCTION fail_rematch()", id))
+        .execute(&db)
+        .await
+        .unwrap();
+
+    let request = RematchRequestEvent { game_id: id as usize, player: "failing_rematch_user".into(), accepted: true };
+    broker.publish(GAMES_EXCHANGE, "rematch", encode(request, "game", None)).await.unwrap();
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    sqlx::query("DROP TRIGGER fail_rematch ON game").execute(&db).await.unwrap();
+
+    let offered = next_rematch(&mut rx).await;
+    assert_eq!(offered.status, RematchStatus::Offered);
+    assert_eq!(offered.game_id, id as usize);
+}
+
+async fn create_played_game(db: &PgPool, user: &str, opponent: Option<&str>, status: GameModelStatus) -> i64 {
+    let user_id = create_account(db, user).await;
+    let opponent_id = match opponent {
+        Some(opponent) => Some(create_account(db, opponent).await),
+        None => None,
+    };
+    let game_type = match opponent {
+        Some(_) => GameType::User,
+        None => GameType::AI,
+    };
+    let game = GameModel {
+        user_id,
+        opponent_id,
+        game_type,
+        invitation: InvitationStatus::Accepted,
+        user_starts: true,
+        time_control: TimeControl::Fischer,
+        time_initial: 300,
+        time_increment: 5,
+        status,
+        ..Default::default()
+    };
+    save_game(db, game).await.unwrap()
+}
+
+async fn next_rematch(rx: &mut UnboundedReceiver<Delivery>) -> RematchEvent {
+    let delivery = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+    delivery.parse::<RematchEvent>().unwrap().payload
+}
+
+#[tokio::test]
+async fn accepted_rematch_should_swap_colors() {
+    let _config = DB.lock().unwrap();
+    let (db, app) = get_app().await;
+    let broker = get_broker(&db).await;
+    let mut rx = subscribe(&broker, STATE_EXCHANGE, "rematch").await;
+    let id = create_played_game(&db, "rematch_user", Some("rematch_opponent"), GameModelStatus::Won).await;
+    let uri = format!("/game/{}/rematch", id);
+
+    let (status, _) = post_json(app.clone(), "rematch_user", &uri, serde_json::json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, again) = post_json(app.clone(), "rematch_user", &uri, serde_json::json!({})).await;
+    assert_eq!(again["status"], "Offered");
+    let rematch_id = again["gameId"].as_i64().unwrap();
+    let offered = next_rematch(&mut rx).await;
+    assert_eq!(offered.status, RematchStatus::Offered);
+    assert_eq!(offered.game_id, id as usize);
+    assert_eq!(offered.rematch_id, rematch_id as usize);
+
+    let accept = serde_json::json!({ "status": "Accepted" });
+    let (status, _) = post_json(app.clone(), "rematch_opponent", &format!("/game/{}/request", rematch_id), accept).await;
+    assert_eq!(status, StatusCode::OK);
+    let accepted = next_rematch(&mut rx).await;
+    assert_eq!(accepted.status, RematchStatus::Accepted);
+    assert_eq!(accepted.player, "rematch_opponent");
+
+    let rematch = get_game(&db, &rematch_id).await.unwrap();
+    assert_eq!(rematch.invitation, InvitationStatus::Accepted);
+    assert_eq!(rematch.rematch_of, Some(id));
+    assert!(!rematch.user_starts);
+    assert!(rematch.time_control == TimeControl::Fischer);
+    assert_eq!(rematch.time_initial, 300);
+    let (_, answer) = post_json(app, "rematch_opponent", &uri, serde_json::json!({})).await;
+    assert_eq!(answer["gameId"], rematch_id);
+    assert_eq!(answer["status"], "Accepted");
+}
+
+#[tokio::test]
+async fn declined_rematch_may_be_offered_again() {
+    let _config = DB.lock().unwrap();
+    let (db, app) = get_app().await;
+    let broker = get_broker(&db).await;
+    let mut rx = subscribe(&broker, STATE_EXCHANGE, "rematch").await;
+    let id = create_played_game(&db, "decline_user", Some("decline_opponent"), GameModelStatus::Drawn).await;
+    let uri = format!("/game/{}/rematch", id);
+
+    let (_, first) = post_json(app.clone(), "decline_opponent", &uri, serde_json::json!({})).await;
+    let first_id = first["gameId"].as_i64().unwrap();
+    let reject = serde_json::json!({ "status": "Rejected" });
+    let (status, _) = post_json(app.clone(), "decline_user", &format!("/game/{}/request", first_id), reject).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(next_rematch(&mut rx).await.status, RematchStatus::Offered);
+    assert_eq!(next_rematch(&mut rx).await.status, RematchStatus::Declined);
+    assert_eq!(get_game(&db, &first_id).await.unwrap().invitation, InvitationStatus::Rejected);
+
+    let (_, second) = post_json(app, "decline_opponent", &uri, serde_json::json!({})).await;
+    assert_eq!(second["status"], "Offered");
+    assert_ne!(second["gameId"], first_id);
+    assert!(get_game(&db, &second["gameId"].as_i64().unwrap()).await.unwrap().user_starts);
+}
+
+#[tokio::test]
+async fn rematch_should_need_finished_game_and_player() {
+    let _config = DB.lock().unwrap();
+    let (db, app) = get_app().await;
+    let id = create_played_game(&db, "unfinished_user", Some("unfinished_opponent"), GameModelStatus::NotFinished).await;
+    create_account(&db, "unfinished_spectator").await;
+    let uri = format!("/game/{}/rematch", id);
+
+    let (status, _) = post_json(app.clone(), "unfinished_user", &uri, serde_json::json!({})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    finish_game(&db, &serde_json::json!(id), GameModelStatus::Lost).await;
+    let (status, _) = post_json(app, "unfinished_spectator", &uri, serde_json::json!({})).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn rematch_against_ai_should_start_at_once() {
+    let _config = DB.lock().unwrap();
+    let (db, app) = get_app().await;
+    let id = create_played_game(&db, "rematch_ai_user", None, GameModelStatus::Lost).await;
+
+    let (_, rematch) = post_json(app, "rematch_ai_user", &format!("/game/{}/rematch", id), serde_json::json!({})).await;
+    assert_eq!(rematch["status"], "Accepted");
+    let rematch = get_game(&db, &rematch["gameId"].as_i64().unwrap()).await.unwrap();
+    assert_eq!(rematch.invitation, InvitationStatus::Accepted);
+    assert!(rematch.opponent_id.is_none());
+    assert!(!rematch.user_starts);
+}
+
+#[tokio::test]
+async fn invitation_past_expiry_should_not_be_accepted() {
+    let _config = DB.lock().unwrap();
+    let (db, app) = get_app().await;
+    let user_id = create_account(&db, "expiry_user").await;
+    let opponent_id = create_account(&db, "expiry_opponent").await;
+    let expires_at = Some(Utc::now() - chrono::Duration::minutes(1));
+    let id = save_game(&db, GameModel { user_id, opponent_id: Some(opponent_id), expires_at, ..Default::default() }).await.unwrap();
+
+    let (_, requests) = get_json(app.clone(), "expiry_opponent", "/game/request").await;
+    assert!(requests.as_array().unwrap().is_empty());
+    let accept = serde_json::json!({ "status": "Accepted" });
+    let (status, _) = post_json(app, "expiry_opponent", &format!("/game/{}/request", id), accept).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    assert!(sweep_invitations(&db).await.unwrap().contains(&id));
+    let game = get_game(&db, &id).await.unwrap();
+    assert_eq!(game.invitation, InvitationStatus::Expired);
+    assert!(matches!(check_issued(&game), Err(GameError::AlreadyExpired)));
+    assert!(!sweep_invitations(&db).await.unwrap().contains(&id));
+}
+
+#[tokio::test]
+async fn issuer_should_cancel_sent_invitation() {
+    let _config = DB.lock().unwrap();
+    let (db, app) = get_app().await;
+    create_account(&db, "cancel_user").await;
+    create_account(&db, "cancel_opponent").await;
+    let request = serde_json::json!({ "type": "User", "rules": "British", "opponent": "cancel_opponent" });
+    let (_, created) = post_json(app.clone(), "cancel_user", "/game", request).await;
+    let uri = format!("/game/{}/request", created["gameId"]);
+
+    let (_, sent) = get_json(app.clone(), "cancel_user", "/game/request/sent").await;
+    assert_eq!(sent[0]["id"], created["gameId"]);
+    assert!(sent[0]["expiresAt"].is_string());
+    let (status, _) = send_json(app.clone(), "DELETE", "cancel_opponent", &uri).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = send_json(app.clone(), "DELETE", "cancel_user", &uri).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send_json(app.clone(), "DELETE", "cancel_user", &uri).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let accept = serde_json::json!({ "status": "Accepted" });
+    let (status, _) = post_json(app.clone(), "cancel_opponent", &uri, accept).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (_, sent) = get_json(app, "cancel_user", "/game/request/sent").await;
+    assert!(sent.as_array().unwrap().is_empty());
+    assert_eq!(get_game(&db, &created["gameId"].as_i64().unwrap()).await.unwrap().invitation, InvitationStatus::Cancelled);
+}
+
+#[tokio::test]
+async fn cancelled_rematch_should_be_published() {
+    let _config = DB.lock().unwrap();
+    let (db, app) = get_app().await;
+    let broker = get_broker(&db).await;
+    let mut rx = subscribe(&broker, STATE_EXCHANGE, "rematch").await;
+    let id = create_played_game(&db, "cancel_rematch_user", Some("cancel_rematch_opponent"), GameModelStatus::Won).await;
+
+    let (_, offered) = post_json(app.clone(), "cancel_rematch_user", &format!("/game/{}/rematch", id), serde_json::json!({})).await;
+    let (status, _) = send_json(app, "DELETE", "cancel_rematch_user", &format!("/game/{}/request", offered["gameId"])).await;
+    assert_eq!(status, StatusCode::OK);
+
+    assert_eq!(next_rematch(&mut rx).await.status, RematchStatus::Offered);
+    let cancelled = next_rematch(&mut rx).await;
+    assert_eq!(cancelled.status, RematchStatus::Cancelled);
+    assert_eq!(cancelled.game_id, id as usize);
+}
+
+#[tokio::test]
+async fn expired_rematch_should_be_published() {
+    let _config = DB.lock().unwrap();
+    let (db, app) = get_app().await;
+    let broker = get_broker(&db).await;
+    let mut rx = subscribe(&broker, STATE_EXCHANGE, "rematch").await;
+    let id = create_played_game(&db, "expire_rematch_user", Some("expire_rematch_opponent"), GameModelStatus::Won).await;
+
+    let (_, offered) = post_json(app.clone(), "expire_rematch_user", &format!("/game/{}/rematch", id), serde_json::json!({})).await;
+    let rematch_id = offered["gameId"].as_i64().unwrap();
+    sqlx::query("UPDATE game SET expires_at = NOW() - INTERVAL '1 minute' WHERE id = $1")
+        .bind(rematch_id)
+        .execute(&db)
+        .await
+        .unwrap();
+    assert!(sweep_invitations(&db).await.unwrap().contains(&rematch_id));
+
+    assert_eq!(next_rematch(&mut rx).await.status, RematchStatus::Offered);
+    let expired = next_rematch(&mut rx).await;
+    assert_eq!(expired.status, RematchStatus::Expired);
+    assert_eq!(expired.game_id, id as usize);
+    assert_eq!(expired.player, "expire_rematch_user");
+
+    let accept = serde_json::json!({ "status": "Accepted" });
+    let (status, _) = post_json(app, "expire_rematch_opponent", &format!("/game/{}/request", rematch_id), accept).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(get_game(&db, &rematch_id).await.unwrap().invitation, InvitationStatus::Expired);
+}
@@ -0,0 +1,62 @@
+use axum::{http::StatusCode, response::{IntoResponse, Response}};
+
+use crate::{game::error::GameError, outbox::error::OutboxError};
+
+#[derive(Debug)]
+pub enum TournamentError {
+    Unknown,
+    NotFound,
+    NotCreator,
+    AlreadyRegistered,
+    RegistrationClosed,
+    NotEnoughPlayers,
+    RoundNotFinished,
+    AlreadyFinished,
+    WrongTimeControl,
+    GameNotFound,
+    GameFinished,
+}
+
+impl From<sqlx::Error> for TournamentError {
+    fn from(error: sqlx::Error) -> Self {
+        let err = error.to_string();
+        if err.contains("pk_tournament_player") {
+            return TournamentError::AlreadyRegistered
+        }
+        return TournamentError::Unknown
+    }
+}
+
+impl From<GameError> for TournamentError {
+    fn from(error: GameError) -> Self {
+        match error {
+            GameError::WrongTimeControl => TournamentError::WrongTimeControl,
+            _ => TournamentError::Unknown,
+        }
+    }
+}
+
+impl From<OutboxError> for TournamentError {
+    fn from(_error: OutboxError) -> Self {
+        return TournamentError::Unknown
+    }
+}
+
+impl IntoResponse for TournamentError {
+    fn into_response(self) -> Response {
+        match self {
+            TournamentError::Unknown => (StatusCode::INTERNAL_SERVER_ERROR, "Database error!"),
+            TournamentError::NotFound => (StatusCode::NOT_FOUND, "Tournament not found!"),
+            TournamentError::NotCreator => (StatusCode::FORBIDDEN, "Only the creator can manage rounds!"),
+            TournamentError::AlreadyRegistered => (StatusCode::BAD_REQUEST, "Already registered!"),
+            TournamentError::RegistrationClosed => (StatusCode::BAD_REQUEST, "Tournament has already started!"),
+            TournamentError::NotEnoughPlayers => (StatusCode::BAD_REQUEST, "Tournament needs at least 2 players!"),
+            TournamentError::RoundNotFinished => (StatusCode::CONFLICT, "Games of the round are not finished!"),
+            TournamentError::AlreadyFinished => (StatusCode::BAD_REQUEST, "Tournament has already finished!"),
+            TournamentError::WrongTimeControl => (StatusCode::BAD_REQUEST, "Time control must be positive!"),
+            TournamentError::GameNotFound => (StatusCode::NOT_FOUND, "Game is not part of the tournament!"),
+            TournamentError::GameFinished => (StatusCode::CONFLICT, "Game has already finished!"),
+        }
+        .into_response()
+    }
+}
@@ -0,0 +1,74 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::{game::{Rules, TimeControl}, tournament::{repository::{Bye, TournamentDetails, TournamentGame}, standings::Standing}};
+
+pub mod service;
+pub mod error;
+pub mod repository;
+pub mod pairing;
+pub mod standings;
+pub mod round;
+
+#[derive(Serialize, Deserialize)]
+pub enum PairingSystem {
+    RoundRobin, Swiss,
+}
+
+// Swiss tournaments without rounds play as many as needed for a single winner
+#[derive(Serialize, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct TournamentRequest {
+    #[validate(
+        length(min = 1, max = 255, message = "Name length must be between 1 and 255"),
+        required(message = "Name cannot be empty")
+        )]
+    pub name: Option<String>,
+    #[validate(required(message = "Rule set must be specified!"))]
+    pub rules: Option<Rules>,
+    pub time_control: Option<TimeControl>,
+    #[validate(required(message = "Pairing system must be specified!"))]
+    pub pairing: Option<PairingSystem>,
+    #[validate(range(min = 1, max = 50, message = "Number of rounds must be between 1 and 50"))]
+    pub rounds: Option<i32>,
+    pub rated: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewTournamentResponse {
+    pub tournament_id: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TournamentResponse {
+    #[serde(flatten)]
+    pub tournament: TournamentDetails,
+    pub players: Vec<String>,
+    pub standings: Vec<Standing>,
+    pub games: Vec<TournamentGame>,
+    pub byes: Vec<Bye>,
+}
+
+// result of a game set by the creator, e.g. a win for the player who turned up
+#[derive(Serialize, Deserialize)]
+pub enum GameResult {
+    WhiteWon, RedWon, Drawn,
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct ResultRequest {
+    pub result: GameResult,
+}
+
+// games of the started round, empty when the last round was already played
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoundResponse {
+    pub round: i32,
+    pub finished: bool,
+    pub games: Vec<i64>,
+    pub bye: Option<String>,
+}
@@ -0,0 +1,107 @@
+use std::collections::HashSet;
+
+// pairings tried before giving up on avoiding rematches, the search is exponential
+// when most players have already met
+const MAX_PAIRING_STEPS: usize = 10_000;
+
+// games of a round as (white, red), and the player left out if the number is odd
+#[derive(Debug, PartialEq)]
+pub struct Pairing {
+    pub games: Vec<(i64, i64)>,
+    pub bye: Option<i64>,
+}
+
+pub fn round_robin_rounds(players: usize) -> i32 {
+    (players + players % 2) as i32 - 1
+}
+
+// circle method: the first player stays, the others rotate by one place each round,
+// so everyone meets everyone once in round_robin_rounds rounds; rounds start at 1
+pub fn round_robin(players: &[i64], round: i32) -> Pairing {
+    let mut seats: Vec<Option<i64>> = players.iter().map(|id| Some(*id)).collect();
+    if seats.len() % 2 == 1 {
+        seats.push(None);
+    }
+    let n = seats.len();
+    let shift = (round - 1) as usize % (n - 1);
+    let mut arranged = vec![seats[0]];
+    arranged.extend((0..n - 1).map(|i| seats[1 + (i + shift) % (n - 1)]));
+
+    let mut pairing = Pairing { games: Vec::new(), bye: None };
+    for i in 0..n / 2 {
+        // colors alternate by table and round
+        let (white, red) = match (i + round as usize) % 2 {
+            0 => (arranged[n - 1 - i], arranged[i]),
+            _ => (arranged[i], arranged[n - 1 - i]),
+        };
+        match (white, red) {
+            (Some(white), Some(red)) => pairing.games.push((white, red)),
+            (Some(player), None) | (None, Some(player)) => pairing.bye = Some(player),
+            (None, None) => {},
+        }
+    }
+    pairing
+}
+
+// rounds needed for a single player to win all games
+pub fn swiss_rounds(players: usize) -> i32 {
+    (usize::BITS - (players.max(2) - 1).leading_zeros()) as i32
+}
+
+pub struct SwissPlayer {
+    pub id: i64,
+    pub points: f64,
+    pub rating: f64,
+    // whites minus reds played so far
+    pub color_balance: i32,
+    pub had_bye: bool,
+}
+
+// players with equal points meet first, higher ratings break ties; rematches are
+// avoided while possible, and the bye goes to the lowest player who hasn't had one
+pub fn swiss(players: &[SwissPlayer], played: &HashSet<(i64, i64)>) -> Pairing {
+    let mut ranked: Vec<&SwissPlayer> = players.iter().collect();
+    ranked.sort_by(|a, b| b.points.total_cmp(&a.points).then(b.rating.total_cmp(&a.rating)).then(a.id.cmp(&b.id)));
+
+    let mut bye = None;
+    if ranked.len() % 2 == 1 {
+        let index = ranked.iter().rposition(|player| !player.had_bye).unwrap_or(ranked.len() - 1);
+        bye = Some(ranked.remove(index).id);
+    }
+
+    let mut steps = MAX_PAIRING_STEPS;
+    let pairs = pair_without_rematches(&ranked, played, &mut steps)
+        .unwrap_or_else(|| ranked.chunks(2).map(|pair| (pair[0], pair[1])).collect());
+    let games = pairs.into_iter().map(|(a, b)| match a.color_balance > b.color_balance {
+        true => (b.id, a.id),
+        false => (a.id, b.id),
+    }).collect();
+    Pairing { games, bye }
+}
+
+fn pair_without_rematches<'a>(players: &[&'a SwissPlayer], played: &HashSet<(i64, i64)>, steps: &mut usize) -> Option<Vec<(&'a SwissPlayer, &'a SwissPlayer)>> {
+    let Some((first, rest)) = players.split_first() else {
+        return Some(Vec::new())
+    };
+    for (i, opponent) in rest.iter().enumerate() {
+        if played.contains(&game_key(first.id, opponent.id)) {
+            continue;
+        }
+        if *steps == 0 {
+            return None
+        }
+        *steps -= 1;
+        let mut remaining = rest.to_vec();
+        remaining.remove(i);
+        if let Some(mut pairs) = pair_without_rematches(&remaining, played, steps) {
+            pairs.insert(0, (*first, *opponent));
+            return Some(pairs)
+        }
+    }
+    None
+}
+
+// the same for both colors
+pub fn game_key(player: i64, opponent: i64) -> (i64, i64) {
+    (player.min(opponent), player.max(opponent))
+}
@@ -0,0 +1,233 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{postgres::PgQueryResult, Executor, PgPool, Postgres};
+use tracing::debug;
+
+use crate::{game::repository::{GameStatus, RuleSet, TimeControl}, rating::glicko::Rating, tournament::error::TournamentError};
+
+pub async fn save_tournament(db: &PgPool, tournament: TournamentModel) -> Result<i64, TournamentError> {
+    sqlx::query_scalar("INSERT INTO tournament
+                        (name, creator_id, ruleset, time_control, time_initial, time_increment, pairing, rated, status, rounds)
+                        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+                        RETURNING id")
+        .bind(&tournament.name)
+        .bind(&tournament.creator_id)
+        .bind(&tournament.ruleset)
+        .bind(&tournament.time_control)
+        .bind(&tournament.time_initial)
+        .bind(&tournament.time_increment)
+        .bind(&tournament.pairing)
+        .bind(&tournament.rated)
+        .bind(&tournament.status)
+        .bind(&tournament.rounds)
+        .fetch_one(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot add tournament to db!");
+            debug!("{}", err);
+            TournamentError::from(err)
+        })
+}
+
+pub async fn get_tournament(db: &PgPool, id: &i64) -> Result<TournamentDetails, TournamentError> {
+    let result = sqlx::query_as::<Postgres, TournamentDetails>("SELECT t.*, a.username AS creator
+                                                               FROM tournament t
+                                                               JOIN account a ON a.id = t.creator_id
+                                                               WHERE t.id = $1")
+        .bind(id)
+        .fetch_optional(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot get tournament from db!");
+            debug!("{}", err);
+            TournamentError::from(err)
+        });
+
+    match result {
+        Ok(None) => Err(TournamentError::NotFound),
+        Ok(Some(tournament)) => Ok(tournament),
+        Err(err) => Err(err),
+    }
+}
+
+// keeps registrations and rounds of the tournament in line until the transaction ends
+pub async fn lock_tournament<'c, E: Executor<'c, Database = Postgres>>(db: E, id: &i64) -> Result<TournamentModel, TournamentError> {
+    let result = sqlx::query_as::<Postgres, TournamentModel>("SELECT * FROM tournament WHERE id = $1 FOR UPDATE")
+        .bind(id)
+        .fetch_optional(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot lock tournament in db!");
+            debug!("{}", err);
+            TournamentError::from(err)
+        });
+
+    match result {
+        Ok(None) => Err(TournamentError::NotFound),
+        Ok(Some(tournament)) => Ok(tournament),
+        Err(err) => Err(err),
+    }
+}
+
+pub async fn update_round<'c, E: Executor<'c, Database = Postgres>>(db: E, tournament: &TournamentModel) -> Result<PgQueryResult, TournamentError> {
+    sqlx::query("UPDATE tournament SET status = $2, rounds = $3, current_round = $4 WHERE id = $1")
+        .bind(&tournament.id)
+        .bind(&tournament.status)
+        .bind(&tournament.rounds)
+        .bind(&tournament.current_round)
+        .execute(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot update tournament in db!");
+            debug!("{}", err);
+            TournamentError::from(err)
+        })
+}
+
+pub async fn register_player<'c, E: Executor<'c, Database = Postgres>>(db: E, tournament_id: &i64, user_id: &i64) -> Result<PgQueryResult, TournamentError> {
+    sqlx::query("INSERT INTO tournament_player (tournament_id, user_id) VALUES ($1, $2)")
+        .bind(tournament_id)
+        .bind(user_id)
+        .execute(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot register player in db!");
+            debug!("{}", err);
+            TournamentError::from(err)
+        })
+}
+
+// in order of registration, with the rating for the rule set of the tournament
+pub async fn get_players<'c, E: Executor<'c, Database = Postgres>>(db: E, tournament_id: &i64) -> Result<Vec<PlayerDetails>, TournamentError> {
+    sqlx::query_as::<Postgres, PlayerDetails>("SELECT p.user_id, a.username, COALESCE(r.rating, $2) AS rating
+                                              FROM tournament_player p
+                                              JOIN tournament t ON t.id = p.tournament_id
+                                              JOIN account a ON a.id = p.user_id
+                                              LEFT JOIN rating r ON r.user_id = p.user_id AND r.ruleset = t.ruleset
+                                              WHERE p.tournament_id = $1
+                                              ORDER BY p.registered_at ASC, p.user_id ASC")
+        .bind(tournament_id)
+        .bind(Rating::default().rating)
+        .fetch_all(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot get tournament players from db!");
+            debug!("{}", err);
+            TournamentError::from(err)
+        })
+}
+
+// white is the user of the game, red the opponent
+pub async fn get_tournament_games<'c, E: Executor<'c, Database = Postgres>>(db: E, tournament_id: &i64) -> Result<Vec<TournamentGame>, TournamentError> {
+    sqlx::query_as::<Postgres, TournamentGame>("SELECT id, round, user_id AS white_id, opponent_id AS red_id, status
+                                               FROM game
+                                               WHERE tournament_id = $1
+                                               ORDER BY round ASC, id ASC")
+        .bind(tournament_id)
+        .fetch_all(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot get tournament games from db!");
+            debug!("{}", err);
+            TournamentError::from(err)
+        })
+}
+
+pub async fn save_bye<'c, E: Executor<'c, Database = Postgres>>(db: E, tournament_id: &i64, round: i32, user_id: &i64) -> Result<PgQueryResult, TournamentError> {
+    sqlx::query("INSERT INTO tournament_bye (tournament_id, round, user_id) VALUES ($1, $2, $3)")
+        .bind(tournament_id)
+        .bind(round)
+        .bind(user_id)
+        .execute(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot add bye to db!");
+            debug!("{}", err);
+            TournamentError::from(err)
+        })
+}
+
+pub async fn get_byes<'c, E: Executor<'c, Database = Postgres>>(db: E, tournament_id: &i64) -> Result<Vec<Bye>, TournamentError> {
+    sqlx::query_as::<Postgres, Bye>("SELECT round, user_id FROM tournament_bye WHERE tournament_id = $1 ORDER BY round ASC")
+        .bind(tournament_id)
+        .fetch_all(db)
+        .await
+        .map_err(|err: sqlx::Error| {
+            debug!("Cannot get byes from db!");
+            debug!("{}", err);
+            TournamentError::from(err)
+        })
+}
+
+#[derive(Serialize, Deserialize, sqlx::Type, Clone, Copy, PartialEq)]
+#[repr(i16)]
+pub enum PairingSystem {
+    RoundRobin = 0,
+    Swiss = 1,
+}
+
+#[derive(Serialize, Deserialize, sqlx::Type, Clone, Copy, PartialEq)]
+#[repr(i16)]
+pub enum TournamentStatus {
+    Registration = 0,
+    Running = 1,
+    Finished = 2,
+}
+
+// rounds are known once the tournament starts, unless set for Swiss
+#[derive(Serialize, Deserialize, sqlx::FromRow)]
+pub struct TournamentModel {
+    pub id: Option<i64>,
+    pub name: String,
+    pub creator_id: i64,
+    pub ruleset: RuleSet,
+    pub time_control: TimeControl,
+    pub time_initial: i64,
+    pub time_increment: i64,
+    pub pairing: PairingSystem,
+    pub rated: bool,
+    pub status: TournamentStatus,
+    pub rounds: i32,
+    pub current_round: i32,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct TournamentDetails {
+    pub id: i64,
+    pub name: String,
+    pub creator: String,
+    pub ruleset: RuleSet,
+    pub time_control: TimeControl,
+    pub time_initial: i64,
+    pub time_increment: i64,
+    pub pairing: PairingSystem,
+    pub rated: bool,
+    pub status: TournamentStatus,
+    pub rounds: i32,
+    pub current_round: i32,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(sqlx::FromRow)]
+pub struct PlayerDetails {
+    pub user_id: i64,
+    pub username: String,
+    pub rating: f64,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct TournamentGame {
+    pub id: i64,
+    pub round: i32,
+    pub white_id: i64,
+    pub red_id: i64,
+    pub status: GameStatus,
+}
+
+#[derive(Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct Bye {
+    pub round: i32,
+    pub user_id: i64,
+}
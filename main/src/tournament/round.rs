@@ -0,0 +1,132 @@
+use std::collections::{HashMap, HashSet};
+
+use protocol::{envelope::encode, game::MatchEvent};
+use sqlx::PgPool;
+use tracing::{error, info};
+
+use crate::{game::{error::{GameError, MoveError}, repository::{adjudicate_game, get_game, lock_game, save_game, GameModel, GameStatus, GameType, InvitationStatus}}, outbox::repository::enqueue, rabbit::{PRODUCER, STATE_EXCHANGE}, rating::update::rate_game, tournament::{error::TournamentError, pairing::{game_key, round_robin, round_robin_rounds, swiss, swiss_rounds, Pairing, SwissPlayer}, repository::{get_byes, get_players, get_tournament_games, lock_tournament, save_bye, update_round, PairingSystem, TournamentStatus}, standings::get_standings, RoundResponse}};
+
+// starts the next round once all games of the current one are finished, or finishes
+// the tournament after the last round; the games and the messages telling their
+// players are saved in one transaction, so a round is never started twice
+pub async fn start_round(db: &PgPool, tournament_id: &i64, user_id: &i64) -> Result<RoundResponse, TournamentError> {
+    let mut tx = db.begin().await?;
+    let mut tournament = lock_tournament(&mut *tx, tournament_id).await?;
+    if tournament.creator_id != *user_id {
+        return Err(TournamentError::NotCreator)
+    }
+    if tournament.status == TournamentStatus::Finished {
+        return Err(TournamentError::AlreadyFinished)
+    }
+    let players = get_players(&mut *tx, tournament_id).await?;
+    let games = get_tournament_games(&mut *tx, tournament_id).await?;
+    let byes = get_byes(&mut *tx, tournament_id).await?;
+    if games.iter().any(|game| game.status == GameStatus::NotFinished) {
+        return Err(TournamentError::RoundNotFinished)
+    }
+
+    if tournament.status == TournamentStatus::Registration {
+        if players.len() < 2 {
+            return Err(TournamentError::NotEnoughPlayers)
+        }
+        tournament.rounds = match tournament.pairing {
+            PairingSystem::RoundRobin => round_robin_rounds(players.len()),
+            PairingSystem::Swiss if tournament.rounds > 0 => tournament.rounds,
+            PairingSystem::Swiss => swiss_rounds(players.len()),
+        };
+        tournament.status = TournamentStatus::Running;
+    }
+    if tournament.current_round >= tournament.rounds {
+        tournament.status = TournamentStatus::Finished;
+        update_round(&mut *tx, &tournament).await?;
+        tx.commit().await?;
+        info!("Tournament {} finished", tournament_id);
+        return Ok(RoundResponse { round: tournament.current_round, finished: true, games: Vec::new(), bye: None })
+    }
+    tournament.current_round += 1;
+    let round = tournament.current_round;
+
+    let pairing = match tournament.pairing {
+        PairingSystem::RoundRobin => {
+            let ids: Vec<i64> = players.iter().map(|player| player.user_id).collect();
+            round_robin(&ids, round)
+        },
+        PairingSystem::Swiss => {
+            let standings = get_standings(tournament.pairing, &players, &games, &byes);
+            let points: HashMap<i64, f64> = standings.iter().map(|standing| (standing.user_id, standing.points)).collect();
+            let played: HashSet<(i64, i64)> = games.iter().map(|game| game_key(game.white_id, game.red_id)).collect();
+            let swiss_players: Vec<SwissPlayer> = players.iter().map(|player| SwissPlayer {
+                id: player.user_id,
+                points: points.get(&player.user_id).copied().unwrap_or_default(),
+                rating: player.rating,
+                color_balance: games.iter().filter(|game| game.white_id == player.user_id).count() as i32
+                    - games.iter().filter(|game| game.red_id == player.user_id).count() as i32,
+                had_bye: byes.iter().any(|bye| bye.user_id == player.user_id),
+            }).collect();
+            swiss(&swiss_players, &played)
+        },
+    };
+
+    let usernames: HashMap<i64, String> = players.iter().map(|player| (player.user_id, player.username.clone())).collect();
+    let Pairing { games: pairs, bye } = pairing;
+    let mut game_ids = Vec::new();
+    for (white, red) in pairs {
+        let game_id = save_game(&mut *tx,
+            GameModel {
+                user_id: white,
+                opponent_id: Some(red),
+                ruleset: tournament.ruleset,
+                game_type: GameType::User,
+                invitation: InvitationStatus::Accepted,
+                user_starts: true,
+                user_turn: true,
+                time_control: tournament.time_control,
+                time_initial: tournament.time_initial,
+                time_increment: tournament.time_increment,
+                rated: tournament.rated,
+                tournament_id: Some(*tournament_id),
+                round: Some(round),
+                ..Default::default()
+            }).await?;
+        let event = MatchEvent { game_id: game_id as usize, user: usernames[&white].clone(), opponent: usernames[&red].clone() };
+        enqueue(&mut *tx, STATE_EXCHANGE, "match", encode(event, PRODUCER, None)).await?;
+        game_ids.push(game_id);
+    }
+    if let Some(bye) = bye {
+        save_bye(&mut *tx, tournament_id, round, &bye).await?;
+    }
+    update_round(&mut *tx, &tournament).await?;
+    tx.commit().await?;
+
+    info!("Started round {} of tournament {} with {} games", round, tournament_id, game_ids.len());
+    Ok(RoundResponse { round, finished: false, games: game_ids, bye: bye.map(|bye| usernames[&bye].clone()) })
+}
+
+// lets the creator finish a game that holds up the round, e.g. when a player doesn't turn up;
+// white is the user of the game, so its status is the result for white
+pub async fn set_result(db: &PgPool, tournament_id: &i64, game_id: &i64, user_id: &i64, status: GameStatus) -> Result<(), TournamentError> {
+    let mut tx = db.begin().await?;
+    let tournament = lock_tournament(&mut *tx, tournament_id).await?;
+    if tournament.creator_id != *user_id {
+        return Err(TournamentError::NotCreator)
+    }
+    match lock_game(&mut *tx, game_id).await {
+        Err(MoveError::NoGame) => return Err(TournamentError::GameNotFound),
+        result => result.map_err(GameError::from)?,
+    };
+    let game = get_game(&mut *tx, game_id).await?;
+    if game.tournament_id != Some(*tournament_id) {
+        return Err(TournamentError::GameNotFound)
+    }
+    match adjudicate_game(&mut *tx, game_id, status).await {
+        Err(GameError::NotFound) => return Err(TournamentError::GameFinished),
+        result => result?,
+    };
+    tx.commit().await?;
+
+    info!("Result of game {} in tournament {} set to {:?}", game_id, tournament_id, status);
+    if let Err(err) = rate_game(db, game_id).await {
+        error!("Cannot update ratings after game {}: {:?}", game_id, err);
+    }
+    Ok(())
+}
@@ -0,0 +1,173 @@
+use std::sync::Arc;
+
+use axum::{extract::{Path, State}, response::{IntoResponse, Response}, Json};
+use tracing::{debug, info};
+
+use crate::{game::{self, repository::{GameStatus, RuleSet}, service::get_time_control}, security::UserData, tournament::{self, error::TournamentError, repository::{get_byes, get_players, get_tournament, get_tournament_games, lock_tournament, register_player, save_tournament, PairingSystem, TournamentModel, TournamentStatus}, round::{set_result, start_round}, standings::get_standings, GameResult, NewTournamentResponse, ResultRequest, TournamentRequest, TournamentResponse}, user::repository::get_user, validation::ValidatedJson, AppState};
+
+pub async fn new_tournament(State(state): State<Arc<AppState>>, user: UserData, ValidatedJson(request): ValidatedJson<TournamentRequest>) -> Response {
+    info!("Creating new tournament requested…");
+    let username = user.username;
+    let ruleset = match request.rules.unwrap() {
+        game::Rules::British => RuleSet::British,
+    };
+    let pairing = match request.pairing.unwrap() {
+        tournament::PairingSystem::RoundRobin => PairingSystem::RoundRobin,
+        tournament::PairingSystem::Swiss => PairingSystem::Swiss,
+    };
+    let (time_control, time_initial, time_increment) = match get_time_control(request.time_control) {
+        Ok(time_control) => time_control,
+        Err(err) => return TournamentError::from(err).into_response(),
+    };
+
+    debug!("Trying to get user {} from db…", username);
+    let query_result = get_user(&state.db, &username).await;
+
+    if let Err(err) = query_result {
+        return err.into_response()
+    }
+    let user = query_result.unwrap();
+
+    debug!("Trying to add tournament to db…");
+    let query_result = save_tournament(&state.db,
+        TournamentModel {
+            id: None,
+            name: request.name.unwrap(),
+            creator_id: user.id,
+            ruleset,
+            time_control,
+            time_initial,
+            time_increment,
+            pairing,
+            rated: request.rated.unwrap_or(false),
+            status: TournamentStatus::Registration,
+            rounds: match pairing {
+                PairingSystem::RoundRobin => 0,
+                PairingSystem::Swiss => request.rounds.unwrap_or(0),
+            },
+            current_round: 0,
+        }).await;
+
+    if let Err(err) = query_result {
+        return err.into_response()
+    }
+    let id = query_result.unwrap();
+
+    info!("Tournament {} succesfully created.", id);
+
+    return Json(NewTournamentResponse { tournament_id: id }).into_response()
+}
+
+pub async fn tournament(State(state): State<Arc<AppState>>, _user: UserData, Path(id): Path<i64>) -> Response {
+    info!("Getting tournament requested…");
+
+    debug!("Trying to get tournament {} from db…", id);
+    let query_result = get_tournament(&state.db, &id).await;
+
+    if let Err(err) = query_result {
+        return err.into_response()
+    }
+    let tournament = query_result.unwrap();
+
+    debug!("Trying to fetch players, games and byes of tournament {} from db…", id);
+    let players = get_players(&state.db, &id).await;
+    let games = get_tournament_games(&state.db, &id).await;
+    let byes = get_byes(&state.db, &id).await;
+    let (players, games, byes) = match (players, games, byes) {
+        (Ok(players), Ok(games), Ok(byes)) => (players, games, byes),
+        (Err(err), _, _) | (_, Err(err), _) | (_, _, Err(err)) => return err.into_response(),
+    };
+    let standings = get_standings(tournament.pairing, &players, &games, &byes);
+    let players = players.into_iter().map(|player| player.username).collect();
+
+    return Json(TournamentResponse { tournament, players, standings, games, byes }).into_response()
+}
+
+pub async fn join_tournament(State(state): State<Arc<AppState>>, user: UserData, Path(id): Path<i64>) -> Response {
+    info!("Tournament registration requested…");
+    let username = user.username;
+
+    debug!("Trying to get user {} from db…", username);
+    let query_result = get_user(&state.db, &username).await;
+
+    if let Err(err) = query_result {
+        return err.into_response()
+    }
+    let user = query_result.unwrap();
+
+    debug!("Trying to register user {} for tournament {}…", username, id);
+    let query_result = register_for_tournament(&state, &id, &user.id).await;
+
+    if let Err(err) = query_result {
+        return err.into_response()
+    }
+
+    info!("User {} registered for tournament {}.", username, id);
+
+    return Json(NewTournamentResponse { tournament_id: id }).into_response()
+}
+
+// the tournament is locked, so no one registers while the first round is paired
+async fn register_for_tournament(state: &Arc<AppState>, id: &i64, user_id: &i64) -> Result<(), TournamentError> {
+    let mut tx = state.db.begin().await?;
+    let tournament = lock_tournament(&mut *tx, id).await?;
+    if tournament.status != TournamentStatus::Registration {
+        return Err(TournamentError::RegistrationClosed)
+    }
+    register_player(&mut *tx, id, user_id).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn next_round(State(state): State<Arc<AppState>>, user: UserData, Path(id): Path<i64>) -> Response {
+    info!("Starting next round of tournament requested…");
+    let username = user.username;
+
+    debug!("Trying to get user {} from db…", username);
+    let query_result = get_user(&state.db, &username).await;
+
+    if let Err(err) = query_result {
+        return err.into_response()
+    }
+    let user = query_result.unwrap();
+
+    debug!("Trying to start next round of tournament {}…", id);
+    let query_result = start_round(&state.db, &id, &user.id).await;
+
+    if let Err(err) = query_result {
+        return err.into_response()
+    }
+    let round = query_result.unwrap();
+    if !round.games.is_empty() {
+        state.outbox.notify_one();
+    }
+
+    return Json(round).into_response()
+}
+
+pub async fn game_result(State(state): State<Arc<AppState>>, user: UserData, Path((id, game_id)): Path<(i64, i64)>, ValidatedJson(request): ValidatedJson<ResultRequest>) -> Response {
+    info!("Setting result of tournament game requested…");
+    let username = user.username;
+    let status = match request.result {
+        GameResult::WhiteWon => GameStatus::Won,
+        GameResult::RedWon => GameStatus::Lost,
+        GameResult::Drawn => GameStatus::Drawn,
+    };
+
+    debug!("Trying to get user {} from db…", username);
+    let query_result = get_user(&state.db, &username).await;
+
+    if let Err(err) = query_result {
+        return err.into_response()
+    }
+    let user = query_result.unwrap();
+
+    debug!("Trying to set result of game {} in tournament {}…", game_id, id);
+    let query_result = set_result(&state.db, &id, &game_id, &user.id, status).await;
+
+    if let Err(err) = query_result {
+        return err.into_response()
+    }
+
+    return Json(NewTournamentResponse { tournament_id: id }).into_response()
+}
@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{game::repository::GameStatus, tournament::repository::{Bye, PairingSystem, PlayerDetails, TournamentGame}};
+
+// a win and a Swiss bye score 1, a draw 0.5; Buchholz sums the points of all
+// opponents, Sonneborn-Berger those of beaten opponents and half of drawn ones
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Standing {
+    pub rank: usize,
+    pub user_id: i64,
+    pub username: String,
+    pub points: f64,
+    pub games: i32,
+    pub wins: i32,
+    pub draws: i32,
+    pub losses: i32,
+    pub byes: i32,
+    pub buchholz: f64,
+    pub sonneborn_berger: f64,
+}
+
+// Swiss ranks ties by Buchholz first, round-robin by Sonneborn-Berger, since all its
+// players meet the same opponents; ties left keep the order of registration and
+// unfinished games don't count yet
+pub fn get_standings(pairing: PairingSystem, players: &[PlayerDetails], games: &[TournamentGame], byes: &[Bye]) -> Vec<Standing> {
+    let mut standings: Vec<Standing> = players.iter().map(|player| Standing {
+        rank: 0,
+        user_id: player.user_id,
+        username: player.username.clone(),
+        points: 0.0,
+        games: 0,
+        wins: 0,
+        draws: 0,
+        losses: 0,
+        byes: 0,
+        buchholz: 0.0,
+        sonneborn_berger: 0.0,
+    }).collect();
+    let index: HashMap<i64, usize> = standings.iter().enumerate().map(|(i, standing)| (standing.user_id, i)).collect();
+    let results: Vec<(usize, usize, f64)> = games.iter().filter_map(|game| {
+        let white_score = match game.status {
+            GameStatus::NotFinished => return None,
+            GameStatus::Won => 1.0,
+            GameStatus::Lost => 0.0,
+            GameStatus::Drawn => 0.5,
+        };
+        Some((*index.get(&game.white_id)?, *index.get(&game.red_id)?, white_score))
+    }).collect();
+
+    for (white, red, white_score) in &results {
+        for (player, score) in [(*white, *white_score), (*red, 1.0 - white_score)] {
+            let standing = &mut standings[player];
+            standing.points += score;
+            standing.games += 1;
+            if score == 1.0 {
+                standing.wins += 1;
+            } else if score == 0.0 {
+                standing.losses += 1;
+            } else {
+                standing.draws += 1;
+            }
+        }
+    }
+    for bye in byes {
+        if let Some(player) = index.get(&bye.user_id) {
+            standings[*player].byes += 1;
+            if pairing == PairingSystem::Swiss {
+                standings[*player].points += 1.0;
+            }
+        }
+    }
+
+    let points: Vec<f64> = standings.iter().map(|standing| standing.points).collect();
+    for (white, red, white_score) in &results {
+        for (player, opponent, score) in [(*white, *red, *white_score), (*red, *white, 1.0 - white_score)] {
+            standings[player].buchholz += points[opponent];
+            standings[player].sonneborn_berger += score * points[opponent];
+        }
+    }
+
+    standings.sort_by(|a, b| {
+        let tie_breaks = match pairing {
+            PairingSystem::Swiss => b.buchholz.total_cmp(&a.buchholz).then(b.sonneborn_berger.total_cmp(&a.sonneborn_berger)),
+            PairingSystem::RoundRobin => b.sonneborn_berger.total_cmp(&a.sonneborn_berger).then(b.buchholz.total_cmp(&a.buchholz)),
+        };
+        b.points.total_cmp(&a.points)
+            .then(tie_breaks)
+            .then(b.wins.cmp(&a.wins))
+    });
+    for (i, standing) in standings.iter_mut().enumerate() {
+        standing.rank = i + 1;
+    }
+    standings
+}
@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use lapin::{message::{Delivery as AmqpDelivery, DeliveryResult}, options::{BasicAckOptions, BasicConsumeOptions, BasicNackOptions, ConfirmSelectOptions, ExchangeDeclareOptions, QueueBindOptions, QueueDeclareOptions}, publisher_confirm::Confirmation, types::{AMQPValue, FieldTable, ShortString}, BasicProperties, Channel, ExchangeKind};
+use tracing::{debug, error, warn};
+
+use super::{dead_letter_queue, Broker, BrokerError, Delivery, Handler, Rejection, DEAD_LETTER_EXCHANGE, MAX_RETRIES, QUEUE_HEADER, REASON_HEADER, RETRIES_HEADER, RETRY_DELAY};
+
+pub struct LapinBroker {
+    channel: Channel,
+}
+
+// messages are persistent and published with confirms, so a publish that returns
+// Ok has been taken over by RabbitMQ
+const PERSISTENT: u8 = 2;
+
+impl LapinBroker {
+    pub async fn new(channel: Channel) -> Result<LapinBroker, BrokerError> {
+        channel.confirm_select(ConfirmSelectOptions::default()).await?;
+        Ok(LapinBroker { channel })
+    }
+}
+
+#[async_trait]
+impl Broker for LapinBroker {
+    async fn declare_exchange(&self, exchange: &str) -> Result<(), BrokerError> {
+        self.channel
+            .exchange_declare(
+                exchange,
+                ExchangeKind::Topic,
+                ExchangeDeclareOptions {
+                    durable: true,
+                    ..Default::default()
+                },
+                FieldTable::default(),
+                )
+            .await?;
+        debug!("Declared exchange {:?}", exchange);
+        Ok(())
+    }
+
+    async fn declare_queue(&self, queue: &str, exchange: &str, routing_key: &str) -> Result<(), BrokerError> {
+        declare_dead_letter_queue(&self.channel, queue).await?;
+        self.channel.queue_declare(
+            queue,
+            QueueDeclareOptions::default(),
+            queue_arguments(queue),
+            )
+            .await?;
+        debug!("Declared queue {:?}", queue);
+
+        self.channel
+            .queue_bind(
+                queue,
+                exchange,
+                routing_key,
+                QueueBindOptions::default(),
+                FieldTable::default(),
+                )
+            .await?;
+        debug!("Declared bind {:?} -> {:?}", exchange, queue);
+        Ok(())
+    }
+
+    async fn publish(&self, exchange: &str, routing_key: &str, data: Vec<u8>) -> Result<(), BrokerError> {
+        let properties = BasicProperties::default().with_delivery_mode(PERSISTENT);
+        publish_confirmed(&self.channel, exchange, routing_key, &data, properties).await
+    }
+
+    async fn consume(&self, queue: &str, consumer_tag: &str, handler: Handler) -> Result<(), BrokerError> {
+        let consumer = self.channel.basic_consume(
+            queue,
+            consumer_tag,
+            BasicConsumeOptions::default(),
+            FieldTable::default())
+            .await?;
+
+        let channel = self.channel.clone();
+        let queue = queue.to_string();
+        consumer.set_delegate(move |delivery: DeliveryResult| {
+            let channel = channel.clone();
+            let queue = queue.clone();
+            let handler = handler.clone();
+            async move {
+                let delivery = match delivery {
+                    Ok(Some(delivery)) => delivery,
+                    Ok(None) => return,
+                    Err(error) => {
+                        error!("Failed to consume queue message {}", error);
+                        return;
+                    }
+                };
+
+                let message = to_delivery(&delivery);
+                let retries = message.retries();
+                match handler(message).await {
+                    Ok(()) => {
+                        if let Err(err) = delivery.ack(BasicAckOptions::default()).await {
+                            error!("Failed to acknowledge message: {:?}", err);
+                        }
+                    },
+                    Err(Rejection::Retry(reason)) if retries < MAX_RETRIES => retry(&channel, &delivery, queue.as_str(), retries, reason).await,
+                    Err(Rejection::Retry(reason) | Rejection::Invalid(reason)) => reject(&channel, &delivery, queue.as_str(), reason).await,
+                }
+            }
+        });
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.channel.status().connected()
+    }
+}
+
+fn to_delivery(delivery: &AmqpDelivery) -> Delivery {
+    let mut headers = HashMap::new();
+    if let Some(table) = delivery.properties.headers() {
+        for (key, value) in table.inner() {
+            match value {
+                AMQPValue::LongString(value) => headers.insert(key.to_string(), value.to_string()),
+                AMQPValue::ShortString(value) => headers.insert(key.to_string(), value.to_string()),
+                _ => None,
+            };
+        }
+    }
+    Delivery { data: delivery.data.clone(), headers }
+}
+
+// messages rejected without a reason, e.g. when publishing to the dead-letter exchange fails,
+// are routed there by the broker, queues declared without these have to be migrated (see README)
+fn queue_arguments(queue: &str) -> FieldTable {
+    let mut arguments = FieldTable::default();
+    arguments.insert("x-dead-letter-exchange".into(), AMQPValue::LongString(DEAD_LETTER_EXCHANGE.into()));
+    arguments.insert("x-dead-letter-routing-key".into(), AMQPValue::LongString(queue.into()));
+    arguments
+}
+
+async fn declare_dead_letter_queue(channel: &Channel, queue: &str) -> Result<(), lapin::Error> {
+    channel
+        .exchange_declare(
+            DEAD_LETTER_EXCHANGE,
+            ExchangeKind::Topic,
+            ExchangeDeclareOptions {
+                durable: true,
+                ..Default::default()
+            },
+            FieldTable::default(),
+            )
+        .await?;
+
+    let dead_queue = dead_letter_queue(queue);
+    channel.queue_declare(
+        dead_queue.as_str(),
+        QueueDeclareOptions {
+            durable: true,
+            ..Default::default()
+        },
+        FieldTable::default(),
+        )
+        .await?;
+
+    channel
+        .queue_bind(
+            dead_queue.as_str(),
+            DEAD_LETTER_EXCHANGE,
+            queue,
+            QueueBindOptions::default(),
+            FieldTable::default(),
+            )
+        .await?;
+    debug!("Declared dead-letter queue {:?}", dead_queue);
+    Ok(())
+}
+
+// publishes the message to the end of its queue through the default exchange, it's
+// acknowledged only then, so it's redelivered if that fails
+async fn retry(channel: &Channel, delivery: &AmqpDelivery, queue: &str, retries: u32, reason: String) {
+    warn!("Retrying message from {}: {}", queue, reason);
+    tokio::time::sleep(RETRY_DELAY).await;
+    let mut headers = delivery.properties
+        .headers()
+        .clone()
+        .unwrap_or_default();
+    headers.insert(RETRIES_HEADER.into(), AMQPValue::LongString((retries + 1).to_string().into()));
+    let properties = delivery.properties.clone().with_headers(headers);
+
+    let published = publish_confirmed(channel, "", queue, &delivery.data, properties).await;
+    let result = match published {
+        Ok(_) => delivery.ack(BasicAckOptions::default()).await,
+        Err(err) => {
+            error!("Failed to retry message from {}: {:?}", queue, err);
+            delivery.nack(BasicNackOptions { requeue: true, ..Default::default() }).await
+        },
+    };
+    if let Err(err) = result {
+        error!("Failed to retry message from {}: {:?}", queue, err);
+    }
+}
+
+// moves a message that cannot be processed out of the queue, so that it isn't redelivered forever
+async fn reject(channel: &Channel, delivery: &AmqpDelivery, queue: &str, reason: String) {
+    warn!("Rejecting message from {}: {}", queue, reason);
+    let mut headers = delivery.properties
+        .headers()
+        .clone()
+        .unwrap_or_default();
+    headers.insert(REASON_HEADER.into(), AMQPValue::LongString(reason.into()));
+    headers.insert(QUEUE_HEADER.into(), AMQPValue::LongString(queue.into()));
+    let properties = BasicProperties::default()
+        .with_headers(headers)
+        .with_timestamp(chrono::Utc::now().timestamp() as u64)
+        .with_content_type(ShortString::from("application/json"))
+        .with_delivery_mode(PERSISTENT);
+
+    let published = publish_confirmed(channel, DEAD_LETTER_EXCHANGE, queue, &delivery.data, properties).await;
+    let result = match published {
+        Ok(_) => delivery.ack(BasicAckOptions::default()).await,
+        Err(err) => {
+            error!("Failed to publish dead letter from {}: {:?}", queue, err);
+            delivery.nack(BasicNackOptions { requeue: false, ..Default::default() }).await
+        },
+    };
+    if let Err(err) = result {
+        error!("Failed to reject message from {}: {:?}", queue, err);
+    }
+}
+
+// waits for the broker to confirm the message, a nack means it wasn't stored
+async fn publish_confirmed(channel: &Channel, exchange: &str, routing_key: &str, data: &[u8], properties: BasicProperties) -> Result<(), BrokerError> {
+    let confirmation = channel
+        .basic_publish(
+            exchange,
+            routing_key,
+            Default::default(),
+            data,
+            properties,
+            )
+        .await?
+        .await?;
+    match confirmation {
+        Confirmation::Nack(_) => Err(BrokerError(format!("Message to {} with key {} was not confirmed", exchange, routing_key))),
+        _ => Ok(()),
+    }
+}
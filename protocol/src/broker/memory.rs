@@ -0,0 +1,140 @@
+use std::{collections::HashMap, sync::{Arc, Mutex}};
+
+use async_trait::async_trait;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tracing::warn;
+
+use super::{dead_letter_queue, Broker, BrokerError, Delivery, Handler, Rejection, DEAD_LETTER_EXCHANGE, MAX_RETRIES, QUEUE_HEADER, REASON_HEADER, RETRIES_HEADER, RETRY_DELAY};
+
+// in-process stand-in for RabbitMQ, routing keys are matched exactly
+#[derive(Clone, Default)]
+pub struct MemoryBroker {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Default)]
+struct Inner {
+    // queues bound to each exchange, with their routing keys
+    bindings: HashMap<String, Vec<(String, String)>>,
+    queues: HashMap<String, Queue>,
+    // publishing fails while set, like with the connection to RabbitMQ lost
+    disconnected: bool,
+}
+
+struct Queue {
+    tx: UnboundedSender<Delivery>,
+    // taken by the consumer
+    rx: Option<UnboundedReceiver<Delivery>>,
+}
+
+impl Inner {
+    fn declare_queue(&mut self, queue: &str) {
+        self.queues.entry(queue.into()).or_insert_with(|| {
+            let (tx, rx) = unbounded_channel();
+            Queue { tx, rx: Some(rx) }
+        });
+    }
+
+    fn bind(&mut self, queue: &str, exchange: &str, routing_key: &str) {
+        let bindings = self.bindings.entry(exchange.into()).or_default();
+        let binding = (queue.to_string(), routing_key.to_string());
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    // like RabbitMQ, messages nobody is bound for are dropped
+    fn route(&self, exchange: &str, routing_key: &str, delivery: Delivery) {
+        let Some(bindings) = self.bindings.get(exchange) else {
+            return;
+        };
+        for (queue, key) in bindings {
+            if key != routing_key {
+                continue;
+            }
+            if let Some(queue) = self.queues.get(queue) {
+                let _ = queue.tx.send(delivery.clone());
+            }
+        }
+    }
+}
+
+impl MemoryBroker {
+    pub fn set_connected(&self, connected: bool) {
+        self.inner.lock().unwrap().disconnected = !connected;
+    }
+
+    fn dead_letter(&self, queue: &str, mut delivery: Delivery, reason: String) {
+        warn!("Rejecting message from {}: {}", queue, reason);
+        delivery.headers.insert(REASON_HEADER.into(), reason);
+        delivery.headers.insert(QUEUE_HEADER.into(), queue.into());
+        self.inner.lock().unwrap().route(DEAD_LETTER_EXCHANGE, queue, delivery);
+    }
+
+    fn retry(&self, queue: &str, mut delivery: Delivery, reason: String) {
+        warn!("Retrying message from {}: {}", queue, reason);
+        delivery.headers.insert(RETRIES_HEADER.into(), (delivery.retries() + 1).to_string());
+        let Some(tx) = self.inner.lock().unwrap().queues.get(queue).map(|queue| queue.tx.clone()) else {
+            return;
+        };
+        tokio::spawn(async move {
+            tokio::time::sleep(RETRY_DELAY).await;
+            let _ = tx.send(delivery);
+        });
+    }
+}
+
+#[async_trait]
+impl Broker for MemoryBroker {
+    async fn declare_exchange(&self, exchange: &str) -> Result<(), BrokerError> {
+        self.inner.lock().unwrap().bindings.entry(exchange.into()).or_default();
+        Ok(())
+    }
+
+    async fn declare_queue(&self, queue: &str, exchange: &str, routing_key: &str) -> Result<(), BrokerError> {
+        let mut inner = self.inner.lock().unwrap();
+        let dead_queue = dead_letter_queue(queue);
+        inner.declare_queue(dead_queue.as_str());
+        inner.bind(dead_queue.as_str(), DEAD_LETTER_EXCHANGE, queue);
+        inner.declare_queue(queue);
+        inner.bind(queue, exchange, routing_key);
+        Ok(())
+    }
+
+    async fn publish(&self, exchange: &str, routing_key: &str, data: Vec<u8>) -> Result<(), BrokerError> {
+        let inner = self.inner.lock().unwrap();
+        if inner.disconnected {
+            return Err(BrokerError("Broker is disconnected".into()));
+        }
+        let delivery = Delivery { data, headers: HashMap::new() };
+        inner.route(exchange, routing_key, delivery);
+        Ok(())
+    }
+
+    async fn consume(&self, queue: &str, _consumer_tag: &str, handler: Handler) -> Result<(), BrokerError> {
+        let rx = match self.inner.lock().unwrap().queues.get_mut(queue) {
+            Some(declared) => declared.rx.take(),
+            None => return Err(BrokerError(format!("Queue {} is not declared", queue))),
+        };
+        let Some(mut rx) = rx else {
+            return Err(BrokerError(format!("Queue {} already has a consumer", queue)));
+        };
+
+        let broker = self.clone();
+        let queue = queue.to_string();
+        tokio::spawn(async move {
+            while let Some(delivery) = rx.recv().await {
+                match handler(delivery.clone()).await {
+                    Ok(()) => (),
+                    Err(Rejection::Retry(reason)) if delivery.retries() < MAX_RETRIES => broker.retry(queue.as_str(), delivery, reason),
+                    Err(Rejection::Retry(reason) | Rejection::Invalid(reason)) => broker.dead_letter(queue.as_str(), delivery, reason),
+                }
+            }
+        });
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        !self.inner.lock().unwrap().disconnected
+    }
+}
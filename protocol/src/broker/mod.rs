@@ -0,0 +1,119 @@
+// Publishing and consuming behind a trait, services talk to RabbitMQ through
+// LapinBroker and tests run the same message flow through MemoryBroker.
+
+use std::{collections::HashMap, fmt, future::Future, pin::Pin, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+
+use crate::envelope::{decode, message_type, Envelope, Message};
+
+mod amqp;
+mod memory;
+
+pub use self::{amqp::LapinBroker, memory::MemoryBroker};
+
+pub const DEAD_LETTER_EXCHANGE: &str = "checkers.dead.topic";
+pub const REASON_HEADER: &str = "x-reason";
+pub const QUEUE_HEADER: &str = "x-original-queue";
+pub const RETRIES_HEADER: &str = "x-retries";
+
+// retried messages go to the end of their queue again after the delay,
+// so that the ones they have to wait for can be processed first
+pub const RETRY_DELAY: Duration = Duration::from_millis(500);
+pub const MAX_RETRIES: u32 = 20;
+
+pub fn dead_letter_queue(queue: &str) -> String {
+    format!("{}.dead", queue)
+}
+
+#[derive(Debug)]
+pub struct BrokerError(pub String);
+
+impl fmt::Display for BrokerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Broker error: {}", self.0)
+    }
+}
+
+impl std::error::Error for BrokerError {}
+
+impl From<lapin::Error> for BrokerError {
+    fn from(err: lapin::Error) -> Self {
+        BrokerError(err.to_string())
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Delivery {
+    pub data: Vec<u8>,
+    pub headers: HashMap<String, String>,
+}
+
+impl Delivery {
+    // message with its envelope, or the reason why it cannot be processed
+    pub fn parse<T: Message>(&self) -> Result<Envelope<T>, String> {
+        decode(&self.data)
+    }
+
+    pub fn message_type(&self) -> Option<String> {
+        message_type(&self.data)
+    }
+
+    // times the message has been retried so far
+    pub fn retries(&self) -> u32 {
+        self.headers.get(RETRIES_HEADER).and_then(|retries| retries.parse().ok()).unwrap_or(0)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Rejection {
+    // message cannot be processed, it's moved to the dead-letter queue
+    Invalid(String),
+    // message cannot be processed yet, e.g. it came before the ones it depends on,
+    // it's dead-lettered once it has been retried MAX_RETRIES times
+    Retry(String),
+}
+
+impl From<String> for Rejection {
+    fn from(reason: String) -> Self {
+        Rejection::Invalid(reason)
+    }
+}
+
+impl From<&str> for Rejection {
+    fn from(reason: &str) -> Self {
+        Rejection::Invalid(reason.into())
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::Invalid(reason) => write!(f, "{}", reason),
+            Rejection::Retry(reason) => write!(f, "{} (retried)", reason),
+        }
+    }
+}
+
+pub type HandlerResult = Result<(), Rejection>;
+pub type Handler = Arc<dyn Fn(Delivery) -> Pin<Box<dyn Future<Output = HandlerResult> + Send>> + Send + Sync>;
+
+pub fn handler<F, Fut>(f: F) -> Handler
+where
+    F: Fn(Delivery) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = HandlerResult> + Send + 'static,
+{
+    Arc::new(move |delivery| Box::pin(f(delivery)))
+}
+
+#[async_trait]
+pub trait Broker: Send + Sync {
+    // exchanges are durable topic exchanges
+    async fn declare_exchange(&self, exchange: &str) -> Result<(), BrokerError>;
+    // queue is bound to the exchange and gets its own dead-letter queue
+    async fn declare_queue(&self, queue: &str, exchange: &str, routing_key: &str) -> Result<(), BrokerError>;
+    async fn publish(&self, exchange: &str, routing_key: &str, data: Vec<u8>) -> Result<(), BrokerError>;
+    // messages are acknowledged after the handler returns, rejected ones are dead-lettered or retried
+    async fn consume(&self, queue: &str, consumer_tag: &str, handler: Handler) -> Result<(), BrokerError>;
+    fn is_connected(&self) -> bool;
+}
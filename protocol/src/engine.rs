@@ -0,0 +1,69 @@
+// move verification and AI moves, between game and game-engine
+
+use serde::{Deserialize, Serialize};
+
+use crate::{AIType, Color, RuleSet};
+
+// routed with key "move"
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MoveEvent {
+    pub game_id: usize,
+    // echoed in the engine response, so that late answers can be told apart,
+    // missing in requests of older versions
+    #[serde(default)]
+    pub correlation_id: Option<u64>,
+    pub game_state: String,
+    #[serde(rename = "move")]
+    pub mov: String,
+    pub ruleset: RuleSet,
+    pub color: Color,
+    pub noncapture_moves: usize,
+    pub nonpromoting_moves: usize,
+}
+
+// routed with key "move_ai"
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AIMoveEvent {
+    pub game_id: usize,
+    // same as in MoveEvent
+    #[serde(default)]
+    pub correlation_id: Option<u64>,
+    pub game_state: String,
+    pub ruleset: RuleSet,
+    pub ai_type: AIType,
+    pub color: Color,
+    pub noncapture_moves: usize,
+    pub nonpromoting_moves: usize,
+}
+
+// answer to both of the above, routed with key "engine"
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct EngineEvent {
+    pub game_id: usize,
+    #[serde(default)]
+    pub correlation_id: Option<u64>,
+    pub legal: bool,
+    pub new_state: String,
+    #[serde(rename = "move")]
+    pub mov: String,
+    pub ai: bool,
+    pub finished: bool,
+    pub lost: bool,
+    pub won: bool,
+    pub reason: Option<RejectionReason>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub enum RejectionReason {
+    NoPiece,
+    DestinationOccupied,
+    CaptureAvailable,
+    BackwardMove,
+    IncompleteJump,
+    Unreachable,
+    InvalidNotation,
+    Ambiguous { candidates: Vec<String> },
+}
@@ -0,0 +1,107 @@
+// every message is sent wrapped in an envelope describing it, payloads of
+// version 1 were sent bare and are still accepted
+
+use chrono::{DateTime, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{engine::{AIMoveEvent, EngineEvent, MoveEvent}, game::{GameEvent, MatchEvent, RematchEvent, RematchRequestEvent, StateEvent, TakebackEvent, UpdateEvent}};
+
+pub const SCHEMA_VERSION: u32 = 2;
+// oldest version consumers still understand
+pub const MIN_SCHEMA_VERSION: u32 = 1;
+
+pub trait Message: Serialize + DeserializeOwned {
+    const TYPE: &'static str;
+}
+
+impl Message for MoveEvent { const TYPE: &'static str = "move"; }
+impl Message for AIMoveEvent { const TYPE: &'static str = "move_ai"; }
+impl Message for EngineEvent { const TYPE: &'static str = "engine"; }
+impl Message for GameEvent { const TYPE: &'static str = "game"; }
+impl Message for StateEvent { const TYPE: &'static str = "state"; }
+impl Message for UpdateEvent { const TYPE: &'static str = "update"; }
+impl Message for TakebackEvent { const TYPE: &'static str = "takeback"; }
+impl Message for MatchEvent { const TYPE: &'static str = "match"; }
+impl Message for RematchRequestEvent { const TYPE: &'static str = "rematch_request"; }
+impl Message for RematchEvent { const TYPE: &'static str = "rematch"; }
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Envelope<T> {
+    pub message_type: String,
+    pub version: u32,
+    pub correlation_id: Option<String>,
+    pub timestamp: DateTime<Utc>,
+    pub producer: String,
+    pub payload: T,
+}
+
+impl<T: Message> Envelope<T> {
+    pub fn new(payload: T, producer: &str, correlation_id: Option<String>) -> Envelope<T> {
+        Envelope {
+            message_type: T::TYPE.into(),
+            version: SCHEMA_VERSION,
+            correlation_id,
+            timestamp: Utc::now(),
+            producer: producer.into(),
+            payload,
+        }
+    }
+}
+
+pub fn encode<T: Message>(payload: T, producer: &str, correlation_id: Option<String>) -> Vec<u8> {
+    let envelope = Envelope::new(payload, producer, correlation_id);
+    serde_json::to_vec(&envelope).unwrap()
+}
+
+// error is the reason why the message cannot be processed
+pub fn decode<T: Message>(data: &[u8]) -> Result<Envelope<T>, String> {
+    let value: Value = serde_json::from_slice(data)
+        .map_err(|err| format!("Malformed message: {}", err))?;
+    if !is_envelope(&value) {
+        let payload: T = serde_json::from_value(value)
+            .map_err(|err| format!("Malformed {} message of version 1: {}", T::TYPE, err))?;
+        return Ok(Envelope {
+            message_type: T::TYPE.into(),
+            version: 1,
+            correlation_id: None,
+            timestamp: Utc::now(),
+            producer: "unknown".into(),
+            payload,
+        })
+    }
+
+    let envelope: Envelope<Value> = serde_json::from_value(value)
+        .map_err(|err| format!("Malformed envelope: {}", err))?;
+    if envelope.message_type != T::TYPE {
+        return Err(format!("Expected {} message, got {}", T::TYPE, envelope.message_type))
+    }
+    if envelope.version < MIN_SCHEMA_VERSION || envelope.version > SCHEMA_VERSION {
+        return Err(format!("Unsupported version {} of {} message", envelope.version, T::TYPE))
+    }
+    let payload: T = serde_json::from_value(envelope.payload)
+        .map_err(|err| format!("Malformed {} message of version {}: {}", T::TYPE, envelope.version, err))?;
+    Ok(Envelope {
+        message_type: envelope.message_type,
+        version: envelope.version,
+        correlation_id: envelope.correlation_id,
+        timestamp: envelope.timestamp,
+        producer: envelope.producer,
+        payload,
+    })
+}
+
+// for consumers of queues carrying several types, bare payloads have none
+pub fn message_type(data: &[u8]) -> Option<String> {
+    let value: Value = serde_json::from_slice(data).ok()?;
+    if !is_envelope(&value) {
+        return None
+    }
+    value["messageType"].as_str().map(|message_type| message_type.to_string())
+}
+
+// bare payloads never have these fields
+fn is_envelope(value: &Value) -> bool {
+    value.get("messageType").is_some() && value.get("payload").is_some()
+}
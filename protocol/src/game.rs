@@ -0,0 +1,131 @@
+// game state, between game and main
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{AIType, GameStatus, GameType, RuleSet, TimeControl};
+
+// request for the state of a game, routed with key "game"
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GameEvent {
+    pub game_id: usize,
+}
+
+// routed with key "state"
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct StateEvent {
+    pub game_id: usize,
+    pub error: bool,
+    pub error_message: Option<String>,
+    pub user: String,
+    pub opponent: String,
+    pub user_turn: bool,
+    pub user_starts: bool,
+    pub current_state: String,
+    pub game_type: GameType,
+    pub ruleset: RuleSet,
+    pub ai_type: AIType,
+    pub status: GameStatus,
+    pub noncapture_moves: usize,
+    pub nonpromoting_moves: usize,
+    #[serde(default)]
+    pub time_control: TimeControl,
+    #[serde(default)]
+    pub time_initial: i64,
+    #[serde(default)]
+    pub time_increment: i64,
+    // older versions had no settings, everything was allowed
+    #[serde(default = "allowed")]
+    pub allow_spectators: bool,
+    #[serde(default = "allowed")]
+    pub spectator_chat: bool,
+    // moves played so far
+    #[serde(default)]
+    pub ply: usize,
+    // milliseconds left on the clocks, empty before the first move
+    #[serde(default)]
+    pub user_time: Option<i64>,
+    #[serde(default)]
+    pub opponent_time: Option<i64>,
+}
+
+fn allowed() -> bool {
+    true
+}
+
+// routed with key "update"
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateEvent {
+    pub game_id: usize,
+    pub status: GameStatus,
+    pub current_state: String,
+    pub user_turn: bool,
+    pub timestamp: DateTime<Utc>,
+    pub last_move: String,
+    pub noncapture_moves: usize,
+    pub nonpromoting_moves: usize,
+    // unique per update, redelivered copies share it
+    #[serde(default)]
+    pub event_id: Option<String>,
+    // number of the move in the game, so that updates applied out of order can be detected
+    #[serde(default)]
+    pub ply: Option<usize>,
+    // milliseconds left on the clocks after the update, empty for games without time control
+    #[serde(default)]
+    pub user_time: Option<i64>,
+    #[serde(default)]
+    pub opponent_time: Option<i64>,
+}
+
+// routed with key "takeback"
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TakebackEvent {
+    pub game_id: usize,
+    pub moves: usize,
+    pub current_state: String,
+    pub user_turn: bool,
+    pub timestamp: DateTime<Utc>,
+    pub noncapture_moves: usize,
+    pub nonpromoting_moves: usize,
+    // ply of the last move left after the takeback
+    #[serde(default)]
+    pub ply: Option<usize>,
+}
+
+// players paired by matchmaking, routed with key "match"
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchEvent {
+    pub game_id: usize,
+    pub user: String,
+    pub opponent: String,
+}
+
+// rematch asked for or declined by a player in the room of a finished game,
+// sent to main with key "rematch"
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RematchRequestEvent {
+    pub game_id: usize,
+    pub player: String,
+    pub accepted: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RematchStatus {
+    Offered, Accepted, Declined, Cancelled, Expired,
+}
+
+// rematch of a game changed, sent to the game service with key "rematch"
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RematchEvent {
+    pub game_id: usize,
+    pub rematch_id: usize,
+    pub player: String,
+    pub status: RematchStatus,
+}
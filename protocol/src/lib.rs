@@ -0,0 +1,59 @@
+// Messages exchanged between services through RabbitMQ.
+//
+// Field and variant names are part of the wire format, renaming them breaks
+// services that haven't been redeployed yet. New fields should be optional or
+// have a serde default, anything else needs a new envelope::SCHEMA_VERSION.
+
+use serde::{Deserialize, Serialize};
+
+pub mod broker;
+pub mod engine;
+pub mod envelope;
+pub mod game;
+pub mod queues;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub enum Color {
+    White, Red,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum RuleSet {
+    #[default]
+    British,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum AIType {
+    #[default]
+    None,
+    Random,
+    Counting,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum GameType {
+    #[default]
+    User,
+    AI,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum GameStatus {
+    #[default]
+    NotFinished,
+    Won,
+    Lost,
+    Drawn,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum TimeControl {
+    #[default]
+    None,
+    Fischer,
+    PerMove,
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,28 @@
+// Queues services consume from, each one has a dead-letter queue next to it.
+
+// game-engine, bound to checkers.moves.topic
+pub const MOVES_QUEUE: &str = "checkers.moves.queue";
+pub const AI_MOVES_QUEUE: &str = "checkers.moves.ai.queue";
+
+// game, bound to checkers.engine.topic and checkers.state.topic
+pub const ENGINE_QUEUE: &str = "checkers.engine.queue";
+pub const STATES_QUEUE: &str = "checkers.states.queue";
+pub const MATCHES_QUEUE: &str = "checkers.matches.queue";
+pub const REMATCH_STATES_QUEUE: &str = "checkers.rematch.states.queue";
+
+// main, bound to checkers.updates.topic and checkers.games.topic
+pub const UPDATES_QUEUE: &str = "checkers.updates.queue";
+pub const GAMES_QUEUE: &str = "checkers.games.queue";
+pub const REMATCHES_QUEUE: &str = "checkers.rematches.queue";
+
+pub const QUEUES: [&str; 9] = [
+    MOVES_QUEUE,
+    AI_MOVES_QUEUE,
+    ENGINE_QUEUE,
+    STATES_QUEUE,
+    MATCHES_QUEUE,
+    REMATCH_STATES_QUEUE,
+    UPDATES_QUEUE,
+    GAMES_QUEUE,
+    REMATCHES_QUEUE,
+];
@@ -0,0 +1,467 @@
+use std::{collections::HashSet, time::Duration};
+
+use chrono::{TimeZone, Utc};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+use crate::{broker::{dead_letter_queue, handler, Broker, Delivery, MemoryBroker, Rejection, REASON_HEADER, RETRIES_HEADER}, engine::{AIMoveEvent, EngineEvent, MoveEvent, RejectionReason}, envelope::{decode, encode, message_type, SCHEMA_VERSION}, game::{GameEvent, MatchEvent, RematchEvent, RematchRequestEvent, RematchStatus, StateEvent, TakebackEvent, UpdateEvent}, queues::QUEUES, AIType, Color, GameStatus, GameType, RuleSet, TimeControl};
+
+// checks both directions against the JSON other services send and expect
+fn assert_round_trip<T: Serialize + DeserializeOwned + PartialEq + std::fmt::Debug>(message: T, wire: Value) {
+    let serialized = serde_json::to_value(&message).unwrap();
+    assert_eq!(serialized, wire);
+    let deserialized: T = serde_json::from_value(wire).unwrap();
+    assert_eq!(deserialized, message);
+}
+
+#[test]
+fn move_event_should_round_trip() {
+    let event = MoveEvent {
+        game_id: 1,
+        correlation_id: Some(7),
+        game_state: "xxxx".into(),
+        mov: "11-15".into(),
+        ruleset: RuleSet::British,
+        color: Color::White,
+        noncapture_moves: 2,
+        nonpromoting_moves: 3,
+    };
+    assert_round_trip(event, json!({
+        "gameId": 1,
+        "correlationId": 7,
+        "gameState": "xxxx",
+        "move": "11-15",
+        "ruleset": "British",
+        "color": "White",
+        "noncaptureMoves": 2,
+        "nonpromotingMoves": 3,
+    }));
+}
+
+#[test]
+fn ai_move_event_should_round_trip() {
+    let event = AIMoveEvent {
+        game_id: 1,
+        correlation_id: Some(5),
+        game_state: "xxxx".into(),
+        ruleset: RuleSet::British,
+        ai_type: AIType::Counting,
+        color: Color::Red,
+        noncapture_moves: 0,
+        nonpromoting_moves: 1,
+    };
+    assert_round_trip(event, json!({
+        "gameId": 1,
+        "correlationId": 5,
+        "gameState": "xxxx",
+        "ruleset": "British",
+        "aiType": "Counting",
+        "color": "Red",
+        "noncaptureMoves": 0,
+        "nonpromotingMoves": 1,
+    }));
+}
+
+#[test]
+fn engine_event_should_round_trip() {
+    let event = EngineEvent {
+        game_id: 1,
+        correlation_id: Some(7),
+        legal: false,
+        new_state: "xxxx".into(),
+        mov: "11-15".into(),
+        reason: Some(RejectionReason::Ambiguous { candidates: vec!["9x18".into(), "9x25".into()] }),
+        ..Default::default()
+    };
+    assert_round_trip(event, json!({
+        "gameId": 1,
+        "correlationId": 7,
+        "legal": false,
+        "newState": "xxxx",
+        "move": "11-15",
+        "ai": false,
+        "finished": false,
+        "lost": false,
+        "won": false,
+        "reason": { "Ambiguous": { "candidates": ["9x18", "9x25"] } },
+    }));
+}
+
+#[test]
+fn engine_event_without_correlation_id_should_be_accepted() {
+    let event: EngineEvent = serde_json::from_value(json!({
+        "gameId": 1,
+        "legal": true,
+        "newState": "xxxx",
+        "move": "11-15",
+        "ai": true,
+        "finished": false,
+        "lost": false,
+        "won": false,
+        "reason": null,
+    })).unwrap();
+    assert_eq!(event.correlation_id, None);
+    assert!(event.ai);
+}
+
+#[test]
+fn state_event_should_round_trip() {
+    let event = StateEvent {
+        game_id: 1,
+        user: "user".into(),
+        opponent: "AI".into(),
+        user_turn: true,
+        user_starts: true,
+        current_state: "xxxx".into(),
+        game_type: GameType::AI,
+        ai_type: AIType::Random,
+        time_control: TimeControl::Fischer,
+        time_initial: 300,
+        time_increment: 5,
+        allow_spectators: true,
+        user_time: Some(120000),
+        ..Default::default()
+    };
+    assert_round_trip(event, json!({
+        "gameId": 1,
+        "error": false,
+        "errorMessage": null,
+        "user": "user",
+        "opponent": "AI",
+        "userTurn": true,
+        "userStarts": true,
+        "currentState": "xxxx",
+        "gameType": "AI",
+        "ruleset": "British",
+        "aiType": "Random",
+        "status": "NotFinished",
+        "noncaptureMoves": 0,
+        "nonpromotingMoves": 0,
+        "timeControl": "Fischer",
+        "timeInitial": 300,
+        "timeIncrement": 5,
+        "allowSpectators": true,
+        "spectatorChat": false,
+        "ply": 0,
+        "userTime": 120000,
+        "opponentTime": null,
+    }));
+}
+
+#[test]
+fn update_event_should_round_trip() {
+    let event = UpdateEvent {
+        game_id: 1,
+        status: GameStatus::Won,
+        current_state: "xxxx".into(),
+        user_turn: false,
+        timestamp: Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap(),
+        last_move: "11-15".into(),
+        noncapture_moves: 0,
+        nonpromoting_moves: 4,
+        event_id: Some("1-7".into()),
+        ply: Some(7),
+        user_time: Some(295000),
+        opponent_time: Some(300000),
+    };
+    assert_round_trip(event, json!({
+        "gameId": 1,
+        "status": "Won",
+        "currentState": "xxxx",
+        "userTurn": false,
+        "timestamp": "2024-05-01T12:00:00Z",
+        "lastMove": "11-15",
+        "noncaptureMoves": 0,
+        "nonpromotingMoves": 4,
+        "eventId": "1-7",
+        "ply": 7,
+        "userTime": 295000,
+        "opponentTime": 300000,
+    }));
+}
+
+#[test]
+fn update_event_without_event_id_should_be_accepted() {
+    let event: UpdateEvent = serde_json::from_value(json!({
+        "gameId": 1,
+        "status": "NotFinished",
+        "currentState": "xxxx",
+        "userTurn": false,
+        "timestamp": "2024-05-01T12:00:00Z",
+        "lastMove": "11-15",
+        "noncaptureMoves": 0,
+        "nonpromotingMoves": 0,
+    })).unwrap();
+    assert_eq!(event.event_id, None);
+    assert_eq!(event.ply, None);
+}
+
+#[test]
+fn takeback_event_should_round_trip() {
+    let event = TakebackEvent {
+        game_id: 1,
+        moves: 2,
+        current_state: "xxxx".into(),
+        user_turn: true,
+        timestamp: Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap(),
+        noncapture_moves: 1,
+        nonpromoting_moves: 1,
+        ply: Some(3),
+    };
+    assert_round_trip(event, json!({
+        "gameId": 1,
+        "moves": 2,
+        "currentState": "xxxx",
+        "userTurn": true,
+        "timestamp": "2024-05-01T12:00:00Z",
+        "noncaptureMoves": 1,
+        "nonpromotingMoves": 1,
+        "ply": 3,
+    }));
+}
+
+#[test]
+fn match_event_should_round_trip() {
+    let event = MatchEvent { game_id: 3, user: "user".into(), opponent: "opponent".into() };
+    assert_round_trip(event, json!({
+        "gameId": 3,
+        "user": "user",
+        "opponent": "opponent",
+    }));
+}
+
+#[test]
+fn rematch_events_should_round_trip() {
+    let request = RematchRequestEvent { game_id: 3, player: "user".into(), accepted: true };
+    assert_round_trip(request, json!({
+        "gameId": 3,
+        "player": "user",
+        "accepted": true,
+    }));
+    let event = RematchEvent { game_id: 3, rematch_id: 4, player: "opponent".into(), status: RematchStatus::Accepted };
+    assert_round_trip(event, json!({
+        "gameId": 3,
+        "rematchId": 4,
+        "player": "opponent",
+        "status": "Accepted",
+    }));
+}
+
+#[test]
+fn game_event_should_round_trip() {
+    assert_round_trip(GameEvent { game_id: 5 }, json!({ "gameId": 5 }));
+}
+
+#[test]
+fn envelope_should_round_trip() {
+    let data = encode(GameEvent { game_id: 5 }, "game", Some("42".into()));
+
+    let wire: Value = serde_json::from_slice(&data).unwrap();
+    assert_eq!(wire["messageType"], "game");
+    assert_eq!(wire["version"], SCHEMA_VERSION);
+    assert_eq!(wire["producer"], "game");
+    assert_eq!(wire["correlationId"], "42");
+    assert_eq!(wire["payload"], json!({ "gameId": 5 }));
+
+    let envelope = decode::<GameEvent>(&data).unwrap();
+    assert_eq!(envelope.payload, GameEvent { game_id: 5 });
+    assert_eq!(envelope.correlation_id, Some("42".into()));
+}
+
+// published by the game service before envelopes, correlation ids and clocks
+#[test]
+fn baseline_move_event_should_be_read_as_version_1() {
+    let data = br#"{"gameId":5,"gameState":"xxxx....oooo","move":"11-15","ruleset":"British","color":"Red","noncaptureMoves":2,"nonpromotingMoves":3}"#;
+
+    let envelope = decode::<MoveEvent>(data).unwrap();
+    assert_eq!(envelope.version, 1);
+    assert_eq!(envelope.message_type, "move");
+    assert_eq!(envelope.payload, MoveEvent {
+        game_id: 5,
+        correlation_id: None,
+        game_state: "xxxx....oooo".into(),
+        mov: "11-15".into(),
+        ruleset: RuleSet::British,
+        color: Color::Red,
+        noncapture_moves: 2,
+        nonpromoting_moves: 3,
+    });
+}
+
+// published by the main service before envelopes, clocks and spectator settings
+#[test]
+fn baseline_state_event_should_be_read_as_version_1() {
+    let data = br#"{"gameId":5,"error":false,"errorMessage":null,"user":"user","opponent":"AI","userTurn":true,"userStarts":true,"currentState":"xxxx....oooo","gameType":"AI","ruleset":"British","aiType":"Random","status":"NotFinished","noncaptureMoves":0,"nonpromotingMoves":1}"#;
+
+    let envelope = decode::<StateEvent>(data).unwrap();
+    assert_eq!(envelope.version, 1);
+    assert_eq!(envelope.message_type, "state");
+    assert_eq!(envelope.payload, StateEvent {
+        game_id: 5,
+        error: false,
+        error_message: None,
+        user: "user".into(),
+        opponent: "AI".into(),
+        user_turn: true,
+        user_starts: true,
+        current_state: "xxxx....oooo".into(),
+        game_type: GameType::AI,
+        ruleset: RuleSet::British,
+        ai_type: AIType::Random,
+        status: GameStatus::NotFinished,
+        noncapture_moves: 0,
+        nonpromoting_moves: 1,
+        time_control: TimeControl::None,
+        time_initial: 0,
+        time_increment: 0,
+        allow_spectators: true,
+        spectator_chat: true,
+        ply: 0,
+        user_time: None,
+        opponent_time: None,
+    });
+}
+
+#[test]
+fn envelope_of_other_type_should_be_rejected() {
+    let data = encode(GameEvent { game_id: 5 }, "game", None);
+
+    let result = decode::<StateEvent>(&data);
+    assert_eq!(result.err(), Some("Expected state message, got game".into()));
+}
+
+#[test]
+fn unknown_version_should_be_rejected() {
+    let data = serde_json::to_vec(&json!({
+        "messageType": "game",
+        "version": SCHEMA_VERSION + 1,
+        "correlationId": null,
+        "timestamp": "2024-05-01T12:00:00Z",
+        "producer": "game",
+        "payload": { "gameId": 5 },
+    })).unwrap();
+
+    let result = decode::<GameEvent>(&data);
+    assert!(result.unwrap_err().starts_with("Unsupported version"));
+}
+
+#[test]
+fn message_type_should_be_read_from_envelope() {
+    assert_eq!(message_type(&encode(GameEvent { game_id: 5 }, "game", None)), Some("game".into()));
+    assert_eq!(message_type(br#"{"gameId":5}"#), None);
+    assert_eq!(message_type(b"not json"), None);
+}
+
+// deliveries of the queue are forwarded to the receiver, failing the ones the filter rejects
+async fn receive(broker: &MemoryBroker, queue: &str, accept: fn(&Delivery) -> bool) -> UnboundedReceiver<Delivery> {
+    let (tx, rx) = unbounded_channel();
+    broker.consume(queue, "test", handler(move |delivery| {
+        let tx = tx.clone();
+        async move {
+            let accepted = accept(&delivery);
+            let _ = tx.send(delivery);
+            match accepted {
+                true => Ok(()),
+                false => Err("Not accepted".into()),
+            }
+        }
+    })).await.unwrap();
+    rx
+}
+
+async fn next(rx: &mut UnboundedReceiver<Delivery>) -> Delivery {
+    tokio::time::timeout(Duration::from_secs(1), rx.recv()).await.unwrap().unwrap()
+}
+
+#[tokio::test]
+async fn memory_broker_should_route_by_key() {
+    let broker = MemoryBroker::default();
+    broker.declare_exchange("test.topic").await.unwrap();
+    broker.declare_queue("test.moves", "test.topic", "move").await.unwrap();
+    broker.declare_queue("test.games", "test.topic", "game").await.unwrap();
+    let mut moves = receive(&broker, "test.moves", |_| true).await;
+    let mut games = receive(&broker, "test.games", |_| true).await;
+
+    broker.publish("test.topic", "move", encode(GameEvent { game_id: 1 }, "test", None)).await.unwrap();
+    broker.publish("test.topic", "game", encode(GameEvent { game_id: 2 }, "test", None)).await.unwrap();
+
+    assert_eq!(next(&mut moves).await.parse::<GameEvent>().unwrap().payload.game_id, 1);
+    assert_eq!(next(&mut games).await.parse::<GameEvent>().unwrap().payload.game_id, 2);
+    assert!(moves.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn memory_broker_should_dead_letter_rejected_messages() {
+    let broker = MemoryBroker::default();
+    broker.declare_exchange("test.topic").await.unwrap();
+    broker.declare_queue("test.queue", "test.topic", "key").await.unwrap();
+    let mut rejected = receive(&broker, "test.queue", |delivery| delivery.data != b"bad").await;
+    let mut dead = receive(&broker, dead_letter_queue("test.queue").as_str(), |_| true).await;
+
+    broker.publish("test.topic", "key", b"bad".to_vec()).await.unwrap();
+
+    assert_eq!(next(&mut rejected).await.data, b"bad");
+    let letter = next(&mut dead).await;
+    assert_eq!(letter.data, b"bad");
+    assert_eq!(letter.headers.get(REASON_HEADER), Some(&"Not accepted".to_string()));
+}
+
+#[tokio::test]
+async fn memory_broker_should_refuse_second_consumer() {
+    let broker = MemoryBroker::default();
+    broker.declare_queue("test.queue", "test.topic", "key").await.unwrap();
+    let _rx = receive(&broker, "test.queue", |_| true).await;
+
+    let result = broker.consume("test.queue", "test", handler(|_| async { Ok(()) })).await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn disconnected_memory_broker_should_refuse_publishing() {
+    let broker = MemoryBroker::default();
+    broker.declare_queue("test.queue", "test.topic", "key").await.unwrap();
+    let mut rx = receive(&broker, "test.queue", |_| true).await;
+
+    broker.set_connected(false);
+    assert!(!broker.is_connected());
+    assert!(broker.publish("test.topic", "key", b"lost".to_vec()).await.is_err());
+    broker.set_connected(true);
+    broker.publish("test.topic", "key", b"sent".to_vec()).await.unwrap();
+
+    assert_eq!(next(&mut rx).await.data, b"sent");
+}
+
+#[test]
+fn queues_should_have_their_own_dead_letter_queues() {
+    let dead_queues: HashSet<String> = QUEUES.iter().map(|queue| dead_letter_queue(queue)).collect();
+    assert_eq!(dead_queues.len(), QUEUES.len());
+    assert!(QUEUES.iter().all(|queue| !dead_queues.contains(*queue)));
+}
+
+#[tokio::test]
+async fn memory_broker_should_retry_messages_at_end_of_queue() {
+    let broker = MemoryBroker::default();
+    broker.declare_exchange("test.topic").await.unwrap();
+    broker.declare_queue("test.queue", "test.topic", "key").await.unwrap();
+    let (tx, mut rx) = unbounded_channel();
+    broker.consume("test.queue", "test", handler(move |delivery| {
+        let tx = tx.clone();
+        async move {
+            let _ = tx.send(delivery.clone());
+            match delivery.data == b"early" && delivery.retries() == 0 {
+                true => Err(Rejection::Retry("Too early".into())),
+                false => Ok(()),
+            }
+        }
+    })).await.unwrap();
+
+    broker.publish("test.topic", "key", b"early".to_vec()).await.unwrap();
+    broker.publish("test.topic", "key", b"late".to_vec()).await.unwrap();
+
+    assert_eq!(next(&mut rx).await.data, b"early");
+    assert_eq!(next(&mut rx).await.data, b"late");
+    let retried = next(&mut rx).await;
+    assert_eq!(retried.data, b"early");
+    assert_eq!(retried.headers.get(RETRIES_HEADER), Some(&"1".to_string()));
+}